@@ -0,0 +1,201 @@
+use std::sync::mpsc::{self, Sender};
+
+use anyhow::{anyhow, Context, Result};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, Device, Host, SampleFormat, SampleRate, Stream, StreamConfig, SupportedBufferSize,
+};
+use log::{error, info, warn};
+use rmusic::{
+    playback::{playback_context::ArcPlaybackContext, PlaybackDaemon},
+    playback_loop::{playback_loop, PlaybackAction},
+};
+use rmusic_tui::settings::audio::AudioSettings;
+
+/// Sample rate used when none is configured
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+/// The playback loop outputs interleaved stereo
+const CHANNELS: u16 = 2;
+
+/// Open the audio output and run the playback daemon in its callback, starting at `gain`.
+/// Playback stops when the returned stream is dropped.
+pub fn start(
+    settings: &AudioSettings,
+    gain: f32,
+) -> Result<(Stream, Sender<PlaybackAction>, ArcPlaybackContext)> {
+    let host = select_host(settings.host.as_deref());
+    let device = select_device(&host, settings.device.as_deref())?;
+    let stream_config = select_config(&device, settings)?;
+
+    // playback Daemon
+    let mut playback_daemon = PlaybackDaemon::new(stream_config.sample_rate.0 as usize);
+    playback_daemon.set_volume(gain);
+    let playback_context = playback_daemon.get_playback_context();
+
+    // Thread communication
+    let (tx, rx) = mpsc::channel();
+
+    // Stream setup
+    let err_fn = |err| error!("an error occurred on the output audio stream: {:?}", err);
+    let decoder = move |data: &mut [f32], callback: &_| {
+        playback_loop(data, callback, &mut playback_daemon, &rx)
+    };
+    let stream = device
+        .build_output_stream(&stream_config, decoder, err_fn, None)
+        .context("Could not open the audio output stream")?;
+    stream
+        .play()
+        .context("Could not start the audio output stream")?;
+    Ok((stream, tx, playback_context))
+}
+
+/// Print every host and its output devices, with the index that can be used to select them
+pub fn list_devices() -> Result<()> {
+    let default_host = cpal::default_host().id();
+    for host_id in cpal::available_hosts() {
+        let host = match cpal::host_from_id(host_id) {
+            Ok(host) => host,
+            Err(err) => {
+                println!("{} (unavailable: {err})", host_id.name());
+                continue;
+            }
+        };
+        let marker = if host_id == default_host {
+            " (default)"
+        } else {
+            ""
+        };
+        println!("{}{marker}", host_id.name());
+
+        let default_device = host.default_output_device().and_then(|d| d.name().ok());
+        for (index, device) in host.output_devices()?.enumerate() {
+            let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+            let marker = if Some(&name) == default_device.as_ref() {
+                " (default)"
+            } else {
+                ""
+            };
+            println!("  {index}: {name}{marker}");
+        }
+    }
+    Ok(())
+}
+
+/// Select the host by name, falls back to the default host
+pub fn select_host(name: Option<&str>) -> Host {
+    let Some(name) = name else {
+        return cpal::default_host();
+    };
+    let host_id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name));
+    match host_id.map(cpal::host_from_id) {
+        Some(Ok(host)) => host,
+        Some(Err(err)) => {
+            warn!("Audio host {name} is unavailable ({err}), using the default host");
+            cpal::default_host()
+        }
+        None => {
+            let available: Vec<_> = cpal::available_hosts().iter().map(|id| id.name()).collect();
+            warn!(
+                "Audio host {name} is not compiled in (available: {}), using the default host",
+                available.join(", ")
+            );
+            cpal::default_host()
+        }
+    }
+}
+
+/// Select the output device by name or index, falls back to the default device
+pub fn select_device(host: &Host, selector: Option<&str>) -> Result<Device> {
+    if let Some(selector) = selector {
+        match find_device(host, selector) {
+            Ok(Some(device)) => return Ok(device),
+            Ok(None) => warn!("No output device matches `{selector}`, using the default device"),
+            Err(err) => warn!("Could not list output devices ({err}), using the default device"),
+        }
+    }
+    host.default_output_device()
+        .or_else(|| host.output_devices().ok()?.next())
+        .ok_or_else(|| anyhow!("No audio output device available on {}", host.id().name()))
+}
+
+/// Find a device by index, exact name or part of the name
+fn find_device(host: &Host, selector: &str) -> Result<Option<Device>> {
+    let devices: Vec<Device> = host.output_devices()?.collect();
+    if let Ok(index) = selector.parse::<usize>() {
+        return Ok(devices.into_iter().nth(index));
+    }
+    let names: Vec<String> = devices
+        .iter()
+        .map(|device| device.name().unwrap_or_default())
+        .collect();
+    let lowercase = selector.to_lowercase();
+    let index = names.iter().position(|name| name == selector).or_else(|| {
+        names
+            .iter()
+            .position(|name| name.to_lowercase().contains(&lowercase))
+    });
+    Ok(index.and_then(|index| devices.into_iter().nth(index)))
+}
+
+/// Pick a stereo f32 config, using the preferred sample rate and buffer size when supported
+pub fn select_config(device: &Device, settings: &AudioSettings) -> Result<StreamConfig> {
+    let preferred_rate = settings.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+    let ranges: Vec<_> = device
+        .supported_output_configs()
+        .context("Could not query the supported output configs")?
+        .collect();
+    let stereo: Vec<_> = ranges
+        .iter()
+        .filter(|range| range.channels() == CHANNELS && range.sample_format() == SampleFormat::F32)
+        .collect();
+    if stereo.is_empty() {
+        warn!("Output device does not report a stereo f32 config, trying one anyway");
+    }
+
+    let Some(range) = stereo
+        .iter()
+        .find(|range| {
+            range.min_sample_rate().0 <= preferred_rate
+                && preferred_rate <= range.max_sample_rate().0
+        })
+        .or(stereo.first())
+        .copied()
+        .or(ranges.first())
+    else {
+        warn!("Output device does not report any config, trying {preferred_rate} Hz");
+        return Ok(StreamConfig {
+            channels: CHANNELS,
+            sample_rate: SampleRate(preferred_rate),
+            buffer_size: BufferSize::Default,
+        });
+    };
+
+    let sample_rate = preferred_rate.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+    if sample_rate != preferred_rate {
+        warn!("Sample rate {preferred_rate} Hz is not supported, using {sample_rate} Hz");
+    }
+
+    let buffer_size = match (settings.buffer_size, range.buffer_size()) {
+        (None, _) => BufferSize::Default,
+        (Some(size), SupportedBufferSize::Range { min, max }) => {
+            let clamped = size.clamp(*min, *max);
+            if clamped != size {
+                warn!("Buffer size {size} is not supported ({min}-{max}), using {clamped}");
+            }
+            BufferSize::Fixed(clamped)
+        }
+        (Some(size), SupportedBufferSize::Unknown) => {
+            warn!("Device does not report buffer sizes, ignoring buffer size {size}");
+            BufferSize::Default
+        }
+    };
+
+    info!("Audio output: {sample_rate} Hz, buffer size {buffer_size:?}");
+    Ok(StreamConfig {
+        channels: CHANNELS,
+        sample_rate: SampleRate(sample_rate),
+        buffer_size,
+    })
+}
@@ -1,9 +1,24 @@
-use clap::Parser;
+use std::{
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::atomic::AtomicUsize,
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use rmusic::database::Library;
+use rmusic_tui::settings::Settings;
+
+use crate::{control, library_check};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
 pub struct Cli {
+    /// Control a running player or manage the library, instead of starting the player
+    #[clap(subcommand)]
+    pub command: Option<Commands>,
+
     /// Log level:
     /// 0 quiet,
     /// 1 errors,
@@ -12,6 +27,52 @@ pub struct Cli {
     #[clap(short, long)]
     #[clap(default_value_t = 2)]
     pub loglevel: u8,
+
+    /// Config file to use instead of `config.toml` in the config directory
+    #[clap(short, long, env = "RMUSIC_TUI_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Theme to use, the name of a preset (default, light, gruvbox, solarized-dark)
+    /// or the path to a theme file
+    #[clap(short, long)]
+    pub theme: Option<String>,
+
+    /// Keep playing in the background without a terminal,
+    /// controlled through the control socket
+    #[clap(long)]
+    pub daemon: bool,
+
+    /// Do not detach from the terminal when running as daemon
+    #[clap(long, requires = "daemon")]
+    pub foreground: bool,
+
+    /// Path of the control socket, defaults to `$XDG_RUNTIME_DIR/rmusic_tui.sock`
+    #[clap(long, env = "RMUSIC_TUI_SOCKET")]
+    pub socket: Option<PathBuf>,
+
+    /// List the audio hosts and output devices and exit
+    #[clap(long)]
+    pub list_devices: bool,
+
+    /// Audio host to use, like ALSA or JACK
+    #[clap(long)]
+    pub host: Option<String>,
+
+    /// Output device name or index, see `--list-devices`
+    #[clap(short, long)]
+    pub device: Option<String>,
+
+    /// Preferred output sample rate in Hz
+    #[clap(long)]
+    pub sample_rate: Option<u32>,
+
+    /// Preferred output buffer size in frames
+    #[clap(long)]
+    pub buffer_size: Option<u32>,
+
+    /// Start with an empty queue instead of the one from the last run
+    #[clap(long)]
+    pub no_restore: bool,
     // /// Valume level in percetage
     // #[clap(short, long)]
     // #[clap(default_value_t = 100)]
@@ -22,3 +83,195 @@ pub struct Cli {
     //
     // pub opus_file: String,
 }
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Play a file, or resume playback without a path
+    Play { path: Option<PathBuf> },
+    /// Pause playback
+    Pause,
+    /// Toggle between playing and paused
+    Toggle,
+    /// Play the next track
+    Next,
+    /// Play the previous track
+    Prev,
+    /// Seek to a position like `1:30` or `90`, or relative like `+10` and `-10` seconds
+    Seek {
+        #[clap(allow_hyphen_values = true)]
+        position: String,
+    },
+    /// Set the volume in percent, or change it relative like `+5` and `-5`
+    Volume {
+        #[clap(allow_hyphen_values = true)]
+        volume: String,
+    },
+    /// Edit the queue
+    Queue {
+        #[clap(subcommand)]
+        command: QueueCommands,
+    },
+    /// Show the playback status
+    Status {
+        /// Print the status as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Manage the library, does not need a running player
+    Library {
+        #[clap(subcommand)]
+        command: LibraryCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QueueCommands {
+    /// Add a file to the end of the queue
+    Add { path: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LibraryCommands {
+    /// Check that every track in the library still matches its file,
+    /// and offer to fix the entries that do not
+    Check {
+        /// Fix everything without asking
+        #[clap(long)]
+        fix: bool,
+        /// Only print the report
+        #[clap(long, conflicts_with = "fix")]
+        dry_run: bool,
+        /// Folder to search for moved files, next to the watched music folders
+        #[clap(long)]
+        search: Vec<PathBuf>,
+    },
+}
+
+impl LibraryCommands {
+    pub fn run(&self, settings: &Settings) -> Result<()> {
+        let LibraryCommands::Check {
+            fix,
+            dry_run,
+            search,
+        } = self;
+        let mut library = Library::try_new()?;
+        let roots: Vec<PathBuf> = settings
+            .library
+            .watch
+            .iter()
+            .chain(search)
+            .cloned()
+            .collect();
+        println!("Checking the library...");
+        let report = library_check::check(&mut library, &roots, &AtomicUsize::new(0))?;
+        println!("{report}");
+        if report.is_clean() || *dry_run {
+            return Ok(());
+        }
+        // Without a terminal there is nobody to answer
+        let interactive = io::stdin().is_terminal();
+        let confirm = |question: String| -> Result<bool> {
+            if *fix {
+                return Ok(true);
+            }
+            if !interactive {
+                return Ok(false);
+            }
+            print!("{question} [y/N] ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            Ok(answer.trim().eq_ignore_ascii_case("y"))
+        };
+
+        if !report.moved.is_empty()
+            && confirm(format!("Relocate {} moved tracks?", report.moved.len()))?
+        {
+            let moved = library_check::relocate(&mut library, &report)?;
+            println!("Relocated {moved} tracks");
+        }
+        if !report.changed.is_empty()
+            && confirm(format!(
+                "Read {} tracks with a changed duration again?",
+                report.changed.len()
+            ))?
+        {
+            let updated = library_check::update(&mut library, &report)?;
+            println!("Updated {updated} tracks");
+        }
+        let orphans = report.orphan_releases.len() + report.orphan_artists.len();
+        if (!report.missing.is_empty() || orphans > 0)
+            && confirm(format!(
+                "Delete {} missing tracks and {orphans} orphans?",
+                report.missing.len()
+            ))?
+        {
+            let deleted = library_check::delete(&mut library, &report)?;
+            println!("Deleted {deleted} entries");
+        }
+        Ok(())
+    }
+}
+
+impl Commands {
+    /// Send the command to the running player and print the reply.
+    /// Library commands run without a player, with the settings from `config`.
+    pub fn run(&self, socket: &Path, config: Option<&Path>) -> Result<()> {
+        let command = match self {
+            Commands::Library { command } => return command.run(&Settings::load(config)?),
+            Commands::Play { path } => {
+                control::Command::Play(path.as_deref().map(absolute).transpose()?)
+            }
+            Commands::Pause => control::Command::Pause,
+            Commands::Toggle => control::Command::Toggle,
+            Commands::Next => control::Command::Next,
+            Commands::Prev => control::Command::Previous,
+            Commands::Seek { position } => control::Command::Seek(position.parse()?),
+            Commands::Volume { volume } => format!("volume {volume}").parse()?,
+            Commands::Queue {
+                command: QueueCommands::Add { path },
+            } => control::Command::Queue(absolute(path)?),
+            Commands::Status { .. } => control::Command::Status,
+        };
+
+        let reply = control::request(socket, &command)?;
+        match (self, reply) {
+            (Commands::Status { json: false }, Some(status)) => {
+                let status: control::Status =
+                    serde_json::from_str(&status).context("Invalid status")?;
+                println!("{status}");
+            }
+            (_, Some(data)) => println!("{data}"),
+            (_, None) => (),
+        }
+        Ok(())
+    }
+}
+
+/// The player can run in another directory
+fn absolute(path: &Path) -> Result<PathBuf> {
+    path.canonicalize()
+        .with_context(|| format!("Could not find {}", path.display()))
+}
+
+impl Cli {
+    /// Options given on the command line take precedence over the config file
+    pub fn override_settings(&self, settings: &mut Settings) {
+        if self.theme.is_some() {
+            settings.theme.clone_from(&self.theme);
+        }
+        let audio = &mut settings.audio;
+        if self.host.is_some() {
+            audio.host.clone_from(&self.host);
+        }
+        if self.device.is_some() {
+            audio.device.clone_from(&self.device);
+        }
+        if self.sample_rate.is_some() {
+            audio.sample_rate = self.sample_rate;
+        }
+        if self.buffer_size.is_some() {
+            audio.buffer_size = self.buffer_size;
+        }
+    }
+}
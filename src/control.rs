@@ -0,0 +1,552 @@
+//! Control socket of the player.
+//!
+//! Both the TUI and the daemon listen on a Unix domain socket, by default
+//! `rmusic_tui.sock` in `$XDG_RUNTIME_DIR`. Clients write one command per line and
+//! get back one line per command: `ok`, `ok <data>` or `error <message>`.
+//! A TUI that is started while a player is listening controls that player through
+//! the socket, instead of starting a second one.
+//!
+//! | command             | effect                                                   |
+//! |---------------------|----------------------------------------------------------|
+//! | `play [path]`       | play a file, or resume playback without a path           |
+//! | `pause`             | pause playback                                           |
+//! | `toggle`            | toggle between playing and paused                        |
+//! | `next`              | play the next track                                      |
+//! | `prev`              | play the previous track                                  |
+//! | `seek <position>`   | seek to `1:30` or `90` seconds, `+10` or `-10` relative  |
+//! | `volume <percent>`  | set the volume, `+5` or `-5` change it relatively        |
+//! | `forward <seconds>` | seek forward                                             |
+//! | `rewind <seconds>`  | seek backward                                            |
+//! | `queue <path>`      | add a file to the end of the queue                       |
+//! | `shuffle`           | cycle the shuffle mode                                   |
+//! | `repeat`            | cycle the repeat mode                                    |
+//! | `status`            | `ok` followed by the [`Status`] as a JSON object         |
+//! | `quit`              | stop the player                                          |
+//!
+//! Paths have to be absolute and take up the rest of the line, so they can contain spaces.
+//!
+//! ```text
+//! $ echo status | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/rmusic_tui.sock
+//! ok {"track":"/music/song.opus","playing":true,"played":12,"length":201,"volume":20,...}
+//! ```
+
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use directories::ProjectDirs;
+use log::{info, warn};
+use rmusic::{database::Library, playback_loop::PlaybackAction};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    transport::{ArcTransport, Transport},
+    volume::Volume,
+};
+
+const SOCKET_FILE: &str = "rmusic_tui.sock";
+
+/// Default location of the control socket
+pub fn socket_path() -> PathBuf {
+    match ProjectDirs::from("", "", "rmusic_tui")
+        .and_then(|dirs| dirs.runtime_dir().map(Path::to_path_buf))
+    {
+        Some(dir) => dir.join(SOCKET_FILE),
+        None => std::env::temp_dir().join(format!(
+            "rmusic_tui-{}.sock",
+            std::env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+pub enum Command {
+    Play(Option<PathBuf>),
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Seek(SeekPosition),
+    Volume(VolumeChange),
+    Forward(u64),
+    Rewind(u64),
+    Queue(PathBuf),
+    Shuffle,
+    Repeat,
+    Status,
+    Quit,
+}
+
+pub enum VolumeChange {
+    /// Set the volume in percent
+    Set(u8),
+    /// Change the volume by percentage points
+    Relative(i16),
+}
+
+pub enum SeekPosition {
+    /// Seconds from the start of the track
+    Absolute(u64),
+    Forward(u64),
+    Backward(u64),
+}
+
+impl FromStr for SeekPosition {
+    type Err = anyhow::Error;
+
+    fn from_str(position: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid position `{position}`, use `1:30`, `90`, `+10` or `-10`");
+        if let Some(seconds) = position.strip_prefix('+') {
+            return Ok(SeekPosition::Forward(
+                seconds.parse().map_err(|_| invalid())?,
+            ));
+        }
+        if let Some(seconds) = position.strip_prefix('-') {
+            return Ok(SeekPosition::Backward(
+                seconds.parse().map_err(|_| invalid())?,
+            ));
+        }
+        let seconds = match position.split_once(':') {
+            Some((minutes, seconds)) => {
+                let minutes: u64 = minutes.parse().map_err(|_| invalid())?;
+                let seconds: u64 = seconds.parse().map_err(|_| invalid())?;
+                if seconds >= 60 {
+                    return Err(invalid());
+                }
+                minutes * 60 + seconds
+            }
+            None => position.parse().map_err(|_| invalid())?,
+        };
+        Ok(SeekPosition::Absolute(seconds))
+    }
+}
+
+impl SeekPosition {
+    /// Like parsing, but also accepts a percentage of a track of `length` seconds, like `50%`
+    pub fn parse_in_track(position: &str, length: u64) -> Result<SeekPosition> {
+        let Some(percent) = position.strip_suffix('%') else {
+            return position.parse();
+        };
+        let percent: f64 = percent
+            .trim()
+            .parse()
+            .ok()
+            .filter(|percent| (0.0..=100.0).contains(percent))
+            .ok_or_else(|| anyhow!("Invalid percentage `{position}`, use 0% to 100%"))?;
+        Ok(SeekPosition::Absolute(
+            (length as f64 * percent / 100.0) as u64,
+        ))
+    }
+
+    /// The action that seeks to this position, `played` is the current position in seconds
+    pub fn to_action(&self, played: u64) -> PlaybackAction {
+        match *self {
+            SeekPosition::Absolute(seconds) if seconds >= played => {
+                PlaybackAction::FastForward(seconds - played)
+            }
+            SeekPosition::Absolute(seconds) => PlaybackAction::Rewind(played - seconds),
+            SeekPosition::Forward(seconds) => PlaybackAction::FastForward(seconds),
+            SeekPosition::Backward(seconds) => PlaybackAction::Rewind(seconds),
+        }
+    }
+}
+
+impl fmt::Display for SeekPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeekPosition::Absolute(seconds) => write!(f, "{}:{:02}", seconds / 60, seconds % 60),
+            SeekPosition::Forward(seconds) => write!(f, "+{seconds}"),
+            SeekPosition::Backward(seconds) => write!(f, "-{seconds}"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (line, None),
+        };
+        let required = |name: &str| argument.ok_or_else(|| anyhow!("`{command}` needs {name}"));
+        let path = |argument: &str| -> Result<PathBuf> {
+            let path = PathBuf::from(argument);
+            if !path.is_absolute() {
+                bail!("`{command}` needs an absolute path");
+            }
+            Ok(path)
+        };
+        let no_argument = |command: Command| {
+            if argument.is_some() {
+                bail!("`{line}` does not take an argument");
+            }
+            Ok(command)
+        };
+
+        match command {
+            "play" => Ok(Command::Play(argument.map(path).transpose()?)),
+            "pause" => no_argument(Command::Pause),
+            "toggle" => no_argument(Command::Toggle),
+            "next" => no_argument(Command::Next),
+            "prev" => no_argument(Command::Previous),
+            "seek" => Ok(Command::Seek(required("a position")?.parse()?)),
+            "volume" => {
+                let volume = required("a percentage")?;
+                let change = if volume.starts_with(['+', '-']) {
+                    VolumeChange::Relative(volume.parse()?)
+                } else {
+                    VolumeChange::Set(volume.parse()?)
+                };
+                Ok(Command::Volume(change))
+            }
+            "forward" => Ok(Command::Forward(
+                required("a number of seconds")?
+                    .parse()
+                    .context("Invalid number of seconds")?,
+            )),
+            "rewind" => Ok(Command::Rewind(
+                required("a number of seconds")?
+                    .parse()
+                    .context("Invalid number of seconds")?,
+            )),
+            "queue" => Ok(Command::Queue(path(required("a path")?)?)),
+            "shuffle" => no_argument(Command::Shuffle),
+            "repeat" => no_argument(Command::Repeat),
+            "status" => no_argument(Command::Status),
+            "quit" => no_argument(Command::Quit),
+            "" => bail!("Empty command"),
+            other => bail!("Unknown command `{other}`"),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Play(None) => write!(f, "play"),
+            Command::Play(Some(path)) => write!(f, "play {}", path.display()),
+            Command::Pause => write!(f, "pause"),
+            Command::Toggle => write!(f, "toggle"),
+            Command::Next => write!(f, "next"),
+            Command::Previous => write!(f, "prev"),
+            Command::Seek(position) => write!(f, "seek {position}"),
+            Command::Volume(VolumeChange::Set(percent)) => write!(f, "volume {percent}"),
+            Command::Volume(VolumeChange::Relative(points)) => write!(f, "volume {points:+}"),
+            Command::Forward(seconds) => write!(f, "forward {seconds}"),
+            Command::Rewind(seconds) => write!(f, "rewind {seconds}"),
+            Command::Queue(path) => write!(f, "queue {}", path.display()),
+            Command::Shuffle => write!(f, "shuffle"),
+            Command::Repeat => write!(f, "repeat"),
+            Command::Status => write!(f, "status"),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+/// Playback status as returned by the `status` command
+#[derive(Serialize, Deserialize)]
+pub struct Status {
+    /// Path of the current track, empty if nothing is playing
+    pub track: String,
+    pub playing: bool,
+    /// Seconds played of the current track
+    pub played: u64,
+    /// Length of the current track in seconds
+    pub length: u64,
+    /// Volume in percent
+    pub volume: u8,
+    pub sample_rate: usize,
+    pub shuffle: String,
+    pub repeat: String,
+}
+
+impl Status {
+    pub fn new(transport: &Transport, volume: &Volume) -> Self {
+        let playback_context = transport.playback_context();
+        let queue = playback_context.lock_queue();
+        Status {
+            track: queue
+                .current_track()
+                .as_ref()
+                .map(|track| track.display().to_string())
+                .unwrap_or_default(),
+            playing: playback_context.is_playing(),
+            played: playback_context.played_sec(),
+            length: playback_context.length_sec(),
+            volume: volume.percent(transport),
+            sample_rate: playback_context.sample_rate(),
+            shuffle: queue.queue_options.shuffle_type.display_small().to_string(),
+            repeat: transport.repeat().display_small().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.track.is_empty() {
+            return write!(f, "Nothing playing, volume {}%", self.volume);
+        }
+        writeln!(
+            f,
+            "{} {}",
+            if self.playing { "Playing" } else { "Paused" },
+            self.track
+        )?;
+        write!(
+            f,
+            "{}:{:02}/{}:{:02} volume {}% shuffle {} repeat {}",
+            self.played / 60,
+            self.played % 60,
+            self.length / 60,
+            self.length % 60,
+            self.volume,
+            self.shuffle,
+            self.repeat,
+        )
+    }
+}
+
+/// Send a command to a running player, returns the data of the reply if there is any
+pub fn request(socket: &Path, command: &Command) -> Result<Option<String>> {
+    let stream = UnixStream::connect(socket).with_context(|| {
+        format!(
+            "Could not connect to {}, is rmusic_tui running?",
+            socket.display()
+        )
+    })?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut writer = stream.try_clone()?;
+    writeln!(writer, "{command}")?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let reply = reply.trim_end();
+    if reply == "ok" {
+        Ok(None)
+    } else if let Some(data) = reply.strip_prefix("ok ") {
+        Ok(Some(data.to_string()))
+    } else if let Some(message) = reply.strip_prefix("error ") {
+        bail!("{message}")
+    } else {
+        bail!("Unexpected reply `{reply}`")
+    }
+}
+
+/// Returns true if a player is listening on the socket
+pub fn is_running(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// Bind the control socket, replacing a stale socket of a player that is no longer running
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if is_running(path) {
+            bail!("Another player is already listening on {}", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Could not remove stale socket {}", path.display()))?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    UnixListener::bind(path).with_context(|| format!("Could not bind to {}", path.display()))
+}
+
+/// Everything a client connection needs to run commands
+pub struct Handler {
+    pub transport: ArcTransport,
+    pub library: Library,
+    /// Set when a client sends `quit`
+    pub quit: Arc<AtomicBool>,
+    pub volume: Volume,
+}
+
+impl Handler {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Handler {
+            transport: self.transport.clone(),
+            library: self.library.try_clone()?,
+            quit: self.quit.clone(),
+            volume: self.volume,
+        })
+    }
+}
+
+/// Accept clients until one of them sends `quit`
+pub fn serve(listener: UnixListener, path: &Path, handler: Handler) -> Result<()> {
+    info!("Listening on {}", path.display());
+    for stream in listener.incoming() {
+        if handler.quit.load(Ordering::Relaxed) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Could not accept control connection: {err}");
+                continue;
+            }
+        };
+        let mut client = match handler.try_clone() {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("Could not open the library for a control connection: {err}");
+                continue;
+            }
+        };
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            if let Err(err) = client.handle(stream) {
+                warn!("Control connection closed: {err}");
+            }
+            if client.quit.load(Ordering::Relaxed) {
+                // Wake up the accept loop so it sees the quit flag
+                let _ = UnixStream::connect(path);
+            }
+        });
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+impl Handler {
+    fn handle(&mut self, stream: UnixStream) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match line.parse().and_then(|command| self.execute(command)) {
+                Ok(Some(data)) => format!("ok {data}"),
+                Ok(None) => "ok".to_string(),
+                Err(err) => format!("error {err:#}"),
+            };
+            writeln!(writer, "{reply}")?;
+            if self.quit.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a command, returns the data to send back if there is any
+    fn execute(&mut self, command: Command) -> Result<Option<String>> {
+        let playback_context = self.transport.playback_context();
+        match command {
+            Command::Play(Some(path)) => {
+                let queue_item = self.library.queue_item_from_path(&path)?;
+                self.send(PlaybackAction::Play(queue_item))?
+            }
+            Command::Play(None) => {
+                if !playback_context.is_playing() {
+                    self.send(PlaybackAction::PlayPause)?
+                }
+            }
+            Command::Pause => {
+                if playback_context.is_playing() {
+                    self.send(PlaybackAction::PlayPause)?
+                }
+            }
+            Command::Toggle => self.send(PlaybackAction::PlayPause)?,
+            Command::Next => self.transport.next()?,
+            Command::Previous => self.transport.previous()?,
+            Command::Seek(position) => {
+                self.send(position.to_action(playback_context.played_sec()))?
+            }
+            Command::Volume(VolumeChange::Set(percent)) => {
+                self.send(self.volume.set(percent, &self.transport))?
+            }
+            Command::Volume(VolumeChange::Relative(points)) => {
+                self.send(self.volume.change(points, &self.transport))?
+            }
+            Command::Forward(seconds) => self.send(PlaybackAction::FastForward(seconds))?,
+            Command::Rewind(seconds) => self.send(PlaybackAction::Rewind(seconds))?,
+            Command::Queue(path) => {
+                let queue_item = self.library.queue_item_from_path(&path)?;
+                playback_context
+                    .lock_queue()
+                    .append_queue_item(queue_item, true);
+            }
+            Command::Shuffle => playback_context.lock_queue().cycle_shuffle(),
+            Command::Repeat => self.transport.cycle_repeat(),
+            Command::Status => {
+                return Ok(Some(serde_json::to_string(&Status::new(
+                    &self.transport,
+                    &self.volume,
+                ))?));
+            }
+            Command::Quit => self.quit.store(true, Ordering::Relaxed),
+        }
+        Ok(None)
+    }
+
+    fn send(&self, action: PlaybackAction) -> Result<()> {
+        self.transport.send(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seek_positions() {
+        assert!(matches!(
+            "1:30".parse::<SeekPosition>(),
+            Ok(SeekPosition::Absolute(90))
+        ));
+        assert!(matches!(
+            "90".parse::<SeekPosition>(),
+            Ok(SeekPosition::Absolute(90))
+        ));
+        assert!(matches!(
+            "+10".parse::<SeekPosition>(),
+            Ok(SeekPosition::Forward(10))
+        ));
+        assert!(matches!(
+            "-5".parse::<SeekPosition>(),
+            Ok(SeekPosition::Backward(5))
+        ));
+        assert!("1:60".parse::<SeekPosition>().is_err());
+        assert!("+".parse::<SeekPosition>().is_err());
+        assert!("abc".parse::<SeekPosition>().is_err());
+    }
+
+    #[test]
+    fn seek_percentages() {
+        assert!(matches!(
+            SeekPosition::parse_in_track("50%", 200),
+            Ok(SeekPosition::Absolute(100))
+        ));
+        assert!(matches!(
+            SeekPosition::parse_in_track("0:10", 200),
+            Ok(SeekPosition::Absolute(10))
+        ));
+        assert!(SeekPosition::parse_in_track("101%", 200).is_err());
+        assert!(SeekPosition::parse_in_track("-1%", 200).is_err());
+    }
+
+    #[test]
+    fn seek_actions() {
+        assert!(matches!(
+            SeekPosition::Absolute(30).to_action(10),
+            PlaybackAction::FastForward(20)
+        ));
+        assert!(matches!(
+            SeekPosition::Absolute(10).to_action(30),
+            PlaybackAction::Rewind(20)
+        ));
+    }
+}
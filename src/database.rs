@@ -0,0 +1,116 @@
+//! Library edits and lookups that rmusic does not offer.
+//!
+//! rmusic keeps the library in a database through sea-orm and only has methods
+//! to add files, so these run queries on its entities over the connection of the library.
+
+use std::path::Path;
+
+use anyhow::Result;
+use futures::executor::block_on;
+use rmusic::{
+    database::{library_view::LibraryView, Library},
+    models::{artist, release, track, Artist, Release, Track},
+    queue::queue_items::QueueItem,
+};
+use sea_orm::{sea_query::Expr, ColumnTrait, EntityTrait, QueryFilter};
+
+/// Delete the track of a file, returns false if there was none
+pub fn remove_file(library: &Library, path: &Path) -> Result<bool> {
+    let result = block_on(
+        track::Entity::delete_many()
+            .filter(track::Column::Path.eq(path.to_string_lossy().into_owned()))
+            .exec(library.connection()),
+    )?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Point the track of a file to the file it moved to, returns false if there was no track
+pub fn relocate_file(library: &Library, from: &Path, to: &Path) -> Result<bool> {
+    let result = block_on(
+        track::Entity::update_many()
+            .col_expr(
+                track::Column::Path,
+                Expr::value(to.to_string_lossy().into_owned()),
+            )
+            .filter(track::Column::Path.eq(from.to_string_lossy().into_owned()))
+            .exec(library.connection()),
+    )?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Read a file into the library again, or add it when it is new. `add_file`
+/// on its own would add a file that is already in the library a second time.
+pub fn reread_file(library: &mut Library, path: &Path) -> Result<()> {
+    if has_file(library, path)? {
+        remove_file(library, path)?;
+    }
+    library.add_file(path)?;
+    Ok(())
+}
+
+/// Returns true if the library has a track for the file
+pub fn has_file(library: &Library, path: &Path) -> Result<bool> {
+    let track = block_on(
+        track::Entity::find()
+            .filter(track::Column::Path.eq(path.to_string_lossy().into_owned()))
+            .one(library.connection()),
+    )?;
+    Ok(track.is_some())
+}
+
+pub fn delete_release(library: &Library, release: &Release) -> Result<()> {
+    block_on(release::Entity::delete_by_id(release.id).exec(library.connection()))?;
+    Ok(())
+}
+
+pub fn delete_artist(library: &Library, artist: &Artist) -> Result<()> {
+    block_on(artist::Entity::delete_by_id(artist.id).exec(library.connection()))?;
+    Ok(())
+}
+
+/// The queue item of an artist, like picking it in the library view, `None` if it is gone
+pub fn artist_queue_item(library: &mut Library, artist_id: i32) -> Result<Option<QueueItem>> {
+    let mut library_view = LibraryView::<Artist, Release, Track>::new(library)?;
+    library_view.sync_with_database_all(library)?;
+    let Some(l1) = artist_index(&library_view, artist_id) else {
+        return Ok(None);
+    };
+    Ok(Some(library_view.get_context_l1(library, l1)?))
+}
+
+/// The queue item of a release, like picking it in the library view, `None` if it is gone.
+/// The library view is synced to find the artist, which reads the whole library.
+pub fn release_queue_item(library: &mut Library, release_id: i32) -> Result<Option<QueueItem>> {
+    let Some(release) =
+        block_on(release::Entity::find_by_id(release_id).one(library.connection()))?
+    else {
+        return Ok(None);
+    };
+    let mut library_view = LibraryView::<Artist, Release, Track>::new(library)?;
+    library_view.sync_with_database_all(library)?;
+    let Some(l1) = artist_index(&library_view, release.artist_id) else {
+        return Ok(None);
+    };
+    library_view.sync_with_database_l2_item(library, l1)?;
+    let Some(l2) = library_view
+        .get_l2(l1)
+        .iter()
+        .position(|release| release.id == release_id)
+    else {
+        return Ok(None);
+    };
+    Ok(Some(library_view.get_context_l2(library, (l1, l2))?))
+}
+
+/// The artist of a release
+pub fn release_artist(library: &Library, release_id: i32) -> Result<Option<i32>> {
+    let release = block_on(release::Entity::find_by_id(release_id).one(library.connection()))?;
+    Ok(release.map(|release| release.artist_id))
+}
+
+fn artist_index(library_view: &LibraryView<Artist, Release, Track>, id: i32) -> Option<usize> {
+    library_view
+        .get_l1()
+        .iter()
+        .position(|artist| artist.id == id)
+}
@@ -0,0 +1,316 @@
+//! The tracks that were played, kept by the transport for every front end.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use rmusic::{playback::playback_context::ArcPlaybackContext, playback_loop::PlaybackAction};
+
+use crate::{control::SeekPosition, ui::item_tracks};
+
+/// Tracks kept in the history, older ones are dropped
+const HISTORY_LIMIT: usize = 200;
+/// Previous restarts the track when more seconds than this have been played
+const RESTART_AFTER: u64 = 3;
+
+/// The tracks that were played, in the order they were played, so previous and
+/// next follow what was heard and not the queue order, which differs with shuffle
+pub struct History {
+    /// The oldest first
+    entries: VecDeque<Entry>,
+    /// The entry that is playing after going back, `None` when playing the newest
+    cursor: Option<usize>,
+    /// The current track when the queue was last looked at
+    last: Option<PathBuf>,
+}
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    /// Position in the queue when it was played, the queue may have changed since
+    position: (usize, usize),
+    pub played_at: Instant,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            entries: VecDeque::new(),
+            cursor: None,
+            last: None,
+        }
+    }
+
+    /// Record the current track when it has changed
+    pub fn update(&mut self, playback_context: &ArcPlaybackContext) {
+        let queue = playback_context.lock_queue();
+        let current = queue.current_track().clone();
+        if current == self.last {
+            return;
+        }
+        let position = queue.current_position();
+        let name = position.and_then(|(item, track)| {
+            queue.queue_items().get(item).and_then(|queue_item| {
+                item_tracks(queue_item)
+                    .get(track)
+                    .map(|track| track.name.clone())
+            })
+        });
+        self.record(current, position, name);
+    }
+
+    /// Add the current track unless it is the entry that is playing
+    fn record(
+        &mut self,
+        current: Option<PathBuf>,
+        position: Option<(usize, usize)>,
+        name: Option<String>,
+    ) {
+        self.last = current.clone();
+        let (Some(path), Some(position)) = (current, position) else {
+            return;
+        };
+        // Reached by going back or forward through the history
+        if let Some(cursor) = self.cursor {
+            if self.entries[cursor].path == path {
+                return;
+            }
+        } else if self.entries.back().is_some_and(|entry| entry.path == path) {
+            return;
+        }
+        let name = name.unwrap_or_else(|| {
+            path.file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+        });
+        self.entries.push_back(Entry {
+            path,
+            name,
+            position,
+            played_at: Instant::now(),
+        });
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.pop_front();
+        }
+        self.cursor = None;
+    }
+
+    /// Restart the track, or play the one that was played before it
+    pub fn previous(&mut self, playback_context: &ArcPlaybackContext) -> PlaybackAction {
+        let played = playback_context.played_sec();
+        if restarts(played) {
+            return SeekPosition::Absolute(0).to_action(played);
+        }
+        let Some(target) = self.previous_entry() else {
+            return PlaybackAction::Previous;
+        };
+        match self.play(target, playback_context) {
+            Some(action) => {
+                self.move_cursor(target);
+                action
+            }
+            None => PlaybackAction::Previous,
+        }
+    }
+
+    /// Go forward again after going back, otherwise the next track of the queue
+    pub fn next(&mut self, playback_context: &ArcPlaybackContext) -> PlaybackAction {
+        let Some(target) = self.next_entry() else {
+            return PlaybackAction::Next;
+        };
+        match self.play(target, playback_context) {
+            Some(action) => {
+                self.move_cursor(target);
+                action
+            }
+            None => {
+                self.cursor = None;
+                PlaybackAction::Next
+            }
+        }
+    }
+
+    /// The entry before the one that is playing
+    fn previous_entry(&self) -> Option<usize> {
+        let current = self.cursor.or_else(|| self.entries.len().checked_sub(1))?;
+        current.checked_sub(1)
+    }
+
+    /// The entry after the one that is playing, when going back through the history
+    fn next_entry(&self) -> Option<usize> {
+        self.cursor
+            .map(|cursor| cursor + 1)
+            .filter(|target| *target < self.entries.len())
+    }
+
+    /// Play the entry at `target` from now on, the newest entry ends going back
+    fn move_cursor(&mut self, target: usize) {
+        self.cursor = (target + 1 < self.entries.len()).then_some(target);
+    }
+
+    /// Play an entry from the queue, `None` if it is no longer in the queue
+    pub fn play(
+        &self,
+        index: usize,
+        playback_context: &ArcPlaybackContext,
+    ) -> Option<PlaybackAction> {
+        let entry = self.entries.get(index)?;
+        let queue = playback_context.lock_queue();
+        let items: Vec<Vec<&Path>> = queue
+            .queue_items()
+            .iter()
+            .map(|queue_item| {
+                item_tracks(queue_item)
+                    .iter()
+                    .map(|track| Path::new(&track.path))
+                    .collect()
+            })
+            .collect();
+        let (item, track) = queue_position(entry, &items)?;
+        Some(PlaybackAction::PlayFromQueue(item, track))
+    }
+
+    /// The entries, the oldest first
+    pub fn entries(&self) -> &VecDeque<Entry> {
+        &self.entries
+    }
+
+    /// The index of the entry that is playing
+    pub fn playing(&self) -> Option<usize> {
+        if self.last.is_none() {
+            return None;
+        }
+        self.cursor.or_else(|| self.entries.len().checked_sub(1))
+    }
+}
+
+/// Previous restarts the track instead of going back after `played` seconds
+fn restarts(played: u64) -> bool {
+    played > RESTART_AFTER
+}
+
+/// Where the entry is in the queue, `items` has the track paths of every queue
+/// item. The same place as when it was played first, the track may be in the
+/// queue more than once.
+fn queue_position(entry: &Entry, items: &[Vec<&Path>]) -> Option<(usize, usize)> {
+    let is_entry = |(item, track): (usize, usize)| {
+        items
+            .get(item)
+            .and_then(|tracks| tracks.get(track))
+            .is_some_and(|path| *path == entry.path)
+    };
+    Some(entry.position)
+        .filter(|position| is_entry(*position))
+        .or_else(|| {
+            items.iter().enumerate().find_map(|(item, tracks)| {
+                tracks
+                    .iter()
+                    .position(|path| *path == entry.path)
+                    .map(|track| (item, track))
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(history: &mut History, name: &str, position: (usize, usize)) {
+        let path = PathBuf::from(format!("/music/{name}.flac"));
+        history.record(Some(path), Some(position), Some(name.to_string()));
+    }
+
+    fn names(history: &History) -> Vec<&str> {
+        history
+            .entries()
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn records_changed_tracks() {
+        let mut history = History::new();
+        assert_eq!(history.playing(), None);
+        play(&mut history, "a", (0, 0));
+        play(&mut history, "a", (0, 0));
+        play(&mut history, "b", (0, 1));
+        assert_eq!(names(&history), ["a", "b"]);
+        assert_eq!(history.playing(), Some(1));
+        // Nothing playing is not an entry
+        history.record(None, None, None);
+        assert_eq!(history.playing(), None);
+        assert_eq!(names(&history), ["a", "b"]);
+        // Without a name the file name is used
+        history.record(Some(PathBuf::from("/music/c.flac")), Some((1, 0)), None);
+        assert_eq!(names(&history), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn keeps_the_newest_entries() {
+        let mut history = History::new();
+        for index in 0..HISTORY_LIMIT + 5 {
+            play(&mut history, &index.to_string(), (index, 0));
+        }
+        assert_eq!(history.entries().len(), HISTORY_LIMIT);
+        assert_eq!(history.entries()[0].name, "5");
+    }
+
+    #[test]
+    fn back_and_forth() {
+        let mut history = History::new();
+        for name in ["a", "b", "c"] {
+            play(&mut history, name, (0, 0));
+        }
+        assert_eq!(history.next_entry(), None);
+        assert_eq!(history.previous_entry(), Some(1));
+        history.move_cursor(1);
+        play(&mut history, "b", (0, 0));
+        assert_eq!(history.playing(), Some(1));
+        assert_eq!(history.previous_entry(), Some(0));
+        history.move_cursor(0);
+        assert_eq!(history.previous_entry(), None);
+        assert_eq!(history.next_entry(), Some(1));
+        history.move_cursor(1);
+        assert_eq!(history.next_entry(), Some(2));
+        // Back at the newest entry
+        history.move_cursor(2);
+        assert_eq!(history.next_entry(), None);
+        assert_eq!(history.playing(), Some(2));
+        assert_eq!(names(&history), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn new_track_after_going_back() {
+        let mut history = History::new();
+        for name in ["a", "b", "c"] {
+            play(&mut history, name, (0, 0));
+        }
+        history.move_cursor(0);
+        play(&mut history, "d", (0, 0));
+        assert_eq!(names(&history), ["a", "b", "c", "d"]);
+        assert_eq!(history.playing(), Some(3));
+        assert_eq!(history.next_entry(), None);
+    }
+
+    #[test]
+    fn restart_or_go_back() {
+        assert!(!restarts(0));
+        assert!(!restarts(RESTART_AFTER));
+        assert!(restarts(RESTART_AFTER + 1));
+    }
+
+    #[test]
+    fn finds_entries_in_the_queue() {
+        let mut history = History::new();
+        play(&mut history, "a", (1, 1));
+        let entry = &history.entries()[0];
+        let a = Path::new("/music/a.flac");
+        let b = Path::new("/music/b.flac");
+        assert_eq!(queue_position(entry, &[vec![a], vec![b, a]]), Some((1, 1)));
+        // The queue changed since
+        assert_eq!(queue_position(entry, &[vec![b], vec![a]]), Some((1, 0)));
+        assert_eq!(queue_position(entry, &[vec![b]]), None);
+    }
+}
@@ -0,0 +1,559 @@
+//! Checking that the library matches the files on disk.
+//!
+//! Every track is compared with its file: files that are gone are missing, unless
+//! a file with the same content is found in the music folders or near the old
+//! location, then the track has moved. The content hashes are kept from the last
+//! check, because the file of a missing track can not be read anymore. Tracks
+//! without a hash are matched by title or file name and duration instead.
+//! Artists without releases and releases without tracks are orphans.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result};
+use lofty::{
+    file::{AudioFile, TaggedFileExt},
+    tag::Accessor,
+};
+use log::warn;
+use rmusic::{
+    database::{library_view::LibraryView, Library},
+    models::{Artist, Release, Track},
+};
+
+use rmusic_tui::settings::data_dir;
+use serde::{Deserialize, Serialize};
+
+use crate::{database, scan::audio_files};
+
+/// Difference in seconds for a duration to still be the same
+const DURATION_MARGIN: i64 = 2;
+const HASH_FILE: &str = "content_hashes.json";
+/// Bytes hashed from the start and from the end of a file
+const HASH_BLOCK: u64 = 64 * 1024;
+
+#[derive(Default)]
+pub struct Report {
+    pub checked: usize,
+    /// Tracks whose file is gone and was not found somewhere else
+    pub missing: Vec<Track>,
+    /// Tracks whose file is gone, with the file they most likely moved to
+    pub moved: Vec<(Track, PathBuf)>,
+    /// Tracks with the duration of their file in seconds, when it differs from the library
+    pub changed: Vec<(Track, i64)>,
+    pub orphan_releases: Vec<Release>,
+    pub orphan_artists: Vec<Artist>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty()
+            && self.moved.is_empty()
+            && self.changed.is_empty()
+            && self.orphan_releases.is_empty()
+            && self.orphan_artists.is_empty()
+    }
+
+    /// The number of problems of every kind, one kind per line
+    pub fn summary(&self) -> String {
+        format!(
+            "Checked {} tracks\n{} missing\n{} moved\n{} with a changed duration\n{} orphaned releases\n{} orphaned artists",
+            self.checked,
+            self.missing.len(),
+            self.moved.len(),
+            self.changed.len(),
+            self.orphan_releases.len(),
+            self.orphan_artists.len(),
+        )
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Checked {} tracks", self.checked)?;
+        writeln!(f, "Missing ({}):", self.missing.len())?;
+        for track in &self.missing {
+            writeln!(f, "  {}", track.path)?;
+        }
+        writeln!(f, "Moved ({}):", self.moved.len())?;
+        for (track, path) in &self.moved {
+            writeln!(f, "  {} -> {}", track.path, path.display())?;
+        }
+        writeln!(f, "Changed duration ({}):", self.changed.len())?;
+        for (track, duration) in &self.changed {
+            writeln!(
+                f,
+                "  {} ({} -> {})",
+                track.path,
+                format_duration(track.duration as i64),
+                format_duration(*duration)
+            )?;
+        }
+        writeln!(f, "Orphaned releases ({}):", self.orphan_releases.len())?;
+        for release in &self.orphan_releases {
+            writeln!(f, "  {}", release.name)?;
+        }
+        write!(f, "Orphaned artists ({}):", self.orphan_artists.len())?;
+        for artist in &self.orphan_artists {
+            write!(f, "\n  {}", artist.name)?;
+        }
+        Ok(())
+    }
+}
+
+fn format_duration(seconds: i64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// What is read from an audio file to compare it with a track
+struct FileInfo {
+    title: Option<String>,
+    /// Duration in seconds
+    duration: i64,
+}
+
+fn file_info(path: &Path) -> Result<FileInfo> {
+    let tagged_file = lofty::read_from_path(path)?;
+    let title = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+        .and_then(|tag| tag.title().map(|title| title.to_string()));
+    Ok(FileInfo {
+        title,
+        duration: tagged_file.properties().duration().as_secs() as i64,
+    })
+}
+
+/// FNV-1a of the size and the first and last block of a file, the same for a file
+/// that was moved and cheap for big files
+fn content_hash(path: &Path) -> Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut data = size.to_le_bytes().to_vec();
+    file.by_ref().take(HASH_BLOCK).read_to_end(&mut data)?;
+    if size > HASH_BLOCK {
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(HASH_BLOCK).max(HASH_BLOCK),
+        ))?;
+        file.read_to_end(&mut data)?;
+    }
+    Ok(data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    }))
+}
+
+/// The content hashes of the files of the tracks, by path
+#[derive(Serialize, Deserialize, Default)]
+struct HashIndex {
+    files: HashMap<String, HashEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct HashEntry {
+    size: u64,
+    /// Modification time in seconds since the epoch
+    modified: u64,
+    hash: u64,
+}
+
+impl HashIndex {
+    fn load() -> Result<HashIndex> {
+        let Some(path) = hash_index_path().filter(|path| path.exists()) else {
+            return Ok(HashIndex::default());
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Could not parse {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = hash_index_path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(self)?)
+            .with_context(|| format!("Could not write {}", path.display()))
+    }
+
+    /// The hash of a file, only read again when its size or modification time changed
+    fn hash(&mut self, path: &Path) -> Result<u64> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let key = path.to_string_lossy().into_owned();
+        if let Some(entry) = self.files.get(&key) {
+            if entry.size == metadata.len() && entry.modified == modified {
+                return Ok(entry.hash);
+            }
+        }
+        let hash = content_hash(path)?;
+        self.files.insert(
+            key,
+            HashEntry {
+                size: metadata.len(),
+                modified,
+                hash,
+            },
+        );
+        Ok(hash)
+    }
+}
+
+fn hash_index_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(HASH_FILE))
+}
+
+/// Check every track in the library. `roots` are searched for moved files,
+/// `progress` counts the tracks that have been checked.
+pub fn check(library: &mut Library, roots: &[PathBuf], progress: &AtomicUsize) -> Result<Report> {
+    let tracks = library.find_all::<Track>()?;
+    let mut report = Report {
+        checked: tracks.len(),
+        ..Default::default()
+    };
+    let old_index = HashIndex::load().unwrap_or_else(|err| {
+        warn!("Starting a new content hash index: {err:#}");
+        HashIndex::default()
+    });
+    // Only the tracks that are in the library now are kept
+    let mut index = HashIndex::default();
+    for track in &tracks {
+        if let Some(entry) = old_index.files.get(&track.path) {
+            index.files.insert(track.path.clone(), *entry);
+        }
+        let path = Path::new(&track.path);
+        if !path.is_file() {
+            report.missing.push(track.clone());
+        } else {
+            if let Err(err) = index.hash(path) {
+                warn!("Could not hash {}: {err}", path.display());
+            }
+            match file_info(path) {
+                Ok(info) if (info.duration - track.duration as i64).abs() > DURATION_MARGIN => {
+                    report.changed.push((track.clone(), info.duration))
+                }
+                Ok(_) => (),
+                Err(err) => warn!("Could not read {}: {err}", path.display()),
+            }
+        }
+        progress.fetch_add(1, Ordering::Relaxed);
+    }
+    find_moved(&mut report, &tracks, roots, &index);
+    if let Err(err) = index.save() {
+        warn!("Could not save the content hashes: {err:#}");
+    }
+    (report.orphan_releases, report.orphan_artists) = orphans(library)?;
+    Ok(report)
+}
+
+/// Move the missing tracks that have exactly one matching file to `report.moved`
+fn find_moved(report: &mut Report, tracks: &[Track], roots: &[PathBuf], index: &HashIndex) {
+    if report.missing.is_empty() {
+        return;
+    }
+    // Renamed album folders are found from the artist folder
+    let mut dirs: Vec<PathBuf> = roots.to_vec();
+    for track in &report.missing {
+        if let Some(dir) = Path::new(&track.path).ancestors().nth(2) {
+            if dir.is_dir() && !dirs.iter().any(|root| dir.starts_with(root)) {
+                dirs.push(dir.to_path_buf());
+            }
+        }
+    }
+
+    let known: HashSet<&Path> = tracks.iter().map(|track| Path::new(&track.path)).collect();
+    let mut candidates: Vec<Candidate> = vec![];
+    for dir in &dirs {
+        let files = match audio_files(dir) {
+            Ok(files) => files,
+            Err(err) => {
+                warn!("Not searching {} for moved files: {err:#}", dir.display());
+                continue;
+            }
+        };
+        for file in files {
+            if known.contains(file.as_path())
+                || candidates.iter().any(|candidate| candidate.path == file)
+            {
+                continue;
+            }
+            let hash = content_hash(&file)
+                .inspect_err(|err| warn!("Could not hash {}: {err}", file.display()))
+                .ok();
+            match file_info(&file) {
+                Ok(info) => candidates.push(Candidate {
+                    path: file,
+                    info,
+                    hash,
+                }),
+                Err(err) => warn!("Could not read {}: {err}", file.display()),
+            }
+        }
+    }
+
+    let mut missing = vec![];
+    for track in report.missing.drain(..) {
+        let wanted = Wanted {
+            path: Path::new(&track.path),
+            name: &track.name,
+            duration: track.duration as i64,
+            hash: index.files.get(&track.path).map(|entry| entry.hash),
+        };
+        match only_match(&wanted, &candidates) {
+            // A file can only be the new location of one track
+            Some(index) => {
+                let candidate = candidates.remove(index);
+                report.moved.push((track, candidate.path));
+            }
+            None => missing.push(track),
+        }
+    }
+    report.missing = missing;
+}
+
+/// A missing track, as far as it is matched with files
+struct Wanted<'a> {
+    path: &'a Path,
+    name: &'a str,
+    /// Duration in seconds
+    duration: i64,
+    /// The content hash from the last check
+    hash: Option<u64>,
+}
+
+/// A file that a missing track may have moved to
+struct Candidate {
+    path: PathBuf,
+    info: FileInfo,
+    hash: Option<u64>,
+}
+
+/// Returns true if the track may have moved to the file. A track with a hash
+/// only matches a file with the same content, others match on title or file
+/// name, and duration.
+fn is_match(wanted: &Wanted, candidate: &Candidate) -> bool {
+    if wanted.hash.is_some() {
+        return candidate.hash == wanted.hash;
+    }
+    let same_title = candidate
+        .info
+        .title
+        .as_ref()
+        .is_some_and(|title| title.eq_ignore_ascii_case(wanted.name));
+    (same_title || candidate.path.file_name() == wanted.path.file_name())
+        && (candidate.info.duration - wanted.duration).abs() <= DURATION_MARGIN
+}
+
+/// The index of the only candidate that matches, `None` for no or several matches
+fn only_match(wanted: &Wanted, candidates: &[Candidate]) -> Option<usize> {
+    let mut matches = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| is_match(wanted, candidate))
+        .map(|(index, _)| index);
+    match (matches.next(), matches.next()) {
+        (Some(index), None) => Some(index),
+        _ => None,
+    }
+}
+
+/// Releases without tracks and artists without releases
+fn orphans(library: &mut Library) -> Result<(Vec<Release>, Vec<Artist>)> {
+    let mut library_view: LibraryView<Artist, Release, Track> = LibraryView::new(library)?;
+    library_view.sync_with_database_all(library)?;
+    let mut releases = vec![];
+    let mut artists = vec![];
+    for l1 in 0..library_view.get_l1().len() {
+        library_view.sync_with_database_l2_item(library, l1)?;
+        if library_view.get_l2(l1).is_empty() {
+            artists.push(library_view.get_l1()[l1].clone());
+            continue;
+        }
+        for l2 in 0..library_view.get_l2(l1).len() {
+            library_view.sync_with_database_l3_item(library, (l1, l2))?;
+            if library_view.get_l3((l1, l2)).is_empty() {
+                releases.push(library_view.get_l2(l1)[l2].clone());
+            }
+        }
+    }
+    Ok((releases, artists))
+}
+
+/// Point the moved tracks to their new file, returns how many were moved
+pub fn relocate(library: &mut Library, report: &Report) -> Result<usize> {
+    for (track, path) in &report.moved {
+        database::relocate_file(library, Path::new(&track.path), path)?;
+    }
+    Ok(report.moved.len())
+}
+
+/// Read the tracks with a changed duration from their file again
+pub fn update(library: &mut Library, report: &Report) -> Result<usize> {
+    for (track, _) in &report.changed {
+        database::reread_file(library, Path::new(&track.path))?;
+    }
+    Ok(report.changed.len())
+}
+
+/// Delete the missing tracks and then the orphans, including the ones
+/// that are left behind by deleting the tracks. Returns how many entries were deleted.
+pub fn delete(library: &mut Library, report: &Report) -> Result<usize> {
+    for track in &report.missing {
+        database::remove_file(library, Path::new(&track.path))?;
+    }
+    let (releases, _) = orphans(library)?;
+    for release in &releases {
+        database::delete_release(library, release)?;
+    }
+    // Artists of the releases that were just deleted are orphans now
+    let (_, artists) = orphans(library)?;
+    for artist in &artists {
+        database::delete_artist(library, artist)?;
+    }
+    Ok(report.missing.len() + releases.len() + artists.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// An empty directory of its own for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rmusic_tui_library_check_{name}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A second of silence as a 16 bit mono WAV file
+    fn write_wav(path: &Path) {
+        let sample_rate: u32 = 8000;
+        let data_len = sample_rate * 2;
+        let mut wav = vec![];
+        wav.extend(b"RIFF");
+        wav.extend((36 + data_len).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16_u32.to_le_bytes());
+        wav.extend(1_u16.to_le_bytes());
+        wav.extend(1_u16.to_le_bytes());
+        wav.extend(sample_rate.to_le_bytes());
+        wav.extend((sample_rate * 2).to_le_bytes());
+        wav.extend(2_u16.to_le_bytes());
+        wav.extend(16_u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend(data_len.to_le_bytes());
+        wav.resize(wav.len() + data_len as usize, 0);
+        fs::File::create(path).unwrap().write_all(&wav).unwrap();
+    }
+
+    #[test]
+    fn content_hash_follows_the_content() {
+        let dir = test_dir("hash");
+        let small = dir.join("small");
+        fs::write(&small, b"some audio").unwrap();
+        let moved = dir.join("moved");
+        fs::write(&moved, b"some audio").unwrap();
+        let other = dir.join("other");
+        fs::write(&other, b"other audio").unwrap();
+        assert_eq!(content_hash(&small).unwrap(), content_hash(&moved).unwrap());
+        assert_ne!(content_hash(&small).unwrap(), content_hash(&other).unwrap());
+
+        // Only the first and last block are read of big files, the size is hashed too
+        let block = HASH_BLOCK as usize;
+        let mut big = vec![1; block * 3];
+        fs::write(dir.join("big"), &big).unwrap();
+        big[block + 1] = 2;
+        fs::write(dir.join("big_middle"), &big).unwrap();
+        big[0] = 2;
+        fs::write(dir.join("big_start"), &big).unwrap();
+        big.push(1);
+        fs::write(dir.join("big_longer"), &big).unwrap();
+        let hash = |name: &str| content_hash(&dir.join(name)).unwrap();
+        assert_eq!(hash("big"), hash("big_middle"));
+        assert_ne!(hash("big"), hash("big_start"));
+        assert_ne!(hash("big_start"), hash("big_longer"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn candidate(path: &str, title: Option<&str>, duration: i64, hash: Option<u64>) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            info: FileInfo {
+                title: title.map(str::to_string),
+                duration,
+            },
+            hash,
+        }
+    }
+
+    #[test]
+    fn moved_by_hash() {
+        let wanted = Wanted {
+            path: Path::new("/music/old/song.flac"),
+            name: "Song",
+            duration: 200,
+            hash: Some(7),
+        };
+        let candidates = [
+            // Same name and duration, but other content
+            candidate("/music/new/song.flac", Some("Song"), 200, Some(8)),
+            candidate("/music/new/renamed.flac", None, 10, Some(7)),
+        ];
+        assert_eq!(only_match(&wanted, &candidates), Some(1));
+        assert_eq!(only_match(&wanted, &candidates[..1]), None);
+    }
+
+    #[test]
+    fn moved_by_title_or_file_name() {
+        let wanted = Wanted {
+            path: Path::new("/music/old/song.flac"),
+            name: "Song",
+            duration: 200,
+            hash: None,
+        };
+        let by_title = candidate("/music/new/01.flac", Some("song"), 201, None);
+        let by_name = candidate("/music/new/song.flac", None, 199, None);
+        let other_duration = candidate("/music/live/song.flac", Some("Song"), 300, None);
+        assert!(is_match(&wanted, &by_title));
+        assert!(is_match(&wanted, &by_name));
+        assert!(!is_match(&wanted, &other_duration));
+        // Two files match, so it is not clear where the track went
+        assert_eq!(only_match(&wanted, &[by_title, by_name]), None);
+    }
+
+    #[test]
+    fn update_keeps_one_track_per_file() {
+        let dir = test_dir("update");
+        // The library database goes in the data directory
+        std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+        let mut library = Library::try_new().unwrap();
+        let file = dir.join("silence.wav");
+        write_wav(&file);
+        library.add_file(&file).unwrap();
+        let track = library.find_all::<Track>().unwrap().remove(0);
+        let report = Report {
+            changed: vec![(track, 2)],
+            ..Default::default()
+        };
+        update(&mut library, &report).unwrap();
+        update(&mut library, &report).unwrap();
+        assert_eq!(library.find_all::<Track>().unwrap().len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! Lyrics of tracks, from `.lrc` files next to the track or from the tags.
+//!
+//! A `.lrc` file wins over the tags, synchronized SYLT frames over the plain
+//! lyrics tag. Plain lyrics that are in the LRC format are synchronized too.
+
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Result;
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, TaggedFileExt},
+    id3::v2::{Frame, FrameId, SynchronizedTextFrame, TimestampFormat},
+    mpeg::MpegFile,
+    tag::ItemKey,
+};
+
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+    /// Every line has a time
+    pub synced: bool,
+    /// Where the lyrics come from, like the name of the `.lrc` file
+    pub source: String,
+}
+
+pub struct LyricLine {
+    /// When the line starts, `None` for plain lyrics
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+impl Lyrics {
+    /// Index of the line that is sung at `position`
+    pub fn active_line(&self, position: Duration) -> Option<usize> {
+        if !self.synced {
+            return None;
+        }
+        self.lines
+            .iter()
+            .rposition(|line| line.time.is_some_and(|time| time <= position))
+    }
+}
+
+/// The lyrics of `track`, `None` if it has none
+pub fn load(track: &Path) -> Result<Option<Lyrics>> {
+    let lrc = track.with_extension("lrc");
+    if lrc.is_file() {
+        let bytes = fs::read(&lrc)?;
+        let source = lrc
+            .file_name()
+            .map_or(Cow::Borrowed(""), |name| name.to_string_lossy());
+        return Ok(Some(parse_lrc(&String::from_utf8_lossy(&bytes), &source)));
+    }
+    if let Some(lyrics) = synchronized_text(track) {
+        return Ok(Some(lyrics));
+    }
+    let tagged_file = lofty::read_from_path(track)?;
+    let text = tagged_file
+        .tags()
+        .iter()
+        .find_map(|tag| tag.get_string(&ItemKey::Lyrics).map(str::to_string));
+    Ok(text.map(|text| parse_lrc(&text, "Lyrics tag")))
+}
+
+/// The SYLT frame of an mp3 file, only timestamps in milliseconds are supported
+fn synchronized_text(track: &Path) -> Option<Lyrics> {
+    let is_mp3 = track
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"));
+    if !is_mp3 {
+        return None;
+    }
+    let mut file = File::open(track).ok()?;
+    let mpeg_file = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+    let frame = mpeg_file
+        .id3v2()?
+        .get(&FrameId::Valid(Cow::Borrowed("SYLT")))?;
+    let Frame::Binary(binary) = frame else {
+        return None;
+    };
+    let sylt = SynchronizedTextFrame::parse(&binary.data, frame.flags()).ok()?;
+    if sylt.timestamp_format != TimestampFormat::MS {
+        return None;
+    }
+    let mut lines: Vec<LyricLine> = sylt
+        .content
+        .into_iter()
+        .map(|(milliseconds, text)| LyricLine {
+            time: Some(Duration::from_millis(milliseconds.into())),
+            text: text.trim().to_string(),
+        })
+        .collect();
+    lines.sort_by_key(|line| line.time);
+    Some(Lyrics {
+        lines,
+        synced: true,
+        source: "SYLT tag".to_string(),
+    })
+}
+
+/// Parse lyrics in the LRC format, text without timestamps becomes plain lyrics
+pub fn parse_lrc(content: &str, source: &str) -> Lyrics {
+    // In milliseconds, a positive offset shows the lines earlier
+    let mut offset: i64 = 0;
+    let mut timed = vec![];
+    let mut plain = vec![];
+    for line in content.lines() {
+        let mut rest = line.trim().trim_start_matches('\u{feff}');
+        let mut times = vec![];
+        let mut tagged = false;
+        // A line can have more than one timestamp, `[00:12.00][01:30.00]text`
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|tag| tag.split_once(']')) {
+            tagged = true;
+            if let Some(time) = parse_time(tag) {
+                times.push(time);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            }
+            rest = after;
+        }
+        if times.is_empty() {
+            // Lines with only tags are metadata like `[ar:Artist]`
+            if !tagged {
+                plain.push(rest.to_string());
+            }
+            continue;
+        }
+        for time in times {
+            timed.push((time, rest.trim().to_string()));
+        }
+    }
+
+    if timed.is_empty() {
+        return Lyrics {
+            lines: plain
+                .into_iter()
+                .map(|text| LyricLine { time: None, text })
+                .collect(),
+            synced: false,
+            source: source.to_string(),
+        };
+    }
+    timed.sort_by_key(|(time, _)| *time);
+    Lyrics {
+        lines: timed
+            .into_iter()
+            .map(|(time, text)| LyricLine {
+                time: Some(Duration::from_millis((time - offset).max(0) as u64)),
+                text,
+            })
+            .collect(),
+        synced: true,
+        source: source.to_string(),
+    }
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss:xx` in milliseconds
+fn parse_time(tag: &str) -> Option<i64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+    let (seconds, fraction) = seconds.split_once(['.', ':']).unwrap_or((seconds, "0"));
+    let seconds: i64 = seconds.parse().ok()?;
+    if !fraction.chars().all(|char| char.is_ascii_digit()) || fraction.is_empty() {
+        return None;
+    }
+    // `.5` is half a second and `.05` five hundredths, use at most milliseconds
+    let digits = &fraction[..fraction.len().min(3)];
+    let fraction: i64 = digits.parse().ok()?;
+    let milliseconds = fraction * 10_i64.pow(3 - digits.len() as u32);
+    Some((minutes * 60 + seconds) * 1000 + milliseconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lyrics: &Lyrics) -> Vec<Option<u64>> {
+        lyrics
+            .lines
+            .iter()
+            .map(|line| line.time.map(|time| time.as_millis() as u64))
+            .collect()
+    }
+
+    #[test]
+    fn synchronized_lines() {
+        let lyrics = parse_lrc(
+            "[ar:Artist]\n\
+             [ti:Title]\n\
+             [00:12.50]First\n\
+             [00:05.1][01:00:05]Chorus\n",
+            "test.lrc",
+        );
+        assert!(lyrics.synced);
+        assert_eq!(lyrics.source, "test.lrc");
+        assert_eq!(
+            times(&lyrics),
+            vec![Some(5_100), Some(12_500), Some(60_050)]
+        );
+        let texts: Vec<&str> = lyrics.lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["Chorus", "First", "Chorus"]);
+    }
+
+    #[test]
+    fn offset_moves_lines_earlier() {
+        let lyrics = parse_lrc("[offset:500]\n[00:01.00]a\n[00:00.20]b\n", "");
+        assert_eq!(times(&lyrics), vec![Some(0), Some(500)]);
+    }
+
+    #[test]
+    fn plain_lyrics() {
+        let lyrics = parse_lrc("First line\n[ar:Artist]\nSecond line\n", "");
+        assert!(!lyrics.synced);
+        assert_eq!(times(&lyrics), vec![None, None]);
+        assert_eq!(lyrics.active_line(Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn active_line() {
+        let lyrics = parse_lrc("[00:01.00]a\n[00:02.00]b\n", "");
+        assert_eq!(lyrics.active_line(Duration::from_millis(500)), None);
+        assert_eq!(lyrics.active_line(Duration::from_millis(1_999)), Some(0));
+        assert_eq!(lyrics.active_line(Duration::from_secs(2)), Some(1));
+    }
+}
@@ -1,44 +1,67 @@
 #[cfg(not(debug_assertions))]
 use std::env;
+use std::path::Path;
 #[cfg(debug_assertions)]
 use std::path::PathBuf;
-use std::sync::mpsc;
-use std::time::Duration;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{env::args_os, os::unix::process::CommandExt};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{SampleRate, SupportedStreamConfig};
-use log::error;
-use rmusic::playback_loop::playback_loop;
+use anyhow::{Context, Result};
 
-use rmusic::playback::PlaybackDaemon;
-
-use anyhow::Result;
-
-use ratatui::crossterm::event::{self, KeyCode, KeyEventKind};
+use clap::Parser;
+use log::{error, warn};
+use ratatui::crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::DefaultTerminal;
+use rmusic::database::Library;
+use rmusic_tui::settings::Settings;
+use transport::{ArcTransport, Transport};
 use tui_logger::{
     init_logger, set_default_level, set_log_file, TuiLoggerFile, TuiLoggerLevelOutput,
 };
+use volume::Volume;
 
+mod audio;
 mod cli;
+mod control;
+mod database;
+mod history;
+mod library_check;
+mod lyrics;
+#[cfg(feature = "mpris")]
+mod mpris;
+mod playlist_file;
+mod playlists;
+mod scan;
+mod session;
+mod transport;
 mod ui;
+mod volume;
+mod watcher;
 
 const FRAMERATE: u64 = 144;
 /// milliseconds per frame
 const MILL_FPS: u64 = 1 / (FRAMERATE * 1000);
 
-macro_rules! exit_on_error {
-    ($expr:expr) => {
-        match $expr {
-            std::result::Result::Ok(val) => val,
-            std::result::Result::Err(err) => {
-                error!("Exiting because of {}", err);
-                std::process::exit(1);
-            }
-        }
-    };
-}
-
 fn main() -> Result<()> {
+    let cli = cli::Cli::parse();
+    let socket = cli.socket.clone().unwrap_or_else(control::socket_path);
+    if let Some(command) = &cli.command {
+        return command.run(&socket, cli.config.as_deref());
+    }
+    if cli.list_devices {
+        return audio::list_devices();
+    }
+    // Load settings before the terminal is taken over, so errors are visible
+    let mut settings = Settings::load(cli.config.as_deref())?;
+    cli.override_settings(&mut settings);
+
     let mut _quiet = false;
     init_logger(log::LevelFilter::Debug)?;
     set_default_level(log::LevelFilter::Trace);
@@ -57,72 +80,209 @@ fn main() -> Result<()> {
         .output_separator(':');
     set_log_file(file_options);
 
-    let app_result = run();
+    let restore = !cli.no_restore;
+    if cli.daemon {
+        return if cli.foreground {
+            run_daemon(settings, &socket, restore)
+        } else {
+            spawn_daemon()
+        };
+    }
+
+    let app_result = run(settings, &socket, restore);
+    let _ = execute!(std::io::stdout(), DisableMouseCapture);
     ratatui::restore();
     app_result
 }
 
-fn run() -> Result<()> {
-    // Audio output
-    let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .expect("No output device available"); // Add log
-
-    let mut supported_configs_range = device
-        .supported_output_configs()
-        .expect("error while querying configs");
-    let config = supported_configs_range.next().unwrap();
-    let sample_rate = if config.try_with_sample_rate(SampleRate(48000)).is_some() {
-        SampleRate(48000)
-    } else {
-        config.max_sample_rate()
+/// Start the daemon again in the background, in its own process group
+/// so it is not stopped together with the terminal
+fn spawn_daemon() -> Result<()> {
+    let child = Command::new(std::env::current_exe()?)
+        .args(args_os().skip(1))
+        .arg("--foreground")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Could not start the daemon")?;
+    println!("Started daemon with pid {}", child.id());
+    Ok(())
+}
+
+/// Play without a terminal, controlled through the control socket
+fn run_daemon(settings: Settings, socket: &Path, restore: bool) -> Result<()> {
+    let listener = control::bind(socket)?;
+    let volume = Volume::new(&settings.playback);
+    let startup_gain = volume.gain(f32::from(settings.playback.volume) / 100.0);
+    let (_stream, tx, playback_context) = audio::start(&settings.audio, startup_gain)?;
+    let transport = Transport::start(tx, playback_context);
+    if restore {
+        session::restore(&transport);
+    }
+    let quit = Arc::new(AtomicBool::new(false));
+    start_mpris(&transport, &quit, volume);
+    let _watcher = watcher::start(&settings.library.watch);
+
+    let handler = control::Handler {
+        transport: transport.clone(),
+        library: Library::try_new()?,
+        quit: quit.clone(),
+        volume,
     };
-    let supported_config = SupportedStreamConfig::new(
-        2,
-        sample_rate,
-        *config.buffer_size(),
-        cpal::SampleFormat::F32,
-    );
+    let socket = socket.to_path_buf();
+    thread::spawn(move || {
+        if let Err(err) = control::serve(listener, &socket, handler) {
+            error!("Control socket stopped: {err:#}");
+        }
+    });
 
-    // playback Daemon
-    let mut playback_daemon = PlaybackDaemon::new(sample_rate.0 as usize);
-    playback_daemon.set_volume(0.2);
+    let mut last_save = Instant::now();
+    while !quit.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(100));
+        if last_save.elapsed() >= session::SAVE_INTERVAL {
+            session::save(&transport);
+            last_save = Instant::now();
+        }
+    }
+    session::save(&transport);
+    Ok(())
+}
 
-    // Thread communication
-    let (tx, rx) = mpsc::channel();
+/// Expose the player on D-Bus, playback works fine without it
+#[cfg(feature = "mpris")]
+fn start_mpris(transport: &ArcTransport, quit: &Arc<AtomicBool>, volume: Volume) {
+    if let Err(err) = mpris::start(transport.clone(), quit.clone(), volume) {
+        warn!("MPRIS disabled: {err:#}");
+    }
+}
 
-    // ui
-    let mut ui = ui::UI::new(playback_daemon.get_playback_context())?;
+#[cfg(not(feature = "mpris"))]
+fn start_mpris(_transport: &ArcTransport, _quit: &Arc<AtomicBool>, _volume: Volume) {}
 
-    // Stream setup
-    let err_fn = |err| error!("an error occurred on the output audio stream: {:?}", err);
-    let decoder = move |data: &mut [f32], callback: &_| {
-        playback_loop(data, callback, &mut playback_daemon, &rx)
-    };
-    let stream =
-        exit_on_error!(device.build_output_stream(&supported_config.into(), decoder, err_fn, None));
-    exit_on_error!(stream.play());
+fn run(settings: Settings, socket: &Path, restore: bool) -> Result<()> {
+    // A daemon is playing already, control it instead of starting a second player
+    if control::is_running(socket) {
+        return run_remote(settings, socket);
+    }
+
+    // Audio output, playback stops when the stream is dropped
+    let volume = Volume::new(&settings.playback);
+    let startup_gain = volume.gain(f32::from(settings.playback.volume) / 100.0);
+    let (_stream, tx, playback_context) = audio::start(&settings.audio, startup_gain)?;
+    let transport = Transport::start(tx, playback_context);
+    if restore {
+        session::restore(&transport);
+    }
+
+    // Control socket, so the player can be controlled from the command line
+    let quit = Arc::new(AtomicBool::new(false));
+    let mut bound = false;
+    match control::bind(socket) {
+        Ok(listener) => {
+            bound = true;
+            let handler = control::Handler {
+                transport: transport.clone(),
+                library: Library::try_new()?,
+                quit: quit.clone(),
+                volume,
+            };
+            let socket = socket.to_path_buf();
+            thread::spawn(move || {
+                if let Err(err) = control::serve(listener, &socket, handler) {
+                    error!("Control socket stopped: {err:#}");
+                }
+            });
+        }
+        Err(err) => warn!("Control socket disabled: {err:#}"),
+    }
+    start_mpris(&transport, &quit, volume);
+
+    // ui
+    let mut ui = ui::UI::new(transport.clone(), settings)?;
 
     let mut terminal = ratatui::init();
+    execute!(std::io::stdout(), EnableMouseCapture)?;
+    ui.detect_graphics();
     terminal.clear()?;
+    let result = ui_loop(&mut terminal, &mut ui, &transport, &quit);
+    session::save(&transport);
+    if let Err(err) = ui.save_state() {
+        warn!("Could not save the UI state: {err:#}");
+    }
+    // The control thread is still waiting for clients, so it does not remove the socket itself
+    if bound && !quit.load(Ordering::Relaxed) {
+        if let Err(err) = std::fs::remove_file(socket) {
+            warn!("Could not remove {}: {err}", socket.display());
+        }
+    }
+    result
+}
+
+/// Control the player that owns the socket, until `q` is pressed
+fn run_remote(settings: Settings, socket: &Path) -> Result<()> {
+    let mut remote = ui::RemoteUI::new(socket.to_path_buf(), settings)?;
+    let mut terminal = ratatui::init();
     loop {
-        terminal.draw(|frame| frame.render_widget(&mut ui, frame.area()))?;
+        remote.update();
+        terminal.draw(|frame| frame.render_widget(&remote, frame.area()))?;
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        while event::poll(Duration::from_secs(0))? {
+            let event = event::read()?;
+            if let event::Event::Key(key) = event {
+                if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
+                    return Ok(());
+                }
+            }
+            remote.handle_input(&event);
+        }
+    }
+}
+
+fn ui_loop(
+    terminal: &mut DefaultTerminal,
+    ui: &mut ui::UI,
+    transport: &Transport,
+    quit: &AtomicBool,
+) -> Result<()> {
+    let mut last_save = Instant::now();
+    while !quit.load(Ordering::Relaxed) {
+        ui.update();
+        let area = terminal
+            .draw(|frame| frame.render_widget(&mut *ui, frame.area()))?
+            .area;
+        if last_save.elapsed() >= session::SAVE_INTERVAL {
+            session::save(transport);
+            last_save = Instant::now();
+        }
 
         // Check if we have to handle input
         if event::poll(Duration::from_millis(MILL_FPS))? {
             // Handel all input in this frame, not just one
             while event::poll(Duration::from_secs(0))? {
                 let event = event::read()?;
+                if let event::Event::Mouse(mouse) = event {
+                    if let Some(action) = ui.handle_mouse(mouse, area) {
+                        let _ = transport.send(action);
+                    }
+                    continue;
+                }
                 if let event::Event::Key(key) = event {
-                    if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
+                    if key.kind == KeyEventKind::Press
+                        && key.code == KeyCode::Char('q')
+                        && !ui.is_typing()
+                    {
                         return Ok(());
                     }
                 }
                 if let Some(action) = ui.handle_input(&event)? {
-                    let _ = tx.send(action);
+                    let _ = transport.send(action);
                 }
             }
         }
     }
+    Ok(())
 }
@@ -0,0 +1,719 @@
+//! MPRIS2 D-Bus interface, so desktops and tools like `playerctl` can see and control playback.
+//!
+//! The player registers on the session bus from `DBUS_SESSION_BUS_ADDRESS`,
+//! so it can be tried without a desktop on a private bus:
+//!
+//! ```text
+//! $ export DBUS_SESSION_BUS_ADDRESS=$(dbus-daemon --session --fork --print-address)
+//! $ rmusic_tui --daemon
+//! $ dbus-send --session --print-reply --dest=org.mpris.MediaPlayer2.rmusic_tui \
+//!     /org/mpris/MediaPlayer2 org.mpris.MediaPlayer2.Player.PlayPause
+//! $ playerctl --player=rmusic_tui metadata
+//! ```
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use lofty::{file::TaggedFileExt, tag::Accessor};
+use log::{info, warn};
+use rmusic::{
+    playback::playback_context::ArcPlaybackContext, playback_loop::PlaybackAction,
+    queue::ShuffleType,
+};
+use zbus::{
+    blocking::{connection, Connection},
+    fdo, interface,
+    zvariant::{ObjectPath, OwnedValue, Value},
+    SignalContext,
+};
+
+use crate::{
+    transport::{length_ms, played_ms, seek_action, ArcTransport, RepeatMode},
+    volume::Volume,
+};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.rmusic_tui";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// There is no track list, so the current track always has the same id
+const TRACK_ID: &str = "/org/rmusic_tui/track/current";
+/// How often the playback state is checked for changes to signal
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const MICROS_PER_MS: i64 = 1_000;
+/// A position that is further than this from where it should be has been seeked
+const SEEK_MARGIN_MS: u64 = 1_000;
+/// Number of shuffle types, cycling this often goes through all of them
+const SHUFFLE_TYPES: usize = 3;
+
+/// Register the player on the session bus, signals changes until `quit` is set
+pub fn start(transport: ArcTransport, quit: Arc<AtomicBool>, volume: Volume) -> Result<()> {
+    let playback_context = transport.playback_context().clone();
+    let connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Root { quit: quit.clone() })?
+        .serve_at(
+            OBJECT_PATH,
+            Player {
+                transport,
+                playback_context,
+                volume,
+                tags: Mutex::new(None),
+            },
+        )?
+        .build()?;
+    info!("Registered {BUS_NAME} on the session bus");
+
+    thread::spawn(move || {
+        if let Err(err) = signal_changes(&connection, &quit) {
+            warn!("Stopped sending MPRIS signals: {err}");
+        }
+    });
+    Ok(())
+}
+
+/// Emit `PropertiesChanged` when the playback state changes, and `Seeked` when the
+/// position jumps, whichever front end seeked
+fn signal_changes(connection: &Connection, quit: &AtomicBool) -> Result<()> {
+    let player_ref = connection
+        .object_server()
+        .interface::<_, Player>(OBJECT_PATH)?;
+    let context = player_ref.signal_context();
+    let mut last_state: Option<State> = None;
+    let mut last_position = None;
+    while !quit.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+        let player = player_ref.get();
+        let state = player.state();
+        let position = Position {
+            played_ms: played_ms(&player.playback_context),
+            at: Instant::now(),
+        };
+        let same_track = last_state
+            .as_ref()
+            .is_some_and(|last_state| last_state.track == state.track);
+        if let Some(last_position) = last_position.filter(|_| same_track) {
+            let playing = last_state.as_ref().is_some_and(|state| state.playing);
+            if position.jumped_from(&last_position, playing) {
+                let position = position.played_ms as i64 * MICROS_PER_MS;
+                zbus::block_on(Player::seeked(context, position))?;
+            }
+        }
+        last_position = Some(position);
+        if last_state.as_ref() == Some(&state) {
+            continue;
+        }
+        zbus::block_on(async {
+            player.playback_status_changed(context).await?;
+            player.metadata_changed(context).await?;
+            player.volume_changed(context).await?;
+            player.shuffle_changed(context).await?;
+            player.loop_status_changed(context).await
+        })?;
+        last_state = Some(state);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    played_ms: u64,
+    at: Instant,
+}
+
+impl Position {
+    /// Returns true if the position is not where playing on from `last` would have
+    /// brought it
+    fn jumped_from(&self, last: &Position, playing: bool) -> bool {
+        let expected = if playing {
+            last.played_ms + self.at.duration_since(last.at).as_millis() as u64
+        } else {
+            last.played_ms
+        };
+        self.played_ms.abs_diff(expected) > SEEK_MARGIN_MS
+    }
+}
+
+struct Root {
+    quit: Arc<AtomicBool>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {
+        self.quit.store(true, Ordering::Relaxed);
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "rmusic".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+struct Player {
+    transport: ArcTransport,
+    playback_context: ArcPlaybackContext,
+    volume: Volume,
+    /// The tags of the last track metadata was asked for
+    tags: Mutex<Option<(PathBuf, Tags)>>,
+}
+
+#[derive(Clone, Default)]
+struct Tags {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+}
+
+impl Tags {
+    fn read(path: &Path) -> Tags {
+        let Ok(tagged_file) = lofty::read_from_path(path) else {
+            return Tags::default();
+        };
+        let Some(tag) = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag())
+        else {
+            return Tags::default();
+        };
+        Tags {
+            title: tag.title().map(|title| title.to_string()),
+            artist: tag.artist().map(|artist| artist.to_string()),
+            album: tag.album().map(|album| album.to_string()),
+        }
+    }
+}
+
+/// The properties that are signaled when they change
+#[derive(PartialEq)]
+struct State {
+    playing: bool,
+    track: Option<String>,
+    volume: u8,
+    shuffle: bool,
+    loop_status: &'static str,
+}
+
+impl Player {
+    fn send(&self, action: PlaybackAction) -> fdo::Result<()> {
+        self.transport
+            .send(action)
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    fn seek_to(&self, target_ms: u64) -> fdo::Result<()> {
+        self.send(seek_action(&self.playback_context, target_ms))
+    }
+
+    fn tags(&self, path: &Path) -> Tags {
+        let mut cached = self.tags.lock().unwrap_or_else(|err| err.into_inner());
+        match &*cached {
+            Some((cached_path, tags)) if cached_path == path => tags.clone(),
+            _ => {
+                let tags = Tags::read(path);
+                *cached = Some((path.to_path_buf(), tags.clone()));
+                tags
+            }
+        }
+    }
+
+    fn state(&self) -> State {
+        State {
+            playing: self.playback_context.is_playing(),
+            track: self
+                .playback_context
+                .lock_queue()
+                .current_track()
+                .as_ref()
+                .map(|track| track.display().to_string()),
+            volume: self.volume.percent(&self.transport),
+            shuffle: self.shuffle(),
+            loop_status: self.loop_status(),
+        }
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.transport
+            .next()
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.transport
+            .previous()
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        match toggle_to(self.playback_context.is_playing(), false) {
+            Some(action) => self.send(action),
+            None => Ok(()),
+        }
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.send(PlaybackAction::PlayPause)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.pause()
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        if let Some(action) = toggle_to(self.playback_context.is_playing(), true) {
+            self.send(action)?;
+        }
+        Ok(())
+    }
+
+    /// Seek relative to the current position, in microseconds, past the end goes to the next track
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        match seek_target(
+            played_ms(&self.playback_context),
+            length_ms(&self.playback_context),
+            offset,
+        ) {
+            Some(target) => self.seek_to(target),
+            None => self.next(),
+        }
+    }
+
+    /// Seek to a position in microseconds, ignored if the track is no longer playing
+    /// or the position is outside of it
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        match position_target(
+            track_id.as_str(),
+            position,
+            length_ms(&self.playback_context),
+        ) {
+            Some(target) => self.seek_to(target),
+            None => Ok(()),
+        }
+    }
+
+    #[zbus(signal)]
+    async fn seeked(context: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "Opening URIs is not supported".to_string(),
+        ))
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &'static str {
+        if self.playback_context.lock_queue().current_track().is_none() {
+            "Stopped"
+        } else if self.playback_context.is_playing() {
+            "Playing"
+        } else {
+            "Paused"
+        }
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &'static str {
+        loop_status(self.transport.repeat())
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, loop_status: String) -> fdo::Result<()> {
+        let repeat = repeat_mode(&loop_status)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown loop status {loop_status}")))?;
+        self.transport.set_repeat(repeat);
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.playback_context
+            .lock_queue()
+            .queue_options
+            .shuffle_type
+            != ShuffleType::None
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, shuffle: bool) {
+        let mut queue = self.playback_context.lock_queue();
+        // Cycle until we are in, or out of, a shuffle mode
+        for _ in 0..SHUFFLE_TYPES {
+            if (queue.queue_options.shuffle_type != ShuffleType::None) == shuffle {
+                break;
+            }
+            queue.cycle_shuffle();
+        }
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> fdo::Result<HashMap<String, OwnedValue>> {
+        let mut metadata = HashMap::new();
+        let Some(path) = self.playback_context.lock_queue().current_track().clone() else {
+            return Ok(metadata);
+        };
+        metadata.insert(
+            "mpris:trackid".to_string(),
+            owned(ObjectPath::from_static_str_unchecked(TRACK_ID))?,
+        );
+        metadata.insert(
+            "mpris:length".to_string(),
+            owned(length_ms(&self.playback_context) as i64 * MICROS_PER_MS)?,
+        );
+        metadata.insert("xesam:url".to_string(), owned(file_url(&path))?);
+        let tags = self.tags(&path);
+        let title = tags.title.or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        });
+        if let Some(title) = title {
+            metadata.insert("xesam:title".to_string(), owned(title)?);
+        }
+        // Artists are a list in MPRIS
+        if let Some(artist) = tags.artist {
+            metadata.insert("xesam:artist".to_string(), owned(vec![artist])?);
+        }
+        if let Some(album) = tags.album {
+            metadata.insert("xesam:album".to_string(), owned(album)?);
+        }
+        Ok(metadata)
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        f64::from(self.volume.percent(&self.transport)) / 100.0
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) -> fdo::Result<()> {
+        let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+        self.send(self.volume.set(percent, &self.transport))
+    }
+
+    /// Position in microseconds, clients poll this so it does not signal changes
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        played_ms(&self.playback_context) as i64 * MICROS_PER_MS
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn owned<'a>(value: impl Into<Value<'a>>) -> fdo::Result<OwnedValue> {
+    OwnedValue::try_from(value.into()).map_err(|err| fdo::Error::Failed(err.to_string()))
+}
+
+/// The `file://` URL of a path, with everything but unreserved characters and `/` escaped
+fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(char::from(*byte))
+            }
+            _ => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    url
+}
+
+/// The action that plays or pauses, `None` if playback already is that way
+fn toggle_to(playing: bool, play: bool) -> Option<PlaybackAction> {
+    (playing != play).then_some(PlaybackAction::PlayPause)
+}
+
+/// Milliseconds to seek to for an offset in microseconds, `None` past the end of the track
+fn seek_target(played_ms: u64, length_ms: u64, offset: i64) -> Option<u64> {
+    let target = (played_ms as i64 + offset / MICROS_PER_MS).max(0) as u64;
+    (target <= length_ms).then_some(target)
+}
+
+/// Milliseconds to seek to for a position in microseconds, `None` if the track
+/// is no longer playing or the position is outside of it
+fn position_target(track_id: &str, position: i64, length_ms: u64) -> Option<u64> {
+    let target = (position / MICROS_PER_MS) as u64;
+    (track_id == TRACK_ID && position >= 0 && target <= length_ms).then_some(target)
+}
+
+fn loop_status(repeat: RepeatMode) -> &'static str {
+    match repeat {
+        RepeatMode::Off => "None",
+        RepeatMode::All => "Playlist",
+        RepeatMode::One => "Track",
+    }
+}
+
+fn repeat_mode(loop_status: &str) -> Option<RepeatMode> {
+    match loop_status {
+        "None" => Some(RepeatMode::Off),
+        "Playlist" => Some(RepeatMode::All),
+        "Track" => Some(RepeatMode::One),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+    };
+
+    use zbus::{
+        blocking::{fdo::PropertiesProxy, MessageIterator},
+        message,
+        names::InterfaceName,
+        MatchRule,
+    };
+
+    use super::*;
+
+    /// A session bus of its own, stopped when dropped
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        /// `None` without `dbus-daemon`, the test is skipped then
+        fn start() -> Option<Bus> {
+            let Ok(mut daemon) = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+            else {
+                eprintln!("dbus-daemon was not found, skipping the test");
+                return None;
+            };
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Some(Bus {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> connection::Builder<'static> {
+            connection::Builder::address(self.address.as_str()).unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[test]
+    fn root_on_the_bus() {
+        let Some(bus) = Bus::start() else {
+            return;
+        };
+        let quit = Arc::new(AtomicBool::new(false));
+        let _server = bus
+            .connect()
+            .name(BUS_NAME)
+            .unwrap()
+            .serve_at(OBJECT_PATH, Root { quit: quit.clone() })
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = bus.connect().build().unwrap();
+
+        let properties = PropertiesProxy::builder(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .build()
+            .unwrap();
+        let identity = properties
+            .get(
+                InterfaceName::from_static_str_unchecked("org.mpris.MediaPlayer2"),
+                "Identity",
+            )
+            .unwrap();
+        assert_eq!(String::try_from(identity).unwrap(), "rmusic");
+
+        client
+            .call_method(
+                Some(BUS_NAME),
+                OBJECT_PATH,
+                Some("org.mpris.MediaPlayer2"),
+                "Quit",
+                &(),
+            )
+            .unwrap();
+        assert!(quit.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn seeked_signal() {
+        let Some(bus) = Bus::start() else {
+            return;
+        };
+        let server = bus.connect().name(BUS_NAME).unwrap().build().unwrap();
+        let client = bus.connect().build().unwrap();
+        let rule = MatchRule::builder()
+            .msg_type(message::Type::Signal)
+            .interface("org.mpris.MediaPlayer2.Player")
+            .unwrap()
+            .member("Seeked")
+            .unwrap()
+            .build();
+        let mut signals = MessageIterator::for_match_rule(rule, &client, None).unwrap();
+
+        let context = SignalContext::new(server.inner(), OBJECT_PATH).unwrap();
+        zbus::block_on(Player::seeked(&context, 1_500_000)).unwrap();
+
+        let signal = signals.next().unwrap().unwrap();
+        let position: i64 = signal.body().deserialize().unwrap();
+        assert_eq!(position, 1_500_000);
+    }
+
+    #[test]
+    fn file_urls() {
+        assert_eq!(
+            file_url(Path::new("/music/A b/#1 100%.flac")),
+            "file:///music/A%20b/%231%20100%25.flac"
+        );
+        assert_eq!(
+            file_url(Path::new("/music/Björk.mp3")),
+            "file:///music/Bj%C3%B6rk.mp3"
+        );
+    }
+
+    #[test]
+    fn play_and_pause() {
+        assert!(matches!(
+            toggle_to(false, true),
+            Some(PlaybackAction::PlayPause)
+        ));
+        assert!(matches!(
+            toggle_to(true, false),
+            Some(PlaybackAction::PlayPause)
+        ));
+        assert!(toggle_to(true, true).is_none());
+        assert!(toggle_to(false, false).is_none());
+    }
+
+    #[test]
+    fn seek_targets() {
+        assert_eq!(seek_target(10_000, 60_000, 5_000_000), Some(15_000));
+        assert_eq!(seek_target(10_000, 60_000, -20_000_000), Some(0));
+        // Past the end goes to the next track
+        assert_eq!(seek_target(10_000, 60_000, 55_000_000), None);
+
+        assert_eq!(position_target(TRACK_ID, 30_000_000, 60_000), Some(30_000));
+        assert_eq!(position_target("/other/track", 30_000_000, 60_000), None);
+        assert_eq!(position_target(TRACK_ID, -1, 60_000), None);
+        assert_eq!(position_target(TRACK_ID, 61_000_000, 60_000), None);
+    }
+
+    #[test]
+    fn loop_statuses() {
+        for repeat in [RepeatMode::Off, RepeatMode::All, RepeatMode::One] {
+            assert_eq!(repeat_mode(loop_status(repeat)), Some(repeat));
+        }
+        assert_eq!(repeat_mode("Shuffle"), None);
+    }
+
+    #[test]
+    fn position_jumps() {
+        let last = Position {
+            played_ms: 10_000,
+            at: Instant::now(),
+        };
+        let later = |played_ms, elapsed_ms| Position {
+            played_ms,
+            at: last.at + Duration::from_millis(elapsed_ms),
+        };
+        assert!(!later(10_500, 500).jumped_from(&last, true));
+        assert!(later(40_000, 500).jumped_from(&last, true));
+        assert!(later(5_000, 500).jumped_from(&last, true));
+        assert!(!later(10_000, 500).jumped_from(&last, false));
+        assert!(later(12_000, 500).jumped_from(&last, false));
+    }
+}
@@ -0,0 +1,423 @@
+//! Reading and writing M3U, M3U8 and PLS playlist files.
+//!
+//! Entries are resolved against the library by path first, and by title
+//! (and duration, when the file has one) for entries whose file moved.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    io::Write as _,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rmusic::{database::Library, models::Track};
+use rmusic_tui::settings::data_dir;
+
+use crate::playlists::{self, Playlist};
+
+/// Difference in seconds for a duration in a playlist file to still match a track
+const DURATION_MARGIN: i64 = 2;
+/// Directory in the data directory for exports that are not given an absolute path
+const EXPORT_DIR: &str = "playlists";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    M3u,
+    Pls,
+}
+
+impl Format {
+    /// The format of a playlist file, based on its extension
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "pls" => Some(Format::Pls),
+            _ => None,
+        }
+    }
+}
+
+/// An entry in a playlist file
+pub struct Entry {
+    /// Line of the location in the file, starting at 1
+    pub line: usize,
+    pub location: String,
+    pub title: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<i64>,
+}
+
+pub struct ImportReport {
+    /// `None` when no entry was found, no empty playlist is made for those
+    pub playlist: Option<Playlist>,
+    pub imported: usize,
+    pub unresolved: Vec<Entry>,
+}
+
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let Some(format) = Format::from_path(path) else {
+        bail!("{} is not an m3u, m3u8 or pls file", path.display());
+    };
+    let bytes = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    // Plain .m3u files are often latin-1, show those characters as replacements
+    let content = String::from_utf8_lossy(&bytes);
+    Ok(match format {
+        Format::M3u => parse_m3u(&content),
+        Format::Pls => parse_pls(&content),
+    })
+}
+
+pub fn parse_m3u(content: &str) -> Vec<Entry> {
+    let mut entries = vec![];
+    let mut info: Option<(Option<i64>, String)> = None;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<seconds>,<artist - title>
+            let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let duration = duration.trim().parse().ok().filter(|seconds| *seconds >= 0);
+            info = Some((duration, title.trim().to_string()));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration, title) = info.take().unwrap_or_default();
+            entries.push(Entry {
+                line: index + 1,
+                location: line.to_string(),
+                title: Some(title).filter(|title| !title.is_empty()),
+                duration,
+            });
+        }
+    }
+    entries
+}
+
+pub fn parse_pls(content: &str) -> Vec<Entry> {
+    let mut entries: Vec<(usize, Entry)> = vec![];
+    let mut titles = HashMap::new();
+    let mut durations = HashMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let numbered = |prefix: &str| key.strip_prefix(prefix)?.parse::<usize>().ok();
+        if let Some(number) = numbered("file") {
+            entries.push((
+                number,
+                Entry {
+                    line: index + 1,
+                    location: value.to_string(),
+                    title: None,
+                    duration: None,
+                },
+            ));
+        } else if let Some(number) = numbered("title") {
+            titles.insert(number, value.to_string());
+        } else if let Some(number) = numbered("length") {
+            // -1 is used for streams
+            if let Ok(seconds) = value.parse::<i64>() {
+                durations.insert(number, seconds);
+            }
+        }
+    }
+    entries.sort_by_key(|(number, _)| *number);
+    entries
+        .into_iter()
+        .map(|(number, mut entry)| {
+            entry.title = titles.remove(&number);
+            entry.duration = durations.remove(&number).filter(|seconds| *seconds >= 0);
+            entry
+        })
+        .collect()
+}
+
+/// Import a playlist file as a new playlist named after the file, with a number
+/// added when there already is a playlist with that name
+pub fn import(path: &Path, library: &mut Library) -> Result<ImportReport> {
+    let entries = read(path)?;
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let tracks = library.find_all::<Track>()?;
+    let by_path: HashMap<PathBuf, &Track> = tracks
+        .iter()
+        .map(|track| (PathBuf::from(&track.path), track))
+        .collect();
+
+    let mut resolved = vec![];
+    let mut unresolved = vec![];
+    for entry in entries {
+        let track = entry_path(&entry.location, directory)
+            .and_then(|path| {
+                by_path
+                    .get(&path)
+                    .or_else(|| by_path.get(&path.canonicalize().ok()?))
+                    .copied()
+            })
+            .or_else(|| find_by_title(&entry, &tracks));
+        match track {
+            Some(track) => resolved.push(track.clone()),
+            None => unresolved.push(entry),
+        }
+    }
+
+    if resolved.is_empty() {
+        return Ok(ImportReport {
+            playlist: None,
+            imported: 0,
+            unresolved,
+        });
+    }
+    let name = path
+        .file_stem()
+        .map_or("Imported".into(), |stem| stem.to_string_lossy());
+    let name = playlists::unique_name(library, &name)?;
+    let playlist = playlists::create(library, &name)?;
+    playlists::set_tracks(library, &playlist, &resolved)?;
+    Ok(ImportReport {
+        playlist: Some(playlist),
+        imported: resolved.len(),
+        unresolved,
+    })
+}
+
+/// Path of an entry, relative locations are relative to the playlist file
+fn entry_path(location: &str, directory: &Path) -> Option<PathBuf> {
+    let decoded;
+    let location = match location.strip_prefix("file://") {
+        // `file://localhost/music` is the same as `file:///music`
+        Some(path) => {
+            decoded = percent_decode(path.strip_prefix("localhost").unwrap_or(path));
+            decoded.as_str()
+        }
+        // Other URLs are streams, they can not be in the library
+        None if location.contains("://") => return None,
+        None => location,
+    };
+    let path = PathBuf::from(location.replace('\\', "/"));
+    Some(if path.is_absolute() {
+        path
+    } else {
+        directory.join(path)
+    })
+}
+
+/// Decode the `%20` escapes of a URI, invalid escapes are kept as they are
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| bytes.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Find a track by the title of the entry, the duration breaks ties
+fn find_by_title<'a>(entry: &Entry, tracks: &'a [Track]) -> Option<&'a Track> {
+    let title = entry.title.as_deref()?;
+    // Titles are usually `Artist - Title`
+    let name = title.rsplit_once(" - ").map_or(title, |(_, name)| name);
+    let candidates: Vec<&Track> = tracks
+        .iter()
+        .filter(|track| {
+            track.name.eq_ignore_ascii_case(name) || track.name.eq_ignore_ascii_case(title)
+        })
+        .collect();
+    match entry.duration {
+        Some(duration) => candidates
+            .into_iter()
+            .find(|track| (track.duration as i64 - duration).abs() <= DURATION_MARGIN),
+        None if candidates.len() == 1 => candidates.first().copied(),
+        None => None,
+    }
+}
+
+/// Where a playlist file is exported to, relative paths are in the data directory
+pub fn export_path(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    let directory = data_dir().ok_or_else(|| {
+        anyhow!(
+            "No data directory to put {} in, use an absolute path",
+            path.display()
+        )
+    })?;
+    Ok(directory.join(EXPORT_DIR).join(path))
+}
+
+/// Write tracks to a playlist file, the format is taken from the extension.
+/// Relative paths are relative to the directory of the playlist file.
+/// An existing file is only replaced with `overwrite`.
+pub fn export(path: &Path, tracks: &[Track], relative: bool, overwrite: bool) -> Result<()> {
+    let Some(format) = Format::from_path(path) else {
+        bail!("{} does not end in .m3u, .m3u8 or .pls", path.display());
+    };
+    let directory = std::path::absolute(path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let location = |track: &Track| {
+        let track_path = Path::new(&track.path);
+        if relative {
+            relative_path(&directory, track_path).display().to_string()
+        } else {
+            track_path.display().to_string()
+        }
+    };
+
+    let mut content = String::new();
+    match format {
+        Format::M3u => {
+            writeln!(content, "#EXTM3U")?;
+            for track in tracks {
+                writeln!(content, "#EXTINF:{},{}", track.duration, track.name)?;
+                writeln!(content, "{}", location(track))?;
+            }
+        }
+        Format::Pls => {
+            writeln!(content, "[playlist]")?;
+            for (index, track) in tracks.iter().enumerate() {
+                let number = index + 1;
+                writeln!(content, "File{number}={}", location(track))?;
+                writeln!(content, "Title{number}={}", track.name)?;
+                writeln!(content, "Length{number}={}", track.duration)?;
+            }
+            writeln!(content, "NumberOfEntries={}", tracks.len())?;
+            writeln!(content, "Version=2")?;
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(overwrite)
+        .create_new(!overwrite)
+        .open(path)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    file.write_all(content.as_bytes())
+        .with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// `path` relative to `directory`, both should be absolute
+fn relative_path(directory: &Path, path: &Path) -> PathBuf {
+    let directory: Vec<Component> = directory.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = directory
+        .iter()
+        .zip(&path)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        // Different roots, like another drive
+        return path.iter().collect();
+    }
+    directory[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .chain(path[common..].iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_entries() {
+        let entries = parse_m3u(
+            "\u{feff}#EXTM3U\n\
+             #EXTINF:215,Artist - Song\n\
+             music/song.flac\n\
+             \n\
+             # a comment\n\
+             /music/other.mp3\n\
+             #EXTINF:-1,Stream\n\
+             http://radio.example/stream\n",
+        );
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].line, 3);
+        assert_eq!(entries[0].location, "music/song.flac");
+        assert_eq!(entries[0].title.as_deref(), Some("Artist - Song"));
+        assert_eq!(entries[0].duration, Some(215));
+        assert_eq!(entries[1].location, "/music/other.mp3");
+        assert_eq!(entries[1].title, None);
+        assert_eq!(entries[1].duration, None);
+        assert_eq!(entries[2].title.as_deref(), Some("Stream"));
+        assert_eq!(entries[2].duration, None);
+    }
+
+    #[test]
+    fn pls_entries() {
+        let entries = parse_pls(
+            "[playlist]\n\
+             File2=b.ogg\n\
+             Title2=Second\n\
+             File1=a.ogg\n\
+             Title1=First\n\
+             Length1=60\n\
+             Length2=-1\n\
+             NumberOfEntries=2\n\
+             Version=2\n",
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "a.ogg");
+        assert_eq!(entries[0].line, 4);
+        assert_eq!(entries[0].title.as_deref(), Some("First"));
+        assert_eq!(entries[0].duration, Some(60));
+        assert_eq!(entries[1].location, "b.ogg");
+        assert_eq!(entries[1].title.as_deref(), Some("Second"));
+        assert_eq!(entries[1].duration, None);
+    }
+
+    #[test]
+    fn entry_paths() {
+        let directory = Path::new("/playlists");
+        assert_eq!(
+            entry_path("file:///music/My%20Song%C3%A9.flac", directory),
+            Some(PathBuf::from("/music/My Songé.flac"))
+        );
+        assert_eq!(
+            entry_path("file://localhost/music/a%2.flac", directory),
+            Some(PathBuf::from("/music/a%2.flac"))
+        );
+        assert_eq!(
+            entry_path("music\\a.flac", directory),
+            Some(PathBuf::from("/playlists/music/a.flac"))
+        );
+        assert_eq!(entry_path("https://radio.example/stream", directory), None);
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_path(Path::new("/music/lists"), Path::new("/music/album/a.flac")),
+            PathBuf::from("../album/a.flac")
+        );
+        assert_eq!(
+            relative_path(Path::new("/music"), Path::new("/music/album/a.flac")),
+            PathBuf::from("album/a.flac")
+        );
+        assert_eq!(
+            relative_path(Path::new("/"), Path::new("/a.flac")),
+            PathBuf::from("a.flac")
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Playlists, kept in tables of their own in the library database.
+//!
+//! rmusic has no playlists, so the tables are created here when they are missing.
+//! Entries refer to tracks by path, so they survive a track being read again.
+
+use std::path::Path;
+
+use anyhow::Result;
+use futures::executor::block_on;
+use rmusic::{
+    database::Library,
+    models::{track, Track},
+    queue::queue_items::QueueItem,
+};
+use sea_orm::{
+    sea_query::Expr, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, Schema,
+};
+
+pub use playlist::Model as Playlist;
+
+mod playlist {
+    use sea_orm::entity::prelude::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+    #[sea_orm(table_name = "tui_playlist")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+        pub name: String,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+mod entry {
+    use sea_orm::entity::prelude::*;
+
+    /// A track in a playlist
+    #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+    #[sea_orm(table_name = "tui_playlist_entry")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+        #[sea_orm(indexed)]
+        pub playlist_id: i32,
+        /// Order in the playlist, from 0
+        pub position: i32,
+        pub path: String,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+/// Create the playlist tables if they are not there yet
+fn create_tables(library: &Library) -> Result<()> {
+    let connection = library.connection();
+    let backend = connection.get_database_backend();
+    let schema = Schema::new(backend);
+    let mut playlists = schema.create_table_from_entity(playlist::Entity);
+    let mut entries = schema.create_table_from_entity(entry::Entity);
+    block_on(connection.execute(backend.build(playlists.if_not_exists())))?;
+    block_on(connection.execute(backend.build(entries.if_not_exists())))?;
+    Ok(())
+}
+
+/// Every playlist, by name
+pub fn all(library: &Library) -> Result<Vec<Playlist>> {
+    create_tables(library)?;
+    Ok(block_on(
+        playlist::Entity::find()
+            .order_by_asc(playlist::Column::Name)
+            .all(library.connection()),
+    )?)
+}
+
+/// `name`, or `name (2)`, `name (3)` and so on when a playlist already has it
+pub fn unique_name(library: &Library, name: &str) -> Result<String> {
+    let names: Vec<String> = all(library)?
+        .into_iter()
+        .map(|playlist| playlist.name)
+        .collect();
+    let taken = |name: &str| names.iter().any(|taken| taken == name);
+    if !taken(name) {
+        return Ok(name.to_string());
+    }
+    Ok((2..)
+        .map(|number| format!("{name} ({number})"))
+        .find(|name| !taken(name))
+        .unwrap_or_default())
+}
+
+pub fn create(library: &Library, name: &str) -> Result<Playlist> {
+    create_tables(library)?;
+    let playlist = playlist::ActiveModel {
+        name: Set(name.to_string()),
+        ..Default::default()
+    };
+    Ok(block_on(
+        playlist::Entity::insert(playlist).exec_with_returning(library.connection()),
+    )?)
+}
+
+pub fn rename(library: &Library, playlist: &Playlist, name: &str) -> Result<()> {
+    block_on(
+        playlist::Entity::update_many()
+            .col_expr(playlist::Column::Name, Expr::value(name))
+            .filter(playlist::Column::Id.eq(playlist.id))
+            .exec(library.connection()),
+    )?;
+    Ok(())
+}
+
+pub fn delete(library: &Library, playlist: &Playlist) -> Result<()> {
+    block_on(
+        entry::Entity::delete_many()
+            .filter(entry::Column::PlaylistId.eq(playlist.id))
+            .exec(library.connection()),
+    )?;
+    block_on(playlist::Entity::delete_by_id(playlist.id).exec(library.connection()))?;
+    Ok(())
+}
+
+/// The tracks of a playlist in order, entries whose track is no longer in the library are skipped
+pub fn tracks(library: &Library, playlist: &Playlist) -> Result<Vec<Track>> {
+    let entries = block_on(
+        entry::Entity::find()
+            .filter(entry::Column::PlaylistId.eq(playlist.id))
+            .order_by_asc(entry::Column::Position)
+            .all(library.connection()),
+    )?;
+    let mut tracks = vec![];
+    for entry in entries {
+        let track = block_on(
+            track::Entity::find()
+                .filter(track::Column::Path.eq(entry.path))
+                .one(library.connection()),
+        )?;
+        tracks.extend(track);
+    }
+    Ok(tracks)
+}
+
+/// Replace the tracks of a playlist
+pub fn set_tracks(library: &Library, playlist: &Playlist, tracks: &[Track]) -> Result<()> {
+    block_on(
+        entry::Entity::delete_many()
+            .filter(entry::Column::PlaylistId.eq(playlist.id))
+            .exec(library.connection()),
+    )?;
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    let entries = tracks
+        .iter()
+        .enumerate()
+        .map(|(position, track)| entry::ActiveModel {
+            playlist_id: Set(playlist.id),
+            position: Set(position as i32),
+            path: Set(track.path.clone()),
+            ..Default::default()
+        });
+    block_on(entry::Entity::insert_many(entries).exec(library.connection()))?;
+    Ok(())
+}
+
+/// A queue item for every track of a playlist
+pub fn queue_items(library: &mut Library, playlist: &Playlist) -> Result<Vec<QueueItem>> {
+    let mut queue_items = vec![];
+    for track in tracks(library, playlist)? {
+        queue_items.push(library.queue_item_from_path(Path::new(&track.path))?);
+    }
+    Ok(queue_items)
+}
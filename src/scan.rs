@@ -0,0 +1,53 @@
+//! Finding the audio files in the music folders.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+
+/// Extensions of the files that are added to the library
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "aif", "aiff", "ape", "flac", "m4a", "mp3", "oga", "ogg", "opus", "wav", "wv",
+];
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|audio| audio.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Every audio file in `dir` and its subfolders, sorted.
+/// Subfolders that can not be read are logged and skipped, symlinks are not followed.
+pub fn audio_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![];
+    read_dir(dir, &mut files, &mut dirs)
+        .with_context(|| format!("Could not read {}", dir.display()))?;
+    while let Some(dir) = dirs.pop() {
+        if let Err(err) = read_dir(&dir, &mut files, &mut dirs) {
+            warn!("Skipping {}: {err}", dir.display());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read_dir(dir: &Path, files: &mut Vec<PathBuf>, dirs: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            dirs.push(entry.path());
+        } else if file_type.is_file() && is_audio_file(&entry.path()) {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,373 @@
+//! The playback session: the queue, the current track and position, the volume,
+//! and the shuffle and repeat modes. It is saved on exit and every minute,
+//! and restored on the next start.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rmusic::{
+    database::Library,
+    playback_loop::PlaybackAction,
+    queue::{queue_items::QueueItem, ShuffleType},
+};
+use rmusic_tui::settings::data_dir;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database,
+    transport::{RepeatMode, Transport},
+    ui::item_tracks,
+};
+
+/// How often the session is saved while running
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+const SESSION_FILE: &str = "session.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    queue: Vec<SavedItem>,
+    /// Index of the current queue item, and of the track inside that item
+    position: Option<(usize, usize)>,
+    /// Seconds played of the current track
+    played: u64,
+    volume: f32,
+    shuffle: Shuffle,
+    repeat: RepeatMode,
+}
+
+/// A queue item, with where it came from so it is restored as the same kind of item
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum SavedItem {
+    Track {
+        path: PathBuf,
+    },
+    /// A release, read again from the library
+    Album {
+        release_id: i32,
+        tracks: Vec<PathBuf>,
+    },
+    /// All tracks of an artist, read again from the library through the release
+    /// of the first track
+    Playlist {
+        release_id: i32,
+        tracks: Vec<PathBuf>,
+    },
+}
+
+/// The shuffle mode of the queue
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Shuffle {
+    Off,
+    Tracks,
+    Albums,
+}
+
+impl From<&ShuffleType> for Shuffle {
+    fn from(shuffle_type: &ShuffleType) -> Shuffle {
+        match shuffle_type {
+            ShuffleType::None => Shuffle::Off,
+            ShuffleType::TrackShuffle => Shuffle::Tracks,
+            ShuffleType::AlbumShuffle => Shuffle::Albums,
+        }
+    }
+}
+
+impl From<Shuffle> for ShuffleType {
+    fn from(shuffle: Shuffle) -> ShuffleType {
+        match shuffle {
+            Shuffle::Off => ShuffleType::None,
+            Shuffle::Tracks => ShuffleType::TrackShuffle,
+            Shuffle::Albums => ShuffleType::AlbumShuffle,
+        }
+    }
+}
+
+impl SavedItem {
+    fn capture(item: &QueueItem) -> SavedItem {
+        let tracks = item_tracks(item);
+        let paths = || {
+            tracks
+                .iter()
+                .map(|track| PathBuf::from(&track.path))
+                .collect()
+        };
+        match (item, tracks.first()) {
+            (QueueItem::Album(_), Some(track)) => SavedItem::Album {
+                release_id: track.release_id,
+                tracks: paths(),
+            },
+            (QueueItem::Playlist(_), Some(track)) => SavedItem::Playlist {
+                release_id: track.release_id,
+                tracks: paths(),
+            },
+            (_, track) => SavedItem::Track {
+                path: track.map_or_else(PathBuf::new, |track| PathBuf::from(&track.path)),
+            },
+        }
+    }
+
+    fn tracks(&self) -> &[PathBuf] {
+        match self {
+            SavedItem::Track { path } => std::slice::from_ref(path),
+            SavedItem::Album { tracks, .. } | SavedItem::Playlist { tracks, .. } => tracks,
+        }
+    }
+
+    /// The item as it was saved, `None` when the library has changed since, then
+    /// the tracks that are left come back one by one
+    fn restore(&self, library: &mut Library) -> Result<Option<QueueItem>> {
+        let queue_item = match *self {
+            SavedItem::Track { ref path } => return Ok(Some(library.queue_item_from_path(path)?)),
+            SavedItem::Album { release_id, .. } => {
+                database::release_queue_item(library, release_id)?
+            }
+            SavedItem::Playlist { release_id, .. } => {
+                match database::release_artist(library, release_id)? {
+                    Some(artist_id) => database::artist_queue_item(library, artist_id)?,
+                    None => None,
+                }
+            }
+        };
+        Ok(queue_item.filter(|queue_item| {
+            item_tracks(queue_item)
+                .iter()
+                .map(|track| Path::new(&track.path))
+                .eq(self.tracks().iter().map(PathBuf::as_path))
+        }))
+    }
+}
+
+impl Session {
+    /// The current session of the player
+    pub fn capture(transport: &Transport) -> Session {
+        let playback_context = transport.playback_context();
+        let queue = playback_context.lock_queue();
+        Session {
+            queue: queue.queue_items().iter().map(SavedItem::capture).collect(),
+            position: queue.current_position(),
+            played: playback_context.played_sec(),
+            volume: transport.gain(),
+            shuffle: Shuffle::from(&queue.queue_options.shuffle_type),
+            repeat: transport.repeat(),
+        }
+    }
+
+    /// Read the saved session, `None` if there is none
+    pub fn load() -> Result<Option<Session>> {
+        let Some(path) = session_path() else {
+            return Ok(None);
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read session {}", path.display()))?;
+        let session = serde_json::from_str(&content)
+            .with_context(|| format!("Could not parse session {}", path.display()))?;
+        Ok(Some(session))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = session_path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write next to the session first, so a crash does not leave half a file
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string(self)?)
+            .with_context(|| format!("Could not write session {}", temp_path.display()))?;
+        fs::rename(&temp_path, &path)
+            .with_context(|| format!("Could not write session {}", path.display()))
+    }
+
+    /// Put the session back, paused at the position it was saved at.
+    /// Albums and playlists that changed in the library come back as separate
+    /// tracks, tracks that are no longer in the library are skipped.
+    pub fn restore(&self, library: &mut Library, transport: &Transport) -> Result<()> {
+        let playback_context = transport.playback_context();
+        let mut queue_items: Vec<QueueItem> = vec![];
+        let mut restored = vec![];
+        for saved_item in &self.queue {
+            match saved_item.restore(library) {
+                Ok(Some(queue_item)) => {
+                    queue_items.push(queue_item);
+                    restored.push(Restored::Whole);
+                    continue;
+                }
+                Ok(None) => (),
+                Err(err) => warn!("Could not restore a queue item as a whole: {err:#}"),
+            }
+            let mut tracks = vec![];
+            for path in saved_item.tracks() {
+                match library.queue_item_from_path(path) {
+                    Ok(queue_item) => {
+                        queue_items.push(queue_item);
+                        tracks.push(true);
+                    }
+                    Err(err) => {
+                        warn!("Not restoring {} to the queue: {err}", path.display());
+                        tracks.push(false);
+                    }
+                }
+            }
+            restored.push(Restored::Tracks(tracks));
+        }
+        let current = restored_position(self.position, &restored);
+        info!("Restoring {} items to the queue", queue_items.len());
+
+        {
+            let mut queue = playback_context.lock_queue();
+            // Not flattened, albums and playlists stay whole like they were saved
+            for queue_item in queue_items {
+                queue.append_queue_item(queue_item, false);
+            }
+            // The queue was saved in play order, shuffling it again would lose that
+            queue.queue_options.shuffle_type = self.shuffle.into();
+        }
+        transport.set_repeat(self.repeat);
+
+        let mut actions = vec![transport.gain_action(self.volume)];
+        // The current track may have been removed, then the next one is current
+        let queue_length = playback_context.lock_queue().queue_items().len();
+        if let Some((item, track)) = current.filter(|(item, _)| *item < queue_length) {
+            actions.push(PlaybackAction::PlayFromQueue(item, track));
+            actions.push(PlaybackAction::PlayPause);
+            if self.played > 0 {
+                actions.push(PlaybackAction::FastForward(self.played));
+            }
+        }
+        for action in actions {
+            transport.send(action)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a saved item came back into the queue
+enum Restored {
+    /// As one item
+    Whole,
+    /// As an item per track, `false` for the tracks that are no longer in the library
+    Tracks(Vec<bool>),
+}
+
+/// The position of the saved current track in the restored queue. When the
+/// current track is gone the one after it is current.
+fn restored_position(
+    position: Option<(usize, usize)>,
+    restored: &[Restored],
+) -> Option<(usize, usize)> {
+    let (item, track) = position?;
+    let mut index = 0;
+    for (item_index, restored_item) in restored.iter().enumerate() {
+        match restored_item {
+            Restored::Whole if item_index == item => return Some((index, track)),
+            Restored::Whole => index += 1,
+            Restored::Tracks(tracks) if item_index == item => {
+                let before = tracks.iter().take(track).filter(|kept| **kept).count();
+                return Some((index + before, 0));
+            }
+            Restored::Tracks(tracks) => index += tracks.iter().filter(|kept| **kept).count(),
+        }
+    }
+    None
+}
+
+fn session_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(SESSION_FILE))
+}
+
+/// Save the session, failing to save is not worth stopping for
+pub fn save(transport: &Transport) {
+    if let Err(err) = Session::capture(transport).save() {
+        warn!("Could not save the session: {err:#}");
+    }
+}
+
+/// Load and restore the saved session, if there is one
+pub fn restore(transport: &Transport) {
+    let result = Session::load().and_then(|session| match session {
+        Some(session) => session.restore(&mut Library::try_new()?, transport),
+        None => Ok(()),
+    });
+    if let Err(err) = result {
+        warn!("Could not restore the session: {err:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let session = Session {
+            queue: vec![
+                SavedItem::Track {
+                    path: PathBuf::from("/music/single.flac"),
+                },
+                SavedItem::Album {
+                    release_id: 3,
+                    tracks: vec![
+                        PathBuf::from("/music/a/1.flac"),
+                        PathBuf::from("/music/a/2.flac"),
+                    ],
+                },
+                SavedItem::Playlist {
+                    release_id: 4,
+                    tracks: vec![PathBuf::from("/music/b/1.flac")],
+                },
+            ],
+            position: Some((1, 1)),
+            played: 42,
+            volume: 0.25,
+            shuffle: Shuffle::Albums,
+            repeat: RepeatMode::One,
+        };
+        let json = serde_json::to_string(&session).unwrap();
+        assert!(json.contains(r#""kind":"album""#));
+        assert!(json.contains(r#""shuffle":"albums""#));
+        let restored: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        assert!(matches!(
+            &restored.queue[1],
+            SavedItem::Album { release_id: 3, tracks } if tracks.len() == 2
+        ));
+        assert_eq!(restored.position, Some((1, 1)));
+        assert_eq!(restored.repeat, RepeatMode::One);
+    }
+
+    #[test]
+    fn shuffle_types() {
+        for shuffle in [Shuffle::Off, Shuffle::Tracks, Shuffle::Albums] {
+            assert!(Shuffle::from(&ShuffleType::from(shuffle)) == shuffle);
+        }
+    }
+
+    #[test]
+    fn positions_after_restoring() {
+        let restored = [
+            Restored::Whole,
+            Restored::Tracks(vec![true, false, true]),
+            Restored::Whole,
+        ];
+        assert_eq!(restored_position(None, &restored), None);
+        assert_eq!(restored_position(Some((0, 0)), &restored), Some((0, 0)));
+        // The album came back as separate tracks
+        assert_eq!(restored_position(Some((1, 0)), &restored), Some((1, 0)));
+        assert_eq!(restored_position(Some((1, 2)), &restored), Some((2, 0)));
+        // The current track is gone, the one after it is current
+        assert_eq!(restored_position(Some((1, 1)), &restored), Some((2, 0)));
+        assert_eq!(restored_position(Some((2, 4)), &restored), Some((3, 4)));
+        assert_eq!(restored_position(Some((3, 0)), &restored), None);
+    }
+}
@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+/// Audio output settings, everything that is not set uses the system default
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioSettings {
+    /// Audio host, like `ALSA` or `JACK`
+    pub host: Option<String>,
+    /// Name or index of the output device, see `--list-devices`
+    pub device: Option<String>,
+    /// Preferred sample rate in Hz
+    pub sample_rate: Option<u32>,
+    /// Preferred buffer size in frames
+    pub buffer_size: Option<u32>,
+}
@@ -1,20 +1,145 @@
+use anyhow::{anyhow, bail, Result};
 use ratatui_eventInput::{Input, Key, Modifier, Side};
+use serde::{de, Deserialize, Deserializer};
 
 type Inputs = Vec<Input>;
 
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct InputMap {
     pub navigation: Navigation,
     pub media: Media,
+    pub dialog: Dialog,
 }
 
+impl InputMap {
+    /// Returns an error listing every input that is bound to more than one action
+    pub fn check_conflicts(&self) -> Result<()> {
+        let bindings: Vec<(String, &Inputs)> = self
+            .navigation
+            .bindings()
+            .into_iter()
+            .map(|(name, inputs)| (format!("navigation.{name}"), inputs))
+            .chain(
+                self.media
+                    .bindings()
+                    .into_iter()
+                    .map(|(name, inputs)| (format!("media.{name}"), inputs)),
+            )
+            .collect();
+        // Nothing else is bound while typing or answering a question
+        let dialog_bindings: Vec<(String, &Inputs)> = self
+            .dialog
+            .bindings()
+            .into_iter()
+            .map(|(name, inputs)| (format!("dialog.{name}"), inputs))
+            .collect();
+
+        let mut conflicts = find_conflicts(&bindings);
+        conflicts.extend(find_conflicts(&dialog_bindings));
+        if !conflicts.is_empty() {
+            bail!("Conflicting key bindings:\n{}", conflicts.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+fn find_conflicts(bindings: &[(String, &Inputs)]) -> Vec<String> {
+    let mut conflicts = vec![];
+    for (index, (name, inputs)) in bindings.iter().enumerate() {
+        for input in *inputs {
+            for (other_name, other_inputs) in &bindings[index + 1..] {
+                if other_inputs.contains(input) {
+                    conflicts.push(format!(
+                        "`{}` is bound to both `{name}` and `{other_name}`",
+                        format_input(input)
+                    ));
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Media {
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub playpause: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub volume_up: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub volume_down: Inputs,
+    /// Mute, or go back to the volume from before muting
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub mute: Inputs,
+    /// Open a popup with the volume, the volume bindings work in it
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub volume_popup: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub fast_forward: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub rewind: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub fast_forward_large: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub rewind_large: Inputs,
+    /// Open a prompt to jump to a time like `1:30` or a percentage like `50%`
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub seek_to: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub shuffle: Inputs,
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub repeat: Inputs,
+    /// Play the next track, or go forward again after going back
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub next: Inputs,
+    /// Restart the track, or play the track that was played before it
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub previous: Inputs,
+    /// Jump to 0%, 10%, up to 90% of the track, one binding for every tenth
+    #[serde(deserialize_with = "deserialize_input_list")]
+    pub seek_percent: Vec<Inputs>,
+}
+
+impl Media {
+    fn bindings(&self) -> Vec<(String, &Inputs)> {
+        let mut bindings: Vec<(String, &Inputs)> = [
+            ("playpause", &self.playpause),
+            ("volume_up", &self.volume_up),
+            ("volume_down", &self.volume_down),
+            ("mute", &self.mute),
+            ("volume_popup", &self.volume_popup),
+            ("fast_forward", &self.fast_forward),
+            ("rewind", &self.rewind),
+            ("fast_forward_large", &self.fast_forward_large),
+            ("rewind_large", &self.rewind_large),
+            ("seek_to", &self.seek_to),
+            ("shuffle", &self.shuffle),
+            ("repeat", &self.repeat),
+            ("next", &self.next),
+            ("previous", &self.previous),
+        ]
+        .into_iter()
+        .map(|(name, inputs)| (name.to_string(), inputs))
+        .collect();
+        bindings.extend(
+            self.seek_percent
+                .iter()
+                .enumerate()
+                .map(|(tenth, inputs)| (format!("seek_percent[{tenth}]"), inputs)),
+        );
+        bindings
+    }
+
+    /// The tenths of a track to jump to for an input, 0 to 9
+    pub fn seek_tenths(&self, input: &Input) -> Option<u64> {
+        self.seek_percent
+            .iter()
+            .take(10)
+            .position(|inputs| inputs.contains(input))
+            .map(|tenths| tenths as u64)
+    }
 }
 
 impl Default for Media {
@@ -23,37 +148,138 @@ impl Default for Media {
             playpause: Input::keys(&[Key::Char('c'), Key::Char(' ')]),
             volume_up: Input::keys(&[Key::Char('+'), Key::Char('=')]),
             volume_down: Input::keys(&[Key::Char('-')]),
+            mute: Input::keys(&[Key::Char('x')]),
+            volume_popup: Input::keys(&[Key::Char('v')]),
             fast_forward: Input::keys(&[Key::Char('m')]),
             rewind: Input::keys(&[Key::Char('n')]),
+            fast_forward_large: Input::keys(&[Key::Char('M')]),
+            rewind_large: Input::keys(&[Key::Char('N')]),
+            seek_to: Input::keys(&[Key::Char('g')]),
             shuffle: Input::keys(&[Key::Char('s')]),
             repeat: Input::keys(&[Key::Char('r')]),
+            next: Input::keys(&[Key::Char('>')]),
+            previous: Input::keys(&[Key::Char('<')]),
+            seek_percent: ('0'..='9')
+                .map(|digit| Input::keys(&[Key::Char(digit)]))
+                .collect(),
         }
     }
 }
 
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Navigation {
     /// Go one item up a list
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub list_up: Inputs,
     /// Go one item down a list
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub list_down: Inputs,
     /// Select/interact with the item selected in a list
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub list_select: Inputs,
     /// Go back to previous list
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub list_back: Inputs,
     /// Cancel the current action
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub cancel: Inputs,
     /// Next tab
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub tab_next: Inputs,
     /// Previous tab
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub tab_previus: Inputs,
     /// (un)Hide hidden files
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub hide_toggle: Inputs,
     /// Add an item to the queue or library depending on the context
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub item_add: Inputs,
+    /// Add an item to the queue right after the current track
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub item_add_next: Inputs,
     /// Select an individual item, depends on the context what it does.
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub item_set: Inputs,
     /// Refresh view
+    #[serde(deserialize_with = "deserialize_inputs")]
     pub refresh: Inputs,
+    /// Remove the selected item
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub item_remove: Inputs,
+    /// Move the selected item one place up
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub item_move_up: Inputs,
+    /// Move the selected item one place down
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub item_move_down: Inputs,
+    /// Remove all items from the list
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub list_clear: Inputs,
+    /// Undo the last edit
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub undo: Inputs,
+    /// Start typing a search in the active list
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub search: Inputs,
+    /// Select the next item that matches the search
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub search_next: Inputs,
+    /// Select the previous item that matches the search
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub search_previous: Inputs,
+    /// Create a new item, like a playlist
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub item_new: Inputs,
+    /// Rename the selected item
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub item_rename: Inputs,
+    /// Add the selected item to a playlist
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub playlist_add: Inputs,
+    /// Write the selected playlist or the queue to a playlist file
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub playlist_export: Inputs,
+    /// Show the imports that run in the background
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub jobs: Inputs,
+    /// Check that the library matches the files on disk
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub library_check: Inputs,
+}
+
+impl Navigation {
+    fn bindings(&self) -> Vec<(&'static str, &Inputs)> {
+        vec![
+            ("list_up", &self.list_up),
+            ("list_down", &self.list_down),
+            ("list_select", &self.list_select),
+            ("list_back", &self.list_back),
+            ("cancel", &self.cancel),
+            ("tab_next", &self.tab_next),
+            ("tab_previus", &self.tab_previus),
+            ("hide_toggle", &self.hide_toggle),
+            ("item_add", &self.item_add),
+            ("item_add_next", &self.item_add_next),
+            ("item_set", &self.item_set),
+            ("refresh", &self.refresh),
+            ("item_remove", &self.item_remove),
+            ("item_move_up", &self.item_move_up),
+            ("item_move_down", &self.item_move_down),
+            ("list_clear", &self.list_clear),
+            ("undo", &self.undo),
+            ("search", &self.search),
+            ("search_next", &self.search_next),
+            ("search_previous", &self.search_previous),
+            ("item_new", &self.item_new),
+            ("item_rename", &self.item_rename),
+            ("playlist_add", &self.playlist_add),
+            ("playlist_export", &self.playlist_export),
+            ("jobs", &self.jobs),
+            ("library_check", &self.library_check),
+        ]
+    }
 }
 
 impl Default for Navigation {
@@ -71,8 +297,314 @@ impl Default for Navigation {
             ],
             hide_toggle: Input::keys(&[Key::Char('H')]),
             item_add: Input::keys(&[Key::Char('a')]),
+            item_add_next: Input::keys(&[Key::Char('A')]),
             item_set: Input::keys(&[Key::Char('p')]),
             refresh: vec![Input::new(Key::Char('r'), Modifier::Control(Side::Any))],
+            item_remove: Input::keys(&[Key::Char('d'), Key::Delete]),
+            item_move_up: Input::keys(&[Key::Char('K')]),
+            item_move_down: Input::keys(&[Key::Char('J')]),
+            list_clear: Input::keys(&[Key::Char('D')]),
+            undo: Input::keys(&[Key::Char('u')]),
+            search: Input::keys(&[Key::Char('/')]),
+            search_next: vec![Input::new(Key::Char('n'), Modifier::Control(Side::Any))],
+            search_previous: vec![Input::new(Key::Char('p'), Modifier::Control(Side::Any))],
+            item_new: Input::keys(&[Key::Char('o')]),
+            item_rename: Input::keys(&[Key::Char('R')]),
+            playlist_add: Input::keys(&[Key::Char('P')]),
+            playlist_export: Input::keys(&[Key::Char('E')]),
+            jobs: Input::keys(&[Key::Char('i')]),
+            library_check: Input::keys(&[Key::Char('C')]),
         }
     }
 }
+
+/// Keys of prompts and questions, while one is open the other bindings do nothing
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dialog {
+    /// Finish typing a name, a path or a search
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub submit: Inputs,
+    /// Answer yes to a question
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub confirm: Inputs,
+    /// Answer no, questions that only guard an action take any other key as no too
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub deny: Inputs,
+    /// Library check: point the moved tracks to their new files
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub check_relocate: Inputs,
+    /// Library check: read the changed tracks again
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub check_update: Inputs,
+    /// Library check: delete the missing tracks and the orphans
+    #[serde(deserialize_with = "deserialize_inputs")]
+    pub check_delete: Inputs,
+}
+
+impl Dialog {
+    fn bindings(&self) -> Vec<(&'static str, &Inputs)> {
+        vec![
+            ("submit", &self.submit),
+            ("confirm", &self.confirm),
+            ("deny", &self.deny),
+            ("check_relocate", &self.check_relocate),
+            ("check_update", &self.check_update),
+            ("check_delete", &self.check_delete),
+        ]
+    }
+
+    /// The answers to show after a question, like `y/n`
+    pub fn answers(&self) -> String {
+        let first = |inputs: &Inputs| inputs.first().map_or("?".to_string(), format_input);
+        format!("{}/{}", first(&self.confirm), first(&self.deny))
+    }
+}
+
+impl Default for Dialog {
+    fn default() -> Self {
+        Self {
+            submit: Input::keys(&[Key::Enter]),
+            confirm: Input::keys(&[Key::Char('y')]),
+            deny: Input::keys(&[Key::Char('n')]),
+            check_relocate: Input::keys(&[Key::Char('r')]),
+            check_update: Input::keys(&[Key::Char('u')]),
+            check_delete: Input::keys(&[Key::Char('d')]),
+        }
+    }
+}
+
+/// A binding in the config file is either a single input or a list of inputs
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Bindings {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn deserialize_inputs<'de, D>(deserializer: D) -> std::result::Result<Inputs, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let bindings = match Bindings::deserialize(deserializer)? {
+        Bindings::One(binding) => vec![binding],
+        Bindings::Many(bindings) => bindings,
+    };
+    bindings
+        .iter()
+        .map(|binding| parse_input(binding).map_err(de::Error::custom))
+        .collect()
+}
+
+/// A list of bindings, every entry is a single input or a list of inputs
+fn deserialize_input_list<'de, D>(deserializer: D) -> std::result::Result<Vec<Inputs>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let list = Vec::<Bindings>::deserialize(deserializer)?;
+    if list.len() > 10 {
+        return Err(de::Error::custom(
+            "there are only 10 tenths to bind, 0 to 9",
+        ));
+    }
+    list.into_iter()
+        .map(|bindings| {
+            let bindings = match bindings {
+                Bindings::One(binding) => vec![binding],
+                Bindings::Many(bindings) => bindings,
+            };
+            bindings
+                .iter()
+                .map(|binding| parse_input(binding).map_err(de::Error::custom))
+                .collect()
+        })
+        .collect()
+}
+
+/// Parse an input like `k`, `PageUp`, `shift+tab` or `ctrl+r`.
+/// Single characters are case sensitive, key and modifier names are not.
+pub fn parse_input(binding: &str) -> Result<Input> {
+    let (modifier, key) = if binding.len() > 1 && binding.ends_with("++") {
+        // `ctrl++`, the key itself is a `+`
+        (Some(&binding[..binding.len() - 2]), "+")
+    } else {
+        match binding.rsplit_once('+') {
+            Some((modifier, key)) if !modifier.is_empty() && !key.is_empty() => {
+                (Some(modifier), key)
+            }
+            _ => (None, binding),
+        }
+    };
+
+    let key = parse_key(key).map_err(|err| anyhow!("`{binding}`: {err}"))?;
+    let Some(modifier) = modifier else {
+        return Ok(Input::new_key(key));
+    };
+    let modifier = match modifier.to_lowercase().as_str() {
+        "ctrl" | "control" => Modifier::Control(Side::Any),
+        "shift" => Modifier::Shift(Side::Any),
+        "alt" => Modifier::Alt(Side::Any),
+        other if other.contains('+') => {
+            bail!("`{binding}`: only one modifier per binding is supported")
+        }
+        other => bail!("`{binding}`: unknown modifier `{other}`"),
+    };
+    Ok(Input::new(key, modifier))
+}
+
+/// Write an input the way it is bound in the config file, the opposite of `parse_input`
+pub fn format_input(input: &Input) -> String {
+    let key = match input.key {
+        Key::Char(' ') => "space".to_string(),
+        Key::Char(char) => char.to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Enter => "Enter".to_string(),
+        Key::Esc => "Esc".to_string(),
+        Key::Tab => "Tab".to_string(),
+        Key::BackTab => "BackTab".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::F(number) => format!("F{number}"),
+        // Keys that can not be bound in the config file
+        other => format!("{other:?}"),
+    };
+    let with = |modifier| *input == Input::new(input.key, modifier);
+    if with(Modifier::Control(Side::Any)) {
+        format!("ctrl+{key}")
+    } else if with(Modifier::Shift(Side::Any)) {
+        format!("shift+{key}")
+    } else if with(Modifier::Alt(Side::Any)) {
+        format!("alt+{key}")
+    } else {
+        key
+    }
+}
+
+/// The first input of a binding the way it is written in the config file,
+/// `?` when nothing is bound
+pub fn format_first(inputs: &[Input]) -> String {
+    inputs.first().map_or("?".to_string(), format_input)
+}
+
+fn parse_key(key: &str) -> Result<Key> {
+    let mut chars = key.chars();
+    if let (Some(char), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Char(char));
+    }
+    let key = match key.to_lowercase().as_str() {
+        "space" => Key::Char(' '),
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "enter" | "return" => Key::Enter,
+        "esc" | "escape" => Key::Esc,
+        "tab" => Key::Tab,
+        "backtab" => Key::BackTab,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "insert" | "ins" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        name => match name.strip_prefix('f').map(str::parse::<u8>) {
+            Some(Ok(number)) if (1..=12).contains(&number) => Key::F(number),
+            _ => bail!("unknown key `{key}`"),
+        },
+    };
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_keys() {
+        assert_eq!(parse_input("k").unwrap(), Input::new_key(Key::Char('k')));
+        assert_eq!(parse_input("K").unwrap(), Input::new_key(Key::Char('K')));
+        assert_eq!(parse_input("+").unwrap(), Input::new_key(Key::Char('+')));
+        assert_eq!(parse_input("PageUp").unwrap(), Input::new_key(Key::PageUp));
+        assert_eq!(
+            parse_input("space").unwrap(),
+            Input::new_key(Key::Char(' '))
+        );
+        assert_eq!(parse_input("F5").unwrap(), Input::new_key(Key::F(5)));
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(
+            parse_input("ctrl+r").unwrap(),
+            Input::new(Key::Char('r'), Modifier::Control(Side::Any))
+        );
+        assert_eq!(
+            parse_input("Shift+Tab").unwrap(),
+            Input::new(Key::Tab, Modifier::Shift(Side::Any))
+        );
+        assert_eq!(
+            parse_input("alt++").unwrap(),
+            Input::new(Key::Char('+'), Modifier::Alt(Side::Any))
+        );
+    }
+
+    #[test]
+    fn invalid_bindings() {
+        assert!(parse_input("F13").is_err());
+        assert!(parse_input("nokey").is_err());
+        assert!(parse_input("hyper+a").is_err());
+        assert!(parse_input("ctrl+shift+a").is_err());
+    }
+
+    #[test]
+    fn formatted_inputs_parse() {
+        let input_map = InputMap::default();
+        let defaults = input_map
+            .navigation
+            .bindings()
+            .into_iter()
+            .map(|(_, inputs)| inputs)
+            .chain(
+                input_map
+                    .media
+                    .bindings()
+                    .into_iter()
+                    .map(|(_, inputs)| inputs),
+            )
+            .flatten();
+        let others = [
+            Input::new(Key::Char('+'), Modifier::Control(Side::Any)),
+            Input::new(Key::F(12), Modifier::Alt(Side::Any)),
+            Input::new_key(Key::PageDown),
+        ];
+        for input in defaults.chain(&others) {
+            assert_eq!(parse_input(&format_input(input)).unwrap(), *input);
+        }
+        assert_eq!(format_input(&Input::new_key(Key::Char(' '))), "space");
+        assert_eq!(
+            format_input(&Input::new(Key::Char('r'), Modifier::Control(Side::Any))),
+            "ctrl+r"
+        );
+    }
+
+    #[test]
+    fn defaults_do_not_conflict() {
+        InputMap::default().check_conflicts().unwrap();
+    }
+
+    #[test]
+    fn conflicts_are_found() {
+        let mut input_map = InputMap::default();
+        input_map.media.playpause = input_map.navigation.list_up.clone();
+        assert!(input_map.check_conflicts().is_err());
+    }
+}
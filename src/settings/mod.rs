@@ -1 +1,108 @@
+pub mod audio;
 pub mod input;
+pub mod library;
+pub mod playback;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use audio::AudioSettings;
+use directories::ProjectDirs;
+use input::InputMap;
+use library::LibrarySettings;
+use playback::PlaybackSettings;
+use serde::Deserialize;
+
+/// User settings, read from a TOML file.
+/// Every missing entry falls back to its default.
+///
+/// ```toml
+/// theme = "light"
+/// album_art = "auto"
+///
+/// [keys.navigation]
+/// list_up = ["Up", "e"]
+/// list_down = ["Down", "n"]
+/// refresh = "ctrl+r"
+///
+/// [keys.media]
+/// playpause = ["Space", "p"]
+/// seek_percent = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]
+///
+/// [playback]
+/// seek_step = 10
+/// volume = 50
+/// volume_curve = "linear"
+///
+/// [audio]
+/// device = "USB Audio"
+/// sample_rate = 44100
+///
+/// [library]
+/// watch = ["/home/user/Music"]
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub keys: InputMap,
+    /// Name of a bundled theme preset or path to a theme file
+    pub theme: Option<String>,
+    pub album_art: AlbumArt,
+    pub playback: PlaybackSettings,
+    pub audio: AudioSettings,
+    pub library: LibrarySettings,
+}
+
+/// How cover art is drawn
+#[derive(Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlbumArt {
+    /// The best graphics protocol the terminal supports
+    #[default]
+    Auto,
+    /// Unicode half blocks, works in every terminal with colors
+    Halfblocks,
+    Sixel,
+    Kitty,
+    /// Only the name of the cover
+    Text,
+}
+
+impl Settings {
+    /// Load the settings from `path`, or from the config directory when `path` is `None`.
+    /// A missing config file in the config directory is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Settings> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match config_dir() {
+                Some(dir) if dir.join(CONFIG_FILE).exists() => dir.join(CONFIG_FILE),
+                _ => return Ok(Settings::default()),
+            },
+        };
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        let settings: Settings = toml::from_str(&content)
+            .with_context(|| format!("Could not parse config file {}", path.display()))?;
+        settings
+            .keys
+            .check_conflicts()
+            .with_context(|| format!("Invalid key bindings in {}", path.display()))?;
+        Ok(settings)
+    }
+}
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// The config directory, `$XDG_CONFIG_HOME/rmusic_tui` on Linux
+pub fn config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "rmusic_tui").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// The directory for state that is kept between runs, `$XDG_DATA_HOME/rmusic_tui` on Linux
+pub fn data_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "rmusic_tui").map(|dirs| dirs.data_dir().to_path_buf())
+}
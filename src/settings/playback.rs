@@ -0,0 +1,38 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaybackSettings {
+    /// Seconds that `fast_forward` and `rewind` seek
+    pub seek_step: u64,
+    /// Seconds that `fast_forward_large` and `rewind_large` seek
+    pub seek_step_large: u64,
+    /// Volume in percent at startup, a restored session keeps its own volume
+    pub volume: u8,
+    /// Percentage points that `volume_up` and `volume_down` change the volume
+    pub volume_step: u8,
+    pub volume_curve: VolumeCurve,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self {
+            seek_step: 5,
+            seek_step_large: 30,
+            volume: 70,
+            volume_step: 5,
+            volume_curve: VolumeCurve::default(),
+        }
+    }
+}
+
+/// How the volume in percent maps to the loudness
+#[derive(Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeCurve {
+    /// Every step changes the loudness by the same number of decibels
+    #[default]
+    Logarithmic,
+    /// The percentage scales the samples directly, most of the change is at the low end
+    Linear,
+}
@@ -0,0 +1,374 @@
+//! The repeat mode and what happens when a track ends.
+//!
+//! rmusic plays the queue from front to back and stops after the last track.
+//! The transport watches the playback context and repeats the track or the
+//! queue when a track ends on its own. The TUI, the control socket and MPRIS
+//! all send their actions through it, so the repeat mode and the history are the
+//! same for all of them.
+
+use std::{
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc, Mutex, MutexGuard, Weak},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use rmusic::{playback::playback_context::ArcPlaybackContext, playback_loop::PlaybackAction};
+use serde::{Deserialize, Serialize};
+
+use crate::{history::History, ui::item_tracks};
+
+/// How often the playback context is checked for a track change
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// A track that changes with less than this left has ended on its own
+const END_MARGIN_MS: u64 = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    /// Start the queue again after the last track
+    All,
+    /// Play the current track again
+    One,
+}
+
+impl RepeatMode {
+    /// Off -> repeat all -> repeat one
+    pub fn cycle(self) -> RepeatMode {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+
+    /// Short name for the status line
+    pub fn display_small(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "",
+            RepeatMode::All => "R",
+            RepeatMode::One => "R1",
+        }
+    }
+}
+
+pub type ArcTransport = Arc<Transport>;
+
+pub struct Transport {
+    tx: Sender<PlaybackAction>,
+    playback_context: ArcPlaybackContext,
+    state: Mutex<State>,
+    history: Mutex<History>,
+    /// The gain once playback has applied the volume actions sent so far
+    gain: Mutex<f32>,
+}
+
+struct State {
+    repeat: RepeatMode,
+    /// The track at the last check
+    playing: Option<Playing>,
+    /// An action changed the track since the last check, so it did not end on its own
+    skipped: bool,
+}
+
+struct Playing {
+    path: PathBuf,
+    position: (usize, usize),
+    /// Milliseconds that were left at the last check
+    left_ms: u64,
+}
+
+impl Transport {
+    /// Start watching the playback context, until the transport is dropped
+    pub fn start(tx: Sender<PlaybackAction>, playback_context: ArcPlaybackContext) -> ArcTransport {
+        let transport = Arc::new(Transport {
+            tx,
+            playback_context: playback_context.clone(),
+            state: Mutex::new(State {
+                repeat: RepeatMode::Off,
+                playing: None,
+                skipped: false,
+            }),
+            history: Mutex::new(History::new()),
+            gain: Mutex::new(playback_context.volume_level()),
+        });
+        let weak = Arc::downgrade(&transport);
+        thread::spawn(move || watch(weak));
+        transport
+    }
+
+    pub fn playback_context(&self) -> &ArcPlaybackContext {
+        &self.playback_context
+    }
+
+    /// Send an action to playback, `Next` starts the queue again on the last track with repeat all
+    pub fn send(&self, action: PlaybackAction) -> Result<()> {
+        let action = {
+            let mut state = self.lock_state();
+            let action = match action {
+                PlaybackAction::Next if state.repeat == RepeatMode::All => {
+                    next_action(state.repeat, self.on_last_track())
+                }
+                action => action,
+            };
+            if matches!(
+                action,
+                PlaybackAction::Play(_)
+                    | PlaybackAction::PlayFromQueue(..)
+                    | PlaybackAction::Next
+                    | PlaybackAction::Previous
+            ) {
+                state.skipped = true;
+            }
+            action
+        };
+        self.tx
+            .send(action)
+            .map_err(|_| anyhow!("Playback has stopped"))
+    }
+
+    /// Go forward through the history, or to the next track of the queue
+    pub fn next(&self) -> Result<()> {
+        let action = self.history().next(&self.playback_context);
+        self.send(action)
+    }
+
+    /// Restart the track, or go back through the history
+    pub fn previous(&self) -> Result<()> {
+        let action = self.history().previous(&self.playback_context);
+        self.send(action)
+    }
+
+    /// The gain playback goes to, `volume_level` of the playback context lags
+    /// behind it until the audio thread has applied the volume actions
+    pub fn gain(&self) -> f32 {
+        *self.gain.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// The action that changes the gain to `gain`, it has to be sent.
+    /// `ChangeVolume` is relative, so it is taken from the gain of the actions
+    /// before it and not from the playback context, which may not have them yet.
+    pub fn gain_action(&self, gain: f32) -> PlaybackAction {
+        let mut target = self.gain.lock().unwrap_or_else(|err| err.into_inner());
+        let delta = gain - *target;
+        *target = gain;
+        PlaybackAction::ChangeVolume(delta)
+    }
+
+    pub fn history(&self) -> MutexGuard<'_, History> {
+        self.history.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.lock_state().repeat
+    }
+
+    pub fn set_repeat(&self, repeat: RepeatMode) {
+        self.lock_state().repeat = repeat;
+    }
+
+    pub fn cycle_repeat(&self) {
+        let mut state = self.lock_state();
+        state.repeat = state.repeat.cycle();
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn on_last_track(&self) -> bool {
+        let queue = self.playback_context.lock_queue();
+        let track_counts: Vec<usize> = queue
+            .queue_items()
+            .iter()
+            .map(|item| item_tracks(item).len())
+            .collect();
+        is_last_track(&track_counts, queue.current_position())
+    }
+
+    /// Check for a track that ended on its own and repeat it or the queue
+    fn check(&self) {
+        self.history().update(&self.playback_context);
+        let (current, position) = {
+            let queue = self.playback_context.lock_queue();
+            (queue.current_track().clone(), queue.current_position())
+        };
+        let mut state = self.lock_state();
+        if let (Some(playing), Some(path), Some(position)) =
+            (state.playing.as_mut(), &current, position)
+        {
+            if playing.path == *path && playing.position == position {
+                playing.left_ms = length_ms(&self.playback_context)
+                    .saturating_sub(played_ms(&self.playback_context));
+                return;
+            }
+        }
+        let action = repeat_action(
+            state.repeat,
+            state.playing.as_ref(),
+            current.is_none(),
+            state.skipped,
+        );
+        state.skipped = action.is_some();
+        state.playing = current.zip(position).map(|(path, position)| Playing {
+            path,
+            position,
+            left_ms: length_ms(&self.playback_context),
+        });
+        drop(state);
+        if let Some(action) = action {
+            let _ = self.tx.send(action);
+        }
+    }
+}
+
+/// What `Next` does with the repeat mode, on the last track repeat all starts the queue again
+fn next_action(repeat: RepeatMode, on_last_track: bool) -> PlaybackAction {
+    if repeat == RepeatMode::All && on_last_track {
+        PlaybackAction::PlayFromQueue(0, 0)
+    } else {
+        PlaybackAction::Next
+    }
+}
+
+/// Returns true if `current` is the last track, `track_counts` has the number
+/// of tracks of every queue item
+fn is_last_track(track_counts: &[usize], current: Option<(usize, usize)>) -> bool {
+    let last = track_counts
+        .len()
+        .checked_sub(1)
+        .map(|item| (item, track_counts[item].saturating_sub(1)));
+    last.is_some() && current == last
+}
+
+/// What to do after the track changed from `playing`. Only a track that ended
+/// on its own is repeated, not one that an action skipped or that was stopped
+/// with time left.
+fn repeat_action(
+    repeat: RepeatMode,
+    playing: Option<&Playing>,
+    queue_ended: bool,
+    skipped: bool,
+) -> Option<PlaybackAction> {
+    let playing = playing.filter(|playing| !skipped && playing.left_ms <= END_MARGIN_MS)?;
+    match repeat {
+        RepeatMode::One => Some(PlaybackAction::PlayFromQueue(
+            playing.position.0,
+            playing.position.1,
+        )),
+        RepeatMode::All if queue_ended => Some(PlaybackAction::PlayFromQueue(0, 0)),
+        _ => None,
+    }
+}
+
+fn watch(transport: Weak<Transport>) {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        match transport.upgrade() {
+            Some(transport) => transport.check(),
+            None => return,
+        }
+    }
+}
+
+/// Milliseconds played of the current track, `played` counts frames at the sample rate
+pub fn played_ms(playback_context: &ArcPlaybackContext) -> u64 {
+    playback_context.played() as u64 * 1000 / (playback_context.sample_rate() as u64).max(1)
+}
+
+/// The action that seeks to `target_ms`. Playback seeks by whole seconds, so the
+/// distance is rounded instead of both positions being cut to seconds
+pub fn seek_action(playback_context: &ArcPlaybackContext, target_ms: u64) -> PlaybackAction {
+    let played = played_ms(playback_context);
+    let seconds = |ms: u64| (ms + 500) / 1000;
+    if target_ms >= played {
+        PlaybackAction::FastForward(seconds(target_ms - played))
+    } else {
+        PlaybackAction::Rewind(seconds(played - target_ms))
+    }
+}
+
+/// Length of the current track in milliseconds
+pub fn length_ms(playback_context: &ArcPlaybackContext) -> u64 {
+    playback_context.length() as u64 * 1000 / (playback_context.sample_rate() as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(position: (usize, usize), left_ms: u64) -> Playing {
+        Playing {
+            path: PathBuf::from("/music/song.flac"),
+            position,
+            left_ms,
+        }
+    }
+
+    #[test]
+    fn repeat_cycles() {
+        assert_eq!(RepeatMode::Off.cycle(), RepeatMode::All);
+        assert_eq!(RepeatMode::All.cycle(), RepeatMode::One);
+        assert_eq!(RepeatMode::One.cycle(), RepeatMode::Off);
+    }
+
+    #[test]
+    fn last_track() {
+        assert!(is_last_track(&[1, 3], Some((1, 2))));
+        assert!(!is_last_track(&[1, 3], Some((1, 1))));
+        assert!(!is_last_track(&[1, 3], Some((0, 0))));
+        assert!(!is_last_track(&[1, 3], None));
+        assert!(!is_last_track(&[], None));
+    }
+
+    #[test]
+    fn next_on_the_last_track() {
+        assert!(matches!(
+            next_action(RepeatMode::All, true),
+            PlaybackAction::PlayFromQueue(0, 0)
+        ));
+        assert!(matches!(
+            next_action(RepeatMode::All, false),
+            PlaybackAction::Next
+        ));
+        assert!(matches!(
+            next_action(RepeatMode::Off, true),
+            PlaybackAction::Next
+        ));
+        assert!(matches!(
+            next_action(RepeatMode::One, true),
+            PlaybackAction::Next
+        ));
+    }
+
+    #[test]
+    fn repeat_at_the_end_of_a_track() {
+        let ended = playing((1, 2), END_MARGIN_MS);
+        assert!(matches!(
+            repeat_action(RepeatMode::One, Some(&ended), false, false),
+            Some(PlaybackAction::PlayFromQueue(1, 2))
+        ));
+        assert!(matches!(
+            repeat_action(RepeatMode::All, Some(&ended), true, false),
+            Some(PlaybackAction::PlayFromQueue(0, 0))
+        ));
+        // The queue goes on to the next track by itself
+        assert!(repeat_action(RepeatMode::All, Some(&ended), false, false).is_none());
+        assert!(repeat_action(RepeatMode::Off, Some(&ended), true, false).is_none());
+        assert!(repeat_action(RepeatMode::One, None, true, false).is_none());
+    }
+
+    #[test]
+    fn no_repeat_before_the_end() {
+        let stopped = playing((0, 0), END_MARGIN_MS + 1);
+        assert!(repeat_action(RepeatMode::One, Some(&stopped), false, false).is_none());
+        assert!(repeat_action(RepeatMode::All, Some(&stopped), true, false).is_none());
+        // An action changed the track
+        let ended = playing((0, 0), 0);
+        assert!(repeat_action(RepeatMode::One, Some(&ended), false, true).is_none());
+        assert!(repeat_action(RepeatMode::All, Some(&ended), true, true).is_none());
+    }
+}
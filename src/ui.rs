@@ -1,49 +1,127 @@
 use std::{
     default::Default,
     f64,
-    sync::{atomic::AtomicU8, Arc},
-    thread,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
 use anyhow::Result;
-use explorer::FileExplorer;
-use futures::executor::block_on;
+use art::CoverArt;
+use check_view::CheckDialog;
+use explorer::{File, FileExplorer};
+use history::HistoryView;
+use jobs::Jobs;
 use library_view::LibraryViewer;
-use log::error;
-use ratatui::{layout::Layout, prelude::*, widgets::LineGauge};
+use log::{error, info, warn};
+use lyrics_view::LyricsView;
+use mouse::{Clicks, Mouse, MouseKind};
+use now_playing::NowPlaying;
+use playlist_view::{ExportDialog, PlaylistPicker, PlaylistView};
+use popup::{render_gauge, render_message, PopupResult, Prompt, PromptResult};
+use queue_view::QueueView;
+use ratatui::{
+    crossterm::event::{MouseButton, MouseEvent, MouseEventKind},
+    layout::Layout,
+    prelude::*,
+    widgets::LineGauge,
+};
 use ratatui_eventInput::Input;
 use rmusic::{
-    database::Library, playback::playback_context::ArcPlaybackContext,
+    database::Library, models::Track, playback::playback_context::ArcPlaybackContext,
     playback_loop::PlaybackAction,
 };
-use rmusic_tui::settings::input::{InputMap, Media, Navigation};
-use tabs::{input_to_log_event, QueueView, TabPage, TabPages};
+use rmusic_tui::settings::{input::InputMap, playback::PlaybackSettings, Settings};
+use search_view::SearchView;
+use tabs::{input_to_log_event, TabPage, TabPages};
 use theme::Theme;
+use ui_state::{ExplorerState, UiState};
+
+use crate::{
+    control::SeekPosition,
+    playlist_file,
+    transport::ArcTransport,
+    volume::Volume,
+    watcher::{self, LibraryWatcher},
+};
 
+pub use queue_view::item_tracks;
+pub use remote::RemoteUI;
+
+mod art;
+mod check_view;
 mod explorer;
+mod fuzzy;
+mod history;
+mod jobs;
 mod library_view;
+mod lyrics_view;
+mod mouse;
+mod now_playing;
+mod playlist_view;
+mod popup;
+mod queue_view;
+mod remote;
+mod search_view;
 mod tabs;
 mod theme;
+mod ui_state;
+
+/// Unresolved playlist entries that are shown after an import, the rest is logged
+const MESSAGE_LINES: usize = 10;
 
 pub struct UI {
     tab_pages: TabPages,
     library: Library,
     input_map: InputMap,
+    playback: PlaybackSettings,
     theme: Theme,
+    transport: ArcTransport,
     playback_context: ArcPlaybackContext,
+    /// Popup that takes all input while it is open
+    popup: Option<Popup>,
+    jobs: Jobs,
+    /// Keeps the library in sync with the music folders from the settings
+    watcher: Option<LibraryWatcher>,
+    /// The watched music folders, also searched for moved files by the library check
+    music_folders: Vec<PathBuf>,
+    art: CoverArt,
+    clicks: Clicks,
+    drag: Option<Drag>,
+    volume: Volume,
+    /// The volume in percent from before muting, while muted
+    muted: Option<u8>,
+}
+
+/// What the mouse drags, from a press until the button is released
+enum Drag {
+    Progress,
+    /// Column of the press and the volume in percent at that time
+    Volume(u16, u8),
+}
+
+enum Popup {
+    PlaylistPicker(PlaylistPicker),
+    Export(ExportDialog),
+    Jobs,
+    Check(CheckDialog),
+    /// Jump to a time or a percentage of the track
+    SeekTo(Prompt),
+    Volume,
+    /// Title and text, closed by any input
+    Message(String, String),
 }
 
 impl UI {
-    pub fn new(playback_context: ArcPlaybackContext) -> Result<Self> {
-        let input_map = InputMap {
-            navigation: Navigation::default(),
-            media: Media::default(),
+    pub fn new(transport: ArcTransport, settings: Settings) -> Result<Self> {
+        let input_map = settings.keys;
+        let theme = match &settings.theme {
+            Some(theme) => Theme::load(theme)?,
+            None => Theme::default(),
         };
 
         // let artist_tab = Artists::new();
 
-        let file_exporer = FileExplorer::new()?;
+        let file_exporer = FileExplorer::with_theme(theme.clone())?;
         // file_exporer.set_filter(vec!["opus".to_string()])?;
 
         let mut library = Library::try_new()?;
@@ -51,21 +129,108 @@ impl UI {
         let tab_pages = vec![
             // TabPage::Artists(artist_tab),
             TabPage::LibraryView(LibraryViewer::new(&mut library)?),
+            TabPage::Search(SearchView::new(&mut library)?),
+            TabPage::Playlists(PlaylistView::new()),
             TabPage::FileExplorer(file_exporer),
+            TabPage::NowPlaying(NowPlaying::new()),
+            TabPage::Lyrics(LyricsView::new()),
             TabPage::Queue(QueueView::new()),
+            TabPage::History(HistoryView::new()),
             TabPage::TuiLogger(
                 tui_logger::TuiWidgetState::new().set_default_display_level(log::LevelFilter::Warn),
             ),
         ];
         let tab_pages = TabPages::new(tab_pages, &mut library)?;
 
-        Ok(Self {
+        let mut ui = Self {
             tab_pages,
             library,
             input_map,
-            theme: Theme::default(),
-            playback_context,
-        })
+            playback: settings.playback,
+            theme,
+            playback_context: transport.playback_context().clone(),
+            transport,
+            popup: None,
+            jobs: Jobs::new(),
+            watcher: watcher::start(&settings.library.watch),
+            music_folders: settings.library.watch,
+            art: CoverArt::new(settings.album_art),
+            clicks: Clicks::default(),
+            drag: None,
+            volume: Volume::new(&settings.playback),
+            muted: None,
+        };
+        if let Err(err) = ui.restore_state() {
+            warn!("Could not restore the UI state: {err:#}");
+        }
+        Ok(ui)
+    }
+
+    /// Put back the state of the last run, things that are gone are skipped
+    fn restore_state(&mut self) -> Result<()> {
+        let state = UiState::load()?;
+        for tab_page in self.tab_pages.iter_mut() {
+            match tab_page {
+                TabPage::LibraryView(library_view) => {
+                    if let Some(library_view_state) = &state.library_view {
+                        library_view.restore_state(library_view_state, &mut self.library)?;
+                    }
+                }
+                TabPage::FileExplorer(file_explorer) => {
+                    if let Some(explorer_state) = &state.explorer {
+                        file_explorer.set_show_hidden(explorer_state.show_hidden)?;
+                        file_explorer.set_cwd(explorer_state.cwd.clone())?;
+                    }
+                }
+                _ => (),
+            }
+        }
+        if let Some(name) = &state.active_tab {
+            self.tab_pages.select_by_name(name, &mut self.library)?;
+        }
+        Ok(())
+    }
+
+    /// Save the state to restore on the next run
+    pub fn save_state(&self) -> Result<()> {
+        let mut state = UiState {
+            active_tab: Some(self.tab_pages.active_tab().tab_name().to_string()),
+            ..Default::default()
+        };
+        for tab_page in self.tab_pages.iter() {
+            match tab_page {
+                TabPage::LibraryView(library_view) => {
+                    state.library_view = Some(library_view.state());
+                }
+                TabPage::FileExplorer(file_explorer) => {
+                    state.explorer = Some(ExplorerState {
+                        cwd: file_explorer.cwd().clone(),
+                        show_hidden: file_explorer.show_hidden(),
+                    });
+                }
+                _ => (),
+            }
+        }
+        state.save()
+    }
+
+    /// Ask the terminal which graphics it supports, has to be done after the terminal is set up
+    pub fn detect_graphics(&mut self) {
+        self.art.detect();
+    }
+
+    /// Work that is done every frame, like picking up changes to the library
+    pub fn update(&mut self) {
+        let imported = self.jobs.poll_finished();
+        let watched = self
+            .watcher
+            .as_ref()
+            .is_some_and(LibraryWatcher::take_changed);
+        if imported || watched {
+            if let Err(err) = self.tab_pages.refresh(&mut self.library) {
+                error!("Error while refreshing the library: {err}");
+            }
+        }
     }
 
     pub fn handle_input<I>(&mut self, input: I) -> Result<Option<PlaybackAction>>
@@ -75,6 +240,34 @@ impl UI {
         let input: Input = input.into();
         let mut playback_action: Option<PlaybackAction> = None;
         let navigation = &self.input_map.navigation;
+        if self.popup.is_some() {
+            return Ok(self.handle_popup_input(&input));
+        }
+        if navigation.jobs.contains(&input) && !self.is_typing() {
+            self.popup = Some(Popup::Jobs);
+            return Ok(playback_action);
+        }
+        if navigation.library_check.contains(&input) && !self.is_typing() {
+            match CheckDialog::new(
+                self.music_folders.clone(),
+                &mut self.library,
+                &self.input_map.dialog,
+            ) {
+                Ok(check_dialog) => self.popup = Some(Popup::Check(check_dialog)),
+                Err(err) => error!("Could not check the library: {err:#}"),
+            }
+            return Ok(playback_action);
+        }
+        if navigation.playlist_export.contains(&input) && !self.is_typing() {
+            if let Some((name, tracks)) = self.export_tracks() {
+                self.popup = Some(Popup::Export(ExportDialog::new(
+                    &name,
+                    tracks,
+                    &self.input_map.dialog,
+                )));
+                return Ok(playback_action);
+            }
+        }
         // State input
         match &mut self.tab_pages.active_tab_mut() {
             TabPage::Artists(artists) => artists.handle_input(input, navigation),
@@ -87,69 +280,133 @@ impl UI {
                     }
                 };
                 if let Some(file) = file {
-                    if file.is_dir() {
-                        let progress = Arc::new(AtomicU8::new(0));
-                        let mut db = self.library.try_clone()?;
-                        let path = file.path().to_path_buf();
-                        thread::spawn(move || {
-                            if let Err(err) = block_on(db.add_folder_rec(&path, &progress)) {
-                                error!("Error while adding folder to library: {:?}", err);
-                            }
-                        });
-                    } else if let Err(err) = self.library.add_file(file.path()) {
-                        error!("Error while adding file to library: {:?}", err);
-                    }
+                    let file = file.clone();
+                    self.open_file(&file);
                 }
             }
             TabPage::LibraryView(library_view) => {
-                let action = match library_view.handle_input(input, navigation, &mut self.library) {
+                let action = match library_view.handle_input(
+                    input,
+                    navigation,
+                    &self.input_map.dialog,
+                    &mut self.library,
+                ) {
                     Ok(action) => action,
                     Err(err) => {
                         error!("Error while handeling library_view input: {err}");
                         return Ok(playback_action);
                     }
                 };
-                match action {
-                    library_view::Action::Play(queue_item) => {
-                        playback_action = Some(PlaybackAction::Play(queue_item));
+                playback_action = self.handle_library_action(action);
+            }
+            TabPage::Search(search_view) => {
+                let action = match search_view.handle_input(
+                    input,
+                    navigation,
+                    &self.input_map.dialog,
+                    &mut self.library,
+                ) {
+                    Ok(action) => action,
+                    Err(err) => {
+                        error!("Error while handeling search input: {err:#}");
+                        return Ok(playback_action);
                     }
-                    library_view::Action::Queue(queue_item, flatten) => {
-                        //TODO:
-                        // self.playback_context
-                        //     .lock_queue()
-                        //     .append_queue_item(queue_item, flatten);
+                };
+                playback_action = self.handle_library_action(action);
+            }
+            TabPage::Playlists(playlist_view) => {
+                let action = match playlist_view.handle_input(
+                    input,
+                    navigation,
+                    &self.input_map.dialog,
+                    &mut self.library,
+                ) {
+                    Ok(action) => action,
+                    Err(err) => {
+                        error!("Error while handeling playlist input: {err}");
+                        return Ok(playback_action);
                     }
-                    library_view::Action::None => (),
-                }
+                };
+                playback_action = self.handle_library_action(action);
             }
             TabPage::TuiLogger(tui_widget_state) => {
                 if let Some(event) = input_to_log_event(input, navigation) {
                     tui_widget_state.transition(event);
                 }
             }
-            TabPage::Queue(queue_view) => queue_view.handle_input(input, navigation),
+            TabPage::Queue(queue_view) => {
+                playback_action = match queue_view.handle_input(
+                    input,
+                    navigation,
+                    &self.playback_context,
+                    &mut self.library,
+                ) {
+                    Ok(action) => action,
+                    Err(err) => {
+                        error!("Error while editing the queue: {err:#}");
+                        return Ok(playback_action);
+                    }
+                };
+            }
+            TabPage::Lyrics(lyrics_view) => {
+                playback_action =
+                    lyrics_view.handle_input(input, navigation, &self.playback_context)
+            }
+            TabPage::History(history_view) => {
+                playback_action = match history_view.handle_input(
+                    input,
+                    navigation,
+                    &self.transport,
+                    &mut self.library,
+                ) {
+                    Ok(action) => action,
+                    Err(err) => {
+                        error!("Error while handeling history input: {err}");
+                        return Ok(playback_action);
+                    }
+                };
+            }
+            TabPage::NowPlaying(_) => (),
         }
-        if playback_action.is_some() {
+        if playback_action.is_some() || self.is_typing() {
             return Ok(playback_action);
         }
         // General input
         let media = &self.input_map.media;
+        let played = self.playback_context.played_sec();
         if media.playpause.contains(&input) {
             playback_action = Some(PlaybackAction::PlayPause);
         } else if media.volume_up.contains(&input) {
-            playback_action = Some(PlaybackAction::ChangeVolume(0.02))
+            playback_action = Some(self.change_volume(self.volume.step()))
         } else if media.volume_down.contains(&input) {
-            playback_action = Some(PlaybackAction::ChangeVolume(-0.02))
+            playback_action = Some(self.change_volume(-self.volume.step()))
+        } else if media.mute.contains(&input) {
+            playback_action = Some(self.toggle_mute())
+        } else if media.volume_popup.contains(&input) {
+            self.popup = Some(Popup::Volume);
+            return Ok(playback_action);
         } else if media.fast_forward.contains(&input) {
-            playback_action = Some(PlaybackAction::FastForward(5))
+            playback_action = Some(PlaybackAction::FastForward(self.playback.seek_step))
         } else if media.rewind.contains(&input) {
-            playback_action = Some(PlaybackAction::Rewind(5))
+            playback_action = Some(PlaybackAction::Rewind(self.playback.seek_step))
+        } else if media.fast_forward_large.contains(&input) {
+            playback_action = Some(PlaybackAction::FastForward(self.playback.seek_step_large))
+        } else if media.rewind_large.contains(&input) {
+            playback_action = Some(PlaybackAction::Rewind(self.playback.seek_step_large))
+        } else if media.seek_to.contains(&input) {
+            self.popup = Some(Popup::SeekTo(Prompt::new("Go to time or %", "")));
+            return Ok(playback_action);
         } else if media.shuffle.contains(&input) {
             self.playback_context.lock_queue().cycle_shuffle();
         } else if media.repeat.contains(&input) {
-            //TODO:
-            // let repeat = &mut self.playback_context.lock_queue().queue_options.repeat;
-            // *repeat = !*repeat;
+            self.transport.cycle_repeat();
+        } else if media.next.contains(&input) {
+            self.transport.next()?;
+        } else if media.previous.contains(&input) {
+            self.transport.previous()?;
+        } else if let Some(tenths) = media.seek_tenths(&input) {
+            let length = self.playback_context.length_sec();
+            playback_action = Some(SeekPosition::Absolute(length * tenths / 10).to_action(played));
         }
 
         if playback_action.is_some() {
@@ -161,6 +418,393 @@ impl UI {
         Ok(playback_action)
     }
 
+    fn handle_library_action(&mut self, action: library_view::Action) -> Option<PlaybackAction> {
+        match action {
+            library_view::Action::Play(queue_item) => {
+                return Some(PlaybackAction::Play(queue_item));
+            }
+            library_view::Action::Queue(queue_item, flatten) => {
+                self.playback_context
+                    .lock_queue()
+                    .append_queue_item(queue_item, flatten);
+            }
+            library_view::Action::QueueNext(queue_item, flatten) => {
+                self.playback_context
+                    .lock_queue()
+                    .insert_queue_item_next(queue_item, flatten);
+            }
+            library_view::Action::AddToPlaylist(queue_item) => {
+                match PlaylistPicker::new(queue_item, &mut self.library) {
+                    Ok(playlist_picker) => {
+                        self.popup = Some(Popup::PlaylistPicker(playlist_picker))
+                    }
+                    Err(err) => error!("Error while loading playlists: {err}"),
+                }
+            }
+            library_view::Action::PlayAll(queue_items) => {
+                if queue_items.is_empty() {
+                    return None;
+                }
+                let mut queue = self.playback_context.lock_queue();
+                queue.clear_queue();
+                for queue_item in queue_items {
+                    queue.append_queue_item(queue_item, true);
+                }
+                return Some(PlaybackAction::PlayFromQueue(0, 0));
+            }
+            library_view::Action::QueueAll(queue_items) => {
+                let mut queue = self.playback_context.lock_queue();
+                for queue_item in queue_items {
+                    queue.append_queue_item(queue_item, true);
+                }
+            }
+            library_view::Action::QueueAllNext(queue_items) => {
+                // Inserted in reverse, so the first one ends up next
+                let mut queue = self.playback_context.lock_queue();
+                for queue_item in queue_items.into_iter().rev() {
+                    queue.insert_queue_item_next(queue_item, true);
+                }
+            }
+            library_view::Action::None => (),
+        }
+        None
+    }
+
+    fn handle_popup_input(&mut self, input: &Input) -> Option<PlaybackAction> {
+        let navigation = &self.input_map.navigation;
+        let mut playback_action = None;
+        let result = match &mut self.popup {
+            None => return None,
+            Some(Popup::PlaylistPicker(playlist_picker)) => playlist_picker
+                .handle_input(input, navigation, &self.input_map.dialog, &mut self.library)
+                .map(|done| {
+                    if done {
+                        PopupResult::Close
+                    } else {
+                        PopupResult::Open
+                    }
+                }),
+            Some(Popup::Export(export_dialog)) => {
+                export_dialog.handle_input(input, navigation, &self.input_map.dialog)
+            }
+            Some(Popup::Jobs) => Ok(self.jobs.handle_input(input, navigation)),
+            Some(Popup::Check(check_dialog)) => {
+                let result = check_dialog.handle_input(input, navigation, &mut self.library);
+                // A report means entries were fixed
+                if let Ok(PopupResult::Report(..)) = result {
+                    if let Err(err) = self.tab_pages.refresh(&mut self.library) {
+                        error!("Error while refreshing the library: {err}");
+                    }
+                }
+                result
+            }
+            Some(Popup::SeekTo(prompt)) => {
+                match prompt.handle_input(input, navigation, &self.input_map.dialog) {
+                    PromptResult::Editing => Ok(PopupResult::Open),
+                    PromptResult::Cancelled => Ok(PopupResult::Close),
+                    PromptResult::Done(text) => {
+                        SeekPosition::parse_in_track(&text, self.playback_context.length_sec()).map(
+                            |position| {
+                                let played = self.playback_context.played_sec();
+                                playback_action = Some(position.to_action(played));
+                                PopupResult::Close
+                            },
+                        )
+                    }
+                }
+            }
+            Some(Popup::Volume) => {
+                let (result, action) = self.handle_volume_input(input);
+                playback_action = action;
+                Ok(result)
+            }
+            Some(Popup::Message(..)) => Ok(PopupResult::Close),
+        };
+        match result {
+            Ok(PopupResult::Open) => (),
+            Ok(PopupResult::Close) => self.popup = None,
+            Ok(PopupResult::Report(title, text)) => self.popup = Some(Popup::Message(title, text)),
+            Err(err) => {
+                error!("{err:#}");
+                self.popup = Some(Popup::Message("Error".to_string(), format!("{err:#}")));
+            }
+        }
+        playback_action
+    }
+
+    /// The volume popup takes the volume bindings, up and down change the volume too
+    fn handle_volume_input(&mut self, input: &Input) -> (PopupResult, Option<PlaybackAction>) {
+        let navigation = &self.input_map.navigation;
+        let media = &self.input_map.media;
+        let step = self.volume.step();
+        let action = if media.volume_up.contains(input) || navigation.list_up.contains(input) {
+            self.change_volume(step)
+        } else if media.volume_down.contains(input) || navigation.list_down.contains(input) {
+            self.change_volume(-step)
+        } else if media.mute.contains(input) {
+            self.toggle_mute()
+        } else if navigation.cancel.contains(input)
+            || navigation.list_select.contains(input)
+            || media.volume_popup.contains(input)
+        {
+            return (PopupResult::Close, None);
+        } else {
+            return (PopupResult::Open, None);
+        };
+        (PopupResult::Open, Some(action))
+    }
+
+    /// Change the volume by percentage points, this ends muting
+    fn change_volume(&mut self, points: i16) -> PlaybackAction {
+        let percent = i16::from(self.volume_percent()) + points;
+        self.muted = None;
+        self.volume
+            .set(percent.clamp(0, 100) as u8, &self.transport)
+    }
+
+    /// Mute, or go back to the volume from before muting
+    fn toggle_mute(&mut self) -> PlaybackAction {
+        if self.is_muted() {
+            let percent = self.volume_percent();
+            self.muted = None;
+            self.volume.set(percent, &self.transport)
+        } else {
+            self.muted = Some(self.volume.percent(&self.transport));
+            self.volume.set(0, &self.transport)
+        }
+    }
+
+    /// Muted and not turned up since, for example through the control socket
+    fn is_muted(&self) -> bool {
+        self.muted.is_some() && self.volume.percent(&self.transport) == 0
+    }
+
+    /// The volume in percent, the one from before muting while muted
+    fn volume_percent(&self) -> u8 {
+        match self.muted {
+            Some(percent) if self.is_muted() => percent,
+            _ => self.volume.percent(&self.transport),
+        }
+    }
+
+    /// The volume in the status line
+    fn volume_label(&self) -> String {
+        if self.is_muted() {
+            "mute".to_string()
+        } else {
+            format!("{}%", self.volume.percent(&self.transport))
+        }
+    }
+
+    /// Mouse input, `area` is the area the UI was last drawn in
+    pub fn handle_mouse(&mut self, event: MouseEvent, area: Rect) -> Option<PlaybackAction> {
+        if self.popup.is_some() {
+            return None;
+        }
+        let rects = UI::layout().split(area);
+        let line_rects = UI::layout_status_line().split(rects[3]);
+        let (gauge_rect, volume_rect) = (line_rects[0], line_rects[1]);
+        let position = Position::new(event.column, event.row);
+        match event.kind {
+            MouseEventKind::Up(_) => self.drag = None,
+            MouseEventKind::Drag(MouseButton::Left) => {
+                return match self.drag {
+                    Some(Drag::Progress) => Some(self.seek_to_column(event.column, gauge_rect)),
+                    Some(Drag::Volume(column, percent)) => {
+                        // Every column is a step of a volume key
+                        let moved = event.column as i16 - column as i16;
+                        let target = i16::from(percent) + moved * self.volume.step();
+                        self.muted = None;
+                        Some(self.volume.set(target.clamp(0, 100) as u8, &self.transport))
+                    }
+                    None => None,
+                };
+            }
+            MouseEventKind::Down(MouseButton::Left) if gauge_rect.contains(position) => {
+                self.drag = Some(Drag::Progress);
+                return Some(self.seek_to_column(event.column, gauge_rect));
+            }
+            MouseEventKind::Down(MouseButton::Left) if volume_rect.contains(position) => {
+                self.drag = Some(Drag::Volume(event.column, self.volume_percent()));
+            }
+            MouseEventKind::ScrollUp if volume_rect.contains(position) => {
+                return Some(self.change_volume(self.volume.step()));
+            }
+            MouseEventKind::ScrollDown if volume_rect.contains(position) => {
+                return Some(self.change_volume(-self.volume.step()));
+            }
+            // The titles are on the first line of the tab bar
+            MouseEventKind::Down(MouseButton::Left) if event.row == rects[0].y => {
+                if let Some(index) = self.tab_pages.tab_at(event.column, rects[0]) {
+                    if let Err(err) = self.tab_pages.select(index, &mut self.library) {
+                        error!("Error while switching tabs: {err}");
+                    }
+                }
+            }
+            _ if rects[1].contains(position) => {
+                let mouse = self.clicks.mouse(&event)?;
+                return self.handle_tab_mouse(&mouse, rects[1]);
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// Mouse input in the active tab, tabs without mouse support scroll with the list bindings
+    fn handle_tab_mouse(&mut self, mouse: &Mouse, area: Rect) -> Option<PlaybackAction> {
+        let theme = &self.theme;
+        match self.tab_pages.active_tab_mut() {
+            TabPage::Artists(artists) => {
+                artists.handle_mouse(mouse, area, theme);
+                return None;
+            }
+            TabPage::FileExplorer(file_explorer) => {
+                match file_explorer.handle_mouse(mouse, area) {
+                    Ok(Some(file)) => {
+                        let file = file.clone();
+                        self.open_file(&file);
+                    }
+                    Ok(None) => (),
+                    Err(err) => error!("Error while handeling file_explorer input: {err}"),
+                }
+                return None;
+            }
+            TabPage::LibraryView(library_view) => {
+                return match library_view.handle_mouse(mouse, area, theme, &mut self.library) {
+                    Ok(action) => self.handle_library_action(action),
+                    Err(err) => {
+                        error!("Error while handeling library_view input: {err}");
+                        None
+                    }
+                };
+            }
+            TabPage::Queue(queue_view) => {
+                return queue_view.handle_mouse(mouse, area, theme, &self.playback_context);
+            }
+            _ => (),
+        }
+        let navigation = &self.input_map.navigation;
+        let input = match mouse.kind {
+            MouseKind::ScrollUp => navigation.list_up.first(),
+            MouseKind::ScrollDown => navigation.list_down.first(),
+            MouseKind::Click | MouseKind::DoubleClick => None,
+        }?;
+        self.handle_input(input.clone()).unwrap_or_else(|err| {
+            error!("{err:#}");
+            None
+        })
+    }
+
+    /// Seek to the point of the progress line at `column`
+    fn seek_to_column(&self, column: u16, gauge_rect: Rect) -> PlaybackAction {
+        // The line starts one column after the label, like `LineGauge` draws it
+        let start = gauge_rect.x + Line::from(self.progress_label()).width() as u16 + 1;
+        let width = gauge_rect.right().saturating_sub(start).max(1);
+        let ratio = f64::from(column.saturating_sub(start).min(width)) / f64::from(width);
+        let length = self.playback_context.length_sec();
+        let played = self.playback_context.played_sec();
+        SeekPosition::Absolute((length as f64 * ratio) as u64).to_action(played)
+    }
+
+    /// Time in min:sec as played/total, shown in front of the progress line
+    fn progress_label(&self) -> String {
+        let time_played = Duration::from_secs(self.playback_context.played_sec());
+        let time_total = Duration::from_secs(self.playback_context.length_sec());
+        format!(
+            "{}:{:02}/{}:{:02} ",
+            time_played.as_secs() / 60,
+            time_played.as_secs() % 60,
+            time_total.as_secs() / 60,
+            time_total.as_secs() % 60,
+        )
+    }
+
+    /// Import a playlist or a directory, or add a file to the library
+    fn open_file(&mut self, file: &File) {
+        if playlist_file::Format::from_path(file.path()).is_some() {
+            self.import_playlist(file.path());
+        } else if file.is_dir() {
+            if let Err(err) = self
+                .jobs
+                .start_import(file.path().to_path_buf(), &mut self.library)
+            {
+                error!("Could not import {}: {err:#}", file.path().display());
+            }
+        } else if let Err(err) = self.library.add_file(file.path()) {
+            error!("Error while adding file to library: {:?}", err);
+        }
+    }
+
+    /// Name and tracks to export from the active tab, if it has any
+    fn export_tracks(&self) -> Option<(String, Vec<Track>)> {
+        match self.tab_pages.active_tab() {
+            TabPage::Playlists(playlist_view) => playlist_view.export_tracks(),
+            TabPage::Queue(_) => {
+                let queue = self.playback_context.lock_queue();
+                let tracks = queue
+                    .queue_items()
+                    .iter()
+                    .flat_map(item_tracks)
+                    .cloned()
+                    .collect();
+                Some(("queue".to_string(), tracks))
+            }
+            _ => None,
+        }
+    }
+
+    fn import_playlist(&mut self, path: &Path) {
+        let report = match playlist_file::import(path, &mut self.library) {
+            Ok(report) => report,
+            Err(err) => {
+                error!("Error while importing playlist: {err:#}");
+                self.popup = Some(Popup::Message("Import".to_string(), format!("{err:#}")));
+                return;
+            }
+        };
+        let mut text = match &report.playlist {
+            Some(playlist) => {
+                info!(
+                    "Imported {} tracks from {} into `{}`",
+                    report.imported,
+                    path.display(),
+                    playlist.name
+                );
+                format!(
+                    "Imported {} tracks into `{}`",
+                    report.imported, playlist.name
+                )
+            }
+            None => {
+                warn!("No track of {} is in the library", path.display());
+                "Nothing imported, no playlist was made".to_string()
+            }
+        };
+        if !report.unresolved.is_empty() {
+            text += &format!(", {} entries not found:", report.unresolved.len());
+        }
+        for (index, entry) in report.unresolved.iter().enumerate() {
+            warn!(
+                "{}:{}: `{}` is not in the library",
+                path.display(),
+                entry.line,
+                entry.location
+            );
+            // The rest is in the log
+            if index < MESSAGE_LINES {
+                text += &format!("\nline {}: {}", entry.line, entry.location);
+            } else if index == MESSAGE_LINES {
+                text += "\n...";
+            }
+        }
+        self.popup = Some(Popup::Message("Import".to_string(), text));
+    }
+
+    /// Returns true while text is typed in the active tab or a popup is open
+    pub fn is_typing(&self) -> bool {
+        self.popup.is_some() || self.tab_pages.active_tab().is_typing()
+    }
+
     fn layout() -> Layout {
         Layout::new(
             ratatui::layout::Direction::Vertical,
@@ -179,7 +823,7 @@ impl UI {
                 Constraint::Fill(1),
                 Constraint::Length(5),
                 Constraint::Length(3),
-                Constraint::Length(2),
+                Constraint::Length(3),
             ],
         )
     }
@@ -192,13 +836,60 @@ impl Widget for &mut UI {
     {
         let rects = UI::layout().split(area);
 
-        self.tab_pages.widget().render(rects[0], buf);
+        self.tab_pages.widget(&self.theme).render(rects[0], buf);
         let mainrect = rects[1];
-        self.tab_pages
-            .active_tab_mut()
-            .render(mainrect, buf, &self.theme, &self.playback_context);
+        self.tab_pages.active_tab_mut().render(
+            mainrect,
+            buf,
+            &self.theme,
+            &self.playback_context,
+            &mut self.art,
+            &self.transport,
+        );
+        match &mut self.popup {
+            None => (),
+            Some(Popup::PlaylistPicker(playlist_picker)) => {
+                playlist_picker.render(mainrect, buf, &self.theme)
+            }
+            Some(Popup::Export(export_dialog)) => export_dialog.render(mainrect, buf, &self.theme),
+            Some(Popup::Jobs) => self.jobs.render(mainrect, buf, &self.theme),
+            Some(Popup::Check(check_dialog)) => check_dialog.render(mainrect, buf, &self.theme),
+            Some(Popup::SeekTo(prompt)) => prompt.render(mainrect, buf, &self.theme),
+            Some(Popup::Volume) => {
+                let percent = self.volume_percent();
+                let label = if self.is_muted() {
+                    format!("muted, {percent}%")
+                } else {
+                    format!("{percent}%")
+                };
+                render_gauge(
+                    mainrect,
+                    buf,
+                    &self.theme,
+                    "Volume",
+                    f64::from(percent) / 100.0,
+                    label,
+                )
+            }
+            Some(Popup::Message(title, text)) => {
+                render_message(mainrect, buf, &self.theme, title, text)
+            }
+        }
 
         // Status line
+        let mut track_rect = rects[2];
+        if let Some(status) = self.jobs.status() {
+            let [rect, jobs_rect] = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Length(status.chars().count() as u16 + 1),
+            ])
+            .areas(rects[2]);
+            track_rect = rect;
+            Line::from(status)
+                .right_aligned()
+                .style(*self.theme.status_style())
+                .render(jobs_rect, buf);
+        }
 
         Line::from(
             self.playback_context
@@ -211,22 +902,12 @@ impl Widget for &mut UI {
                 + " "
                 + &self.playback_context.sample_rate().to_string(),
         )
-        .render(rects[2], buf);
+        .style(*self.theme.status_style())
+        .render(track_rect, buf);
 
         let line_rects = UI::layout_status_line().split(rects[3]);
 
-        // Show time in min:sec and played/total
-        let label = {
-            let time_played = Duration::from_secs(self.playback_context.played_sec());
-            let time_total = Duration::from_secs(self.playback_context.length_sec());
-            format!(
-                "{}:{:02}/{}:{:02} ",
-                time_played.as_secs() / 60,
-                time_played.as_secs() % 60,
-                time_total.as_secs() / 60,
-                time_total.as_secs() % 60,
-            )
-        };
+        let label = self.progress_label();
         let played = self.playback_context.played();
         let length = self.playback_context.length();
 
@@ -238,15 +919,17 @@ impl Widget for &mut UI {
                 played as f64 / length as f64
             })
             .label(label)
-            .filled_style(Style::new().white().bold())
-            .unfilled_style(Style::new().black())
+            .filled_style(*self.theme.gauge_filled_style())
+            .unfilled_style(*self.theme.gauge_unfilled_style())
             //INFO: CHANGE this with `unfilled_char()` when going to ratatui 0.30
             .line_set(symbols::line::THICK)
             .render(line_rects[0], buf);
 
         // Volume level
-        //" 1.00" 4-5 chars
-        Line::from(format!(" {}", self.playback_context.volume_level())).render(line_rects[1], buf);
+        //" 100%" 3-5 chars
+        Line::from(format!(" {}", self.volume_label()))
+            .style(*self.theme.status_style())
+            .render(line_rects[1], buf);
         // Queue shuffle
         //" XX" 2-3 chars
         Line::from(
@@ -258,19 +941,13 @@ impl Widget for &mut UI {
                     .shuffle_type
                     .display_small(),
         )
+        .style(*self.theme.status_style())
         .render(line_rects[2], buf);
 
-        //TODO:
         // Queue repeat
-        //" R" 1-2 chars
-        // Line::from(
-        //     " ".to_string()
-        //         + if self.playback_context.lock_queue().queue_options.repeat {
-        //             "R"
-        //         } else {
-        //             ""
-        //         },
-        // )
-        // .render(line_rects[3], buf);
+        //" R1" 1-3 chars
+        Line::from(" ".to_string() + self.transport.repeat().display_small())
+            .style(*self.theme.status_style())
+            .render(line_rects[3], buf);
     }
 }
@@ -0,0 +1,296 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use anyhow::Result;
+use image::DynamicImage;
+use lofty::{file::TaggedFileExt, picture::PictureType};
+use log::{error, info, warn};
+use ratatui::{
+    prelude::*,
+    widgets::{Paragraph, Wrap},
+};
+use ratatui_image::{
+    picker::{Picker, ProtocolType},
+    protocol::Protocol,
+    Image, Resize,
+};
+use rmusic_tui::settings::AlbumArt;
+
+use super::theme::Theme;
+
+/// File names of covers next to the tracks, without extension
+const COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+/// Font size used for the half blocks when the terminal does not tell
+const FALLBACK_FONT_SIZE: (u16, u16) = (8, 16);
+/// Narrower areas do not get a cover pane
+const PANE_MIN_WIDTH: u16 = 80;
+/// Decoded covers kept by the worker
+const DECODED_LIMIT: usize = 32;
+/// Covers that are ready to draw
+const READY_LIMIT: usize = 8;
+
+/// Cover art of tracks, loaded and resized on a separate thread
+pub struct CoverArt {
+    setting: AlbumArt,
+    /// `None` until the terminal is asked, and when only text is shown
+    picker: Option<Picker>,
+    requests: Sender<Request>,
+    responses: Receiver<(Key, Cover)>,
+    /// The most recently used last
+    ready: Vec<(Key, Cover)>,
+    /// The last cover that was asked for, older requests may be skipped by the worker
+    pending: Option<Key>,
+}
+
+/// A cover for a track at a size in cells
+#[derive(Clone, PartialEq)]
+struct Key {
+    track: PathBuf,
+    size: (u16, u16),
+}
+
+struct Request {
+    key: Key,
+    picker: Option<Picker>,
+}
+
+enum Cover {
+    Missing,
+    /// Where the cover comes from, shown when images are not
+    Text(String),
+    Image(Protocol),
+}
+
+/// Where a cover was found
+enum Source {
+    Embedded(Vec<u8>),
+    File(PathBuf),
+}
+
+impl Source {
+    fn describe(&self) -> String {
+        match self {
+            Source::Embedded(_) => "Embedded cover".to_string(),
+            Source::File(path) => path
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string()),
+        }
+    }
+
+    fn decode(&self) -> Result<DynamicImage> {
+        Ok(match self {
+            Source::Embedded(data) => image::load_from_memory(data)?,
+            Source::File(path) => image::open(path)?,
+        })
+    }
+}
+
+impl CoverArt {
+    pub fn new(setting: AlbumArt) -> CoverArt {
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_responses, responses) = mpsc::channel();
+        thread::spawn(move || art_worker(worker_requests, worker_responses));
+        CoverArt {
+            setting,
+            picker: None,
+            requests,
+            responses,
+            ready: vec![],
+            pending: None,
+        }
+    }
+
+    /// Ask the terminal which graphics it supports, after the terminal is set up
+    /// and before input is read
+    pub fn detect(&mut self) {
+        if self.setting == AlbumArt::Text {
+            return;
+        }
+        let mut picker = match Picker::from_query_stdio() {
+            Ok(picker) => picker,
+            Err(err) => {
+                warn!("Could not ask the terminal for graphics support: {err}");
+                Picker::from_fontsize(FALLBACK_FONT_SIZE)
+            }
+        };
+        match self.setting {
+            AlbumArt::Halfblocks => picker.set_protocol_type(ProtocolType::Halfblocks),
+            AlbumArt::Sixel => picker.set_protocol_type(ProtocolType::Sixel),
+            AlbumArt::Kitty => picker.set_protocol_type(ProtocolType::Kitty),
+            AlbumArt::Auto | AlbumArt::Text => (),
+        }
+        info!("Drawing album art with {:?}", picker.protocol_type());
+        self.picker = Some(picker);
+    }
+
+    /// Draw the cover of `track`, or text while it is loading or when there is none
+    pub fn render(&mut self, track: Option<&Path>, area: Rect, buffer: &mut Buffer, theme: &Theme) {
+        for (key, cover) in self.responses.try_iter() {
+            if self.pending.as_ref() == Some(&key) {
+                self.pending = None;
+            }
+            self.ready.retain(|(ready, _)| *ready != key);
+            self.ready.push((key, cover));
+            if self.ready.len() > READY_LIMIT {
+                self.ready.remove(0);
+            }
+        }
+
+        let Some(track) = track else {
+            return render_text("", area, buffer, theme);
+        };
+        let key = Key {
+            track: track.to_path_buf(),
+            size: (area.width, area.height),
+        };
+        let Some(index) = self.ready.iter().position(|(ready, _)| *ready == key) else {
+            if self.pending.as_ref() != Some(&key) {
+                let request = Request {
+                    key: key.clone(),
+                    picker: self.picker.clone(),
+                };
+                if self.requests.send(request).is_err() {
+                    error!("The album art thread has stopped");
+                }
+                self.pending = Some(key);
+            }
+            return render_text("Loading cover...", area, buffer, theme);
+        };
+        // Keep the most recently used last
+        let entry = self.ready.remove(index);
+        self.ready.push(entry);
+        match &self.ready[self.ready.len() - 1].1 {
+            Cover::Missing => render_text("No cover", area, buffer, theme),
+            Cover::Text(text) => render_text(text, area, buffer, theme),
+            Cover::Image(protocol) => Image::new(protocol).render(area, buffer),
+        }
+    }
+}
+
+/// Split a pane for the cover off the right of `area`, when there is room for it
+pub fn split_pane(area: Rect) -> (Rect, Option<Rect>) {
+    if area.width < PANE_MIN_WIDTH {
+        return (area, None);
+    }
+    // Cells are about twice as high as wide, so this is roughly square
+    let width = (area.width / 4).min(area.height * 2);
+    let [rest, pane] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Length(width)]).areas(area);
+    (rest, Some(pane))
+}
+
+fn render_text(text: &str, area: Rect, buffer: &mut Buffer, theme: &Theme) {
+    let mut paragraph = Paragraph::new(text)
+        .style(*theme.style())
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    if let Some(block) = theme.block() {
+        paragraph = paragraph.block(block.clone());
+    }
+    paragraph.render(area, buffer);
+}
+
+fn art_worker(requests: Receiver<Request>, responses: Sender<(Key, Cover)>) {
+    // The most recently used last, `None` when the track has no cover
+    let mut decoded: Vec<(PathBuf, Option<DynamicImage>)> = vec![];
+    while let Ok(mut request) = requests.recv() {
+        // Only the newest request matters, the selection has moved on
+        if let Some(newer) = requests.try_iter().last() {
+            request = newer;
+        }
+        let Key { track, size } = &request.key;
+
+        let cover = match &request.picker {
+            None => match find_cover(track) {
+                Some(source) => Cover::Text(source.describe()),
+                None => Cover::Missing,
+            },
+            Some(picker) => {
+                let image = match decoded.iter().position(|(path, _)| path == track) {
+                    Some(index) => decoded.remove(index).1,
+                    None => load_cover(track),
+                };
+                let cover = match &image {
+                    None => Cover::Missing,
+                    Some(image) => {
+                        let area = Rect::new(0, 0, size.0, size.1);
+                        match picker.new_protocol(image.clone(), area, Resize::Fit(None)) {
+                            Ok(protocol) => Cover::Image(protocol),
+                            Err(err) => {
+                                warn!("Could not draw the cover of {}: {err}", track.display());
+                                Cover::Text("Could not draw cover".to_string())
+                            }
+                        }
+                    }
+                };
+                decoded.push((track.clone(), image));
+                if decoded.len() > DECODED_LIMIT {
+                    decoded.remove(0);
+                }
+                cover
+            }
+        };
+        if responses.send((request.key, cover)).is_err() {
+            // The UI is gone
+            return;
+        }
+    }
+}
+
+fn load_cover(track: &Path) -> Option<DynamicImage> {
+    let source = find_cover(track)?;
+    match source.decode() {
+        Ok(image) => Some(image),
+        Err(err) => {
+            warn!("Could not decode the cover of {}: {err}", track.display());
+            None
+        }
+    }
+}
+
+/// The embedded front cover, any embedded picture, or a cover file next to the track
+fn find_cover(track: &Path) -> Option<Source> {
+    if let Ok(tagged_file) = lofty::read_from_path(track) {
+        let pictures: Vec<_> = tagged_file
+            .tags()
+            .iter()
+            .flat_map(|tag| tag.pictures())
+            .collect();
+        let picture = pictures
+            .iter()
+            .find(|picture| picture.pic_type() == PictureType::CoverFront)
+            .or_else(|| pictures.first());
+        if let Some(picture) = picture {
+            return Some(Source::Embedded(picture.data().to_vec()));
+        }
+    }
+    cover_file(track.parent()?).map(Source::File)
+}
+
+fn cover_file(dir: &Path) -> Option<PathBuf> {
+    let files: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    // Search by name first, so cover.png wins from folder.jpg
+    COVER_NAMES.iter().find_map(|name| {
+        files
+            .iter()
+            .find(|file| {
+                let stem = file.file_stem().and_then(|stem| stem.to_str());
+                let extension = file.extension().and_then(|extension| extension.to_str());
+                stem.is_some_and(|stem| stem.eq_ignore_ascii_case(name))
+                    && extension.is_some_and(|extension| {
+                        COVER_EXTENSIONS
+                            .iter()
+                            .any(|cover| cover.eq_ignore_ascii_case(extension))
+                    })
+            })
+            .cloned()
+    })
+}
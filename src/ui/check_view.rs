@@ -0,0 +1,184 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::Result;
+use log::{error, info};
+use ratatui::prelude::*;
+use ratatui_eventInput::Input;
+use rmusic::database::Library;
+use rmusic_tui::settings::input::{format_first, Dialog, Navigation};
+
+use super::{
+    popup::{render_message, PopupResult},
+    theme::Theme,
+};
+use crate::library_check::{self, Report};
+
+/// Checks the library on a separate thread, then offers to fix what was found
+pub struct CheckDialog {
+    progress: Arc<AtomicUsize>,
+    result: Receiver<Result<Report>>,
+    state: State,
+    /// Deleting was asked for and waits for an answer
+    confirm_delete: bool,
+    keys: Dialog,
+}
+
+enum State {
+    Checking,
+    Done(Report),
+    Failed(String),
+}
+
+impl CheckDialog {
+    pub fn new(roots: Vec<PathBuf>, library: &mut Library, keys: &Dialog) -> Result<CheckDialog> {
+        let mut library = library.try_clone()?;
+        let progress = Arc::new(AtomicUsize::new(0));
+        let (tx, result) = mpsc::channel();
+        {
+            let progress = progress.clone();
+            thread::spawn(move || {
+                // The dialog may be closed before the check is done
+                let _ = tx.send(library_check::check(&mut library, &roots, &progress));
+            });
+        }
+        Ok(CheckDialog {
+            progress,
+            result,
+            state: State::Checking,
+            confirm_delete: false,
+            keys: keys.clone(),
+        })
+    }
+
+    /// Picks up the report once the check is done
+    fn poll(&mut self) {
+        if !matches!(self.state, State::Checking) {
+            return;
+        }
+        self.state = match self.result.try_recv() {
+            Ok(Ok(report)) => {
+                info!("Library check:\n{report}");
+                State::Done(report)
+            }
+            Ok(Err(err)) => {
+                error!("Could not check the library: {err:#}");
+                State::Failed(format!("Could not check the library: {err:#}"))
+            }
+            Err(TryRecvError::Disconnected) => State::Failed("The check stopped".to_string()),
+            Err(TryRecvError::Empty) => return,
+        };
+    }
+
+    /// A report result means the library has changed
+    pub fn handle_input(
+        &mut self,
+        input: &Input,
+        input_map: &Navigation,
+        library: &mut Library,
+    ) -> Result<PopupResult> {
+        if input_map.cancel.contains(input) {
+            if self.confirm_delete {
+                self.confirm_delete = false;
+                return Ok(PopupResult::Open);
+            }
+            return Ok(PopupResult::Close);
+        }
+        self.poll();
+        let report = match &self.state {
+            State::Checking => return Ok(PopupResult::Open),
+            State::Failed(_) => return Ok(PopupResult::Close),
+            State::Done(report) => report,
+        };
+        if self.confirm_delete {
+            self.confirm_delete = false;
+            if !self.keys.confirm.contains(input) {
+                return Ok(PopupResult::Open);
+            }
+            let text = format!(
+                "Deleted {} entries",
+                library_check::delete(library, report)?
+            );
+            info!("{text}");
+            return Ok(PopupResult::Report("Library check".to_string(), text));
+        }
+        let keys = &self.keys;
+        let text = if keys.check_relocate.contains(input) && !report.moved.is_empty() {
+            format!(
+                "Relocated {} tracks",
+                library_check::relocate(library, report)?
+            )
+        } else if keys.check_update.contains(input) && !report.changed.is_empty() {
+            format!("Updated {} tracks", library_check::update(library, report)?)
+        } else if keys.check_delete.contains(input) && has_deletions(report) {
+            self.confirm_delete = true;
+            return Ok(PopupResult::Open);
+        } else if keys.submit.contains(input) {
+            return Ok(PopupResult::Close);
+        } else {
+            return Ok(PopupResult::Open);
+        };
+        info!("{text}");
+        Ok(PopupResult::Report("Library check".to_string(), text))
+    }
+
+    pub fn render(&mut self, rect: Rect, buffer: &mut Buffer, theme: &Theme) {
+        self.poll();
+        let text = match &self.state {
+            State::Checking => format!(
+                "Checking the library... {} tracks",
+                self.progress.load(Ordering::Relaxed)
+            ),
+            State::Failed(err) => err.clone(),
+            State::Done(report) if report.is_clean() => {
+                format!("{}\n\nEverything is fine", report.summary())
+            }
+            State::Done(report) if self.confirm_delete => format!(
+                "{}\n\nDelete {} missing tracks and {} orphans?\n{}: delete\n{}: keep them",
+                report.summary(),
+                report.missing.len(),
+                report.orphan_releases.len() + report.orphan_artists.len(),
+                format_first(&self.keys.confirm),
+                format_first(&self.keys.deny),
+            ),
+            State::Done(report) => {
+                let keys = &self.keys;
+                let mut text = report.summary() + "\n\nThe details are in the log\n";
+                if !report.moved.is_empty() {
+                    text += &format!(
+                        "\n{}: relocate the moved tracks",
+                        format_first(&keys.check_relocate)
+                    );
+                }
+                if !report.changed.is_empty() {
+                    text += &format!(
+                        "\n{}: read the changed tracks again",
+                        format_first(&keys.check_update)
+                    );
+                }
+                if has_deletions(report) {
+                    text += &format!(
+                        "\n{}: delete the missing tracks and orphans",
+                        format_first(&keys.check_delete)
+                    );
+                }
+                text += &format!("\n{}: close", format_first(&keys.submit));
+                text
+            }
+        };
+        render_message(rect, buffer, theme, "Library check", &text);
+    }
+}
+
+fn has_deletions(report: &Report) -> bool {
+    !report.missing.is_empty()
+        || !report.orphan_releases.is_empty()
+        || !report.orphan_artists.is_empty()
+}
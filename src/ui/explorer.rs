@@ -1,10 +1,13 @@
 use std::{io::Result, path::PathBuf};
 
-use ratatui::widgets::WidgetRef;
+use ratatui::{layout::Rect, widgets::WidgetRef};
 use ratatui_eventInput::{Input, Key};
 use rmusic_tui::settings::input::Navigation;
 
-use crate::ui::Theme;
+use crate::ui::{
+    mouse::{inner_area, Mouse, MouseKind},
+    Theme,
+};
 use widget::Renderer;
 
 mod widget;
@@ -99,13 +102,7 @@ impl FileExplorer {
             }
         // Right key
         } else if key_map.list_select.contains(&input) {
-            if self.files[self.selected].path.is_dir() {
-                self.cwd = self.files.swap_remove(self.selected).path;
-                self.get_and_set_files()?;
-                self.selected = 0
-            } else {
-                return Ok(Some(self.current()));
-            }
+            return self.open_selected();
         // `H` key
         } else if key_map.hide_toggle.contains(&input) {
             self.show_hidden = !self.show_hidden;
@@ -120,6 +117,43 @@ impl FileExplorer {
         Ok(None)
     }
 
+    /// Clicks select a file, double clicks open it like `list_select`
+    pub fn handle_mouse(&mut self, mouse: &Mouse, area: Rect) -> Result<Option<&File>> {
+        let last_index = self.files.len() - 1;
+        match mouse.kind {
+            MouseKind::ScrollUp => self.selected = self.selected.saturating_sub(1),
+            MouseKind::ScrollDown => self.selected = (self.selected + 1).min(last_index),
+            MouseKind::Click | MouseKind::DoubleClick => {
+                let inner = inner_area(area, &self.theme);
+                // The list is drawn with the selected file at the bottom when it does not fit
+                let offset = (self.selected + 1).saturating_sub(usize::from(inner.height));
+                let Some(index) = mouse
+                    .row(inner, offset)
+                    .filter(|index| *index <= last_index)
+                else {
+                    return Ok(None);
+                };
+                self.selected = index;
+                if mouse.kind == MouseKind::DoubleClick {
+                    return self.open_selected();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Go into the selected directory, or return the selected file
+    fn open_selected(&mut self) -> Result<Option<&File>> {
+        if self.files[self.selected].path.is_dir() {
+            self.cwd = self.files.swap_remove(self.selected).path;
+            self.get_and_set_files()?;
+            self.selected = 0;
+            Ok(None)
+        } else {
+            Ok(Some(self.current()))
+        }
+    }
+
     pub fn set_theme(&mut self, theme: Theme) {
         self.theme = theme;
     }
@@ -129,6 +163,30 @@ impl FileExplorer {
         self.get_and_set_files()
     }
 
+    /// Change the working directory, goes up to the first directory that exists
+    pub fn set_cwd(&mut self, cwd: PathBuf) -> Result<()> {
+        let Some(cwd) = cwd.ancestors().find(|dir| dir.is_dir()) else {
+            return Ok(());
+        };
+        self.cwd = cwd.to_path_buf();
+        self.selected = 0;
+        self.get_and_set_files()
+    }
+
+    pub fn set_show_hidden(&mut self, show_hidden: bool) -> Result<()> {
+        self.show_hidden = show_hidden;
+        self.selected = 0;
+        self.get_and_set_files()
+    }
+
+    pub const fn cwd(&self) -> &PathBuf {
+        &self.cwd
+    }
+
+    pub const fn show_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
     pub fn current(&self) -> &File {
         &self.files[self.selected]
     }
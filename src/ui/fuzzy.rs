@@ -0,0 +1,81 @@
+/// A fuzzy match of a query in a text
+pub struct Match {
+    pub score: i64,
+    /// Indexes of the matched characters in the text
+    pub positions: Vec<usize>,
+}
+
+/// Match `query` against `text`, `None` if not every character of the query is found
+/// in order. Matching is case insensitive, consecutive characters and characters at
+/// the start of a word score higher.
+pub fn fuzzy_match(text: &str, query: &str) -> Option<Match> {
+    let query: Vec<char> = query.chars().collect();
+    let mut wanted = query.iter().peekable();
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(query.len());
+    let mut previous_matched = false;
+    let mut previous_char = ' ';
+    for (index, char) in text.chars().enumerate() {
+        let Some(wanted_char) = wanted.peek() else {
+            break;
+        };
+        if char.to_lowercase().eq(wanted_char.to_lowercase()) {
+            score += 1;
+            if previous_matched {
+                score += 4;
+            }
+            if !previous_char.is_alphanumeric() {
+                score += 3;
+            }
+            positions.push(index);
+            previous_matched = true;
+            wanted.next();
+        } else {
+            // Gaps between matched characters lower the score a bit
+            score -= i64::from(previous_matched);
+            previous_matched = false;
+        }
+        previous_char = char;
+    }
+    wanted
+        .peek()
+        .is_none()
+        .then_some(Match { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order() {
+        let found = fuzzy_match("Hello World", "hw").unwrap();
+        assert_eq!(found.positions, vec![0, 6]);
+        assert_eq!(found.score, 7);
+        assert!(fuzzy_match("Hello World", "wh").is_none());
+        assert!(fuzzy_match("abc", "abcd").is_none());
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let found = fuzzy_match("ÉCOLE", "éco").unwrap();
+        assert_eq!(found.positions, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn consecutive_and_word_starts_score_higher() {
+        let consecutive = fuzzy_match("abx", "ab").unwrap();
+        let gap = fuzzy_match("axb", "ab").unwrap();
+        assert!(consecutive.score > gap.score);
+        let word_start = fuzzy_match("x bar", "b").unwrap();
+        let inside = fuzzy_match("xbar", "b").unwrap();
+        assert!(word_start.score > inside.score);
+    }
+
+    #[test]
+    fn empty_query_matches() {
+        let found = fuzzy_match("anything", "").unwrap();
+        assert!(found.positions.is_empty());
+        assert_eq!(found.score, 0);
+    }
+}
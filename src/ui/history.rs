@@ -0,0 +1,128 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use ratatui::{
+    prelude::*,
+    widgets::{List, ListItem, ListState},
+};
+use ratatui_eventInput::Input;
+use rmusic::{database::Library, playback_loop::PlaybackAction};
+use rmusic_tui::settings::input::Navigation;
+
+use super::theme::Theme;
+use crate::{history::History, transport::Transport};
+
+/// The recently played tracks, the newest at the top
+pub struct HistoryView {
+    list_state: ListState,
+}
+
+impl HistoryView {
+    pub fn new() -> HistoryView {
+        HistoryView {
+            list_state: ListState::default(),
+        }
+    }
+
+    /// The index in the history of the selected row
+    fn selected(&self, history: &History) -> Option<usize> {
+        let row = self.list_state.selected()?;
+        history.entries().len().checked_sub(row + 1)
+    }
+
+    pub fn handle_input<I>(
+        &mut self,
+        input: I,
+        input_map: &Navigation,
+        transport: &Transport,
+        library: &mut Library,
+    ) -> Result<Option<PlaybackAction>>
+    where
+        I: Into<Input>,
+    {
+        let input: Input = input.into();
+        if input_map.list_down.contains(&input) {
+            self.list_state.scroll_down_by(1);
+            return Ok(None);
+        } else if input_map.list_up.contains(&input) {
+            self.list_state.scroll_up_by(1);
+            return Ok(None);
+        }
+        let playback_context = transport.playback_context();
+        let history = transport.history();
+        let Some(index) = self.selected(&history) else {
+            return Ok(None);
+        };
+        let path = history.entries()[index].path.clone();
+        if input_map.list_select.contains(&input) || input_map.item_set.contains(&input) {
+            // Tracks that are no longer in the queue replace it, like in the library
+            if let Some(action) = history.play(index, playback_context) {
+                return Ok(Some(action));
+            }
+            drop(history);
+            let queue_item = library.queue_item_from_path(&path)?;
+            return Ok(Some(PlaybackAction::Play(queue_item)));
+        }
+        drop(history);
+        if input_map.item_add.contains(&input) {
+            let queue_item = library.queue_item_from_path(&path)?;
+            playback_context
+                .lock_queue()
+                .append_queue_item(queue_item, true);
+        } else if input_map.item_add_next.contains(&input) {
+            let queue_item = library.queue_item_from_path(&path)?;
+            playback_context
+                .lock_queue()
+                .insert_queue_item_next(queue_item, true);
+        }
+        Ok(None)
+    }
+
+    pub fn render(
+        &mut self,
+        rect: Rect,
+        buffer: &mut Buffer,
+        theme: &Theme,
+        transport: &Transport,
+    ) {
+        let history = transport.history();
+        let playing = history.playing();
+        let items = history
+            .entries()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, entry)| {
+                let item = ListItem::new(Line::from(vec![
+                    Span::raw(entry.name.as_str()),
+                    Span::raw("  "),
+                    Span::raw(played_ago(entry.played_at)).style(*theme.dir_style()),
+                ]));
+                if Some(index) == playing {
+                    item.style(*theme.playing_style())
+                } else {
+                    item
+                }
+            });
+        let mut list = List::new(items)
+            .style(*theme.item_style())
+            .highlight_spacing(theme.highlight_spacing().clone())
+            .highlight_style(*theme.highlight_item_style())
+            .highlight_symbol(theme.highlight_symbol().unwrap_or_default())
+            // TODO: make option of padding
+            .scroll_padding(3);
+        if let Some(block) = theme.block() {
+            list = list.block(block.clone());
+        }
+        StatefulWidget::render(list, rect, buffer, &mut self.list_state);
+    }
+}
+
+fn played_ago(played_at: Instant) -> String {
+    let minutes = played_at.elapsed().as_secs() / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{minutes} min ago"),
+        _ => format!("{} h ago", minutes / 60),
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use ratatui::{
+    prelude::*,
+    widgets::{Clear, List, ListItem, ListState},
+};
+use ratatui_eventInput::Input;
+use rmusic::{database::Library, models::Track};
+use rmusic_tui::settings::input::Navigation;
+
+use super::{
+    popup::{popup_area, popup_block, PopupResult},
+    theme::Theme,
+};
+use crate::scan::audio_files;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+/// Errors of a job that are shown at once, the rest is in the log
+const ERROR_LINES: usize = 10;
+
+/// Imports that run in the background, with their progress
+pub struct Jobs {
+    jobs: Vec<Job>,
+    list_state: ListState,
+}
+
+struct Job {
+    path: PathBuf,
+    started: Instant,
+    progress: Arc<Progress>,
+    cancel: Arc<AtomicBool>,
+    /// The end of the job has been picked up by `poll_finished`
+    reported: bool,
+}
+
+/// Shared between a job and its thread
+#[derive(Default)]
+struct Progress {
+    /// Number of files found, known once the folder has been scanned
+    total: AtomicUsize,
+    done: AtomicUsize,
+    errors: Mutex<Vec<String>>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    status: Status,
+    ended: Option<Instant>,
+}
+
+#[derive(Clone, Default)]
+enum Status {
+    #[default]
+    Scanning,
+    Importing,
+    Done,
+    Cancelled,
+    Failed(String),
+}
+
+impl Progress {
+    fn status(&self) -> Status {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    fn set_status(&self, status: Status) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(status, Status::Scanning | Status::Importing) {
+            state.ended = Some(Instant::now());
+        }
+        state.status = status;
+    }
+
+    fn ended(&self) -> Option<Instant> {
+        self.state.lock().unwrap().ended
+    }
+}
+
+impl Job {
+    fn is_running(&self) -> bool {
+        self.progress.ended().is_none()
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.progress
+            .ended()
+            .unwrap_or_else(Instant::now)
+            .duration_since(self.started)
+    }
+
+    fn ratio(&self) -> f64 {
+        let total = self.progress.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        self.progress.done.load(Ordering::Relaxed) as f64 / total as f64
+    }
+
+    fn description(&self) -> String {
+        let done = self.progress.done.load(Ordering::Relaxed);
+        let total = self.progress.total.load(Ordering::Relaxed);
+        let errors = self.progress.errors.lock().unwrap().len();
+        let elapsed = self.elapsed().as_secs();
+        let status = match self.progress.status() {
+            Status::Scanning => "scanning".to_string(),
+            Status::Importing => format!("{:.0}%", self.ratio() * 100.0),
+            Status::Done => "done".to_string(),
+            Status::Cancelled => "cancelled".to_string(),
+            Status::Failed(err) => format!("failed: {err}"),
+        };
+        format!(
+            "{} {status}, {done}/{total} files, {errors} errors, {}:{:02}",
+            self.path.display(),
+            elapsed / 60,
+            elapsed % 60
+        )
+    }
+}
+
+impl Jobs {
+    pub fn new() -> Jobs {
+        Jobs {
+            jobs: vec![],
+            list_state: ListState::default(),
+        }
+    }
+
+    /// Add every audio file in `path` and its subfolders to the library, on a separate thread
+    pub fn start_import(&mut self, path: PathBuf, library: &mut Library) -> Result<()> {
+        let library = library.try_clone()?;
+        let progress = Arc::new(Progress::default());
+        let cancel = Arc::new(AtomicBool::new(false));
+        {
+            let path = path.clone();
+            let progress = progress.clone();
+            let cancel = cancel.clone();
+            thread::spawn(move || import(library, &path, &progress, &cancel));
+        }
+        info!("Importing {}", path.display());
+        self.jobs.push(Job {
+            path,
+            started: Instant::now(),
+            progress,
+            cancel,
+            reported: false,
+        });
+        if self.list_state.selected().is_none() {
+            self.list_state.select(Some(0));
+        }
+        Ok(())
+    }
+
+    /// Returns true if a job ended since the last call, so the library has changed
+    pub fn poll_finished(&mut self) -> bool {
+        let mut finished = false;
+        for job in self.jobs.iter_mut().filter(|job| !job.reported) {
+            if job.is_running() {
+                continue;
+            }
+            job.reported = true;
+            finished = true;
+            let errors = job.progress.errors.lock().unwrap();
+            for error in errors.iter() {
+                warn!("Error while importing: {error}");
+            }
+            info!("Import of {}", job.description());
+        }
+        finished
+    }
+
+    /// A spinner with the progress of the running jobs, for the status line
+    pub fn status(&self) -> Option<String> {
+        let running: Vec<&Job> = self.jobs.iter().filter(|job| job.is_running()).collect();
+        let first = running.first()?;
+        let frame = first.elapsed().as_millis() / 100;
+        let spinner = SPINNER[frame as usize % SPINNER.len()];
+        let done: usize = running
+            .iter()
+            .map(|job| job.progress.done.load(Ordering::Relaxed))
+            .sum();
+        let total: usize = running
+            .iter()
+            .map(|job| job.progress.total.load(Ordering::Relaxed))
+            .sum();
+        Some(format!("{spinner} importing {done}/{total}"))
+    }
+
+    fn selected(&self) -> Option<&Job> {
+        self.jobs.get(self.list_state.selected()?)
+    }
+
+    pub fn handle_input(&mut self, input: &Input, input_map: &Navigation) -> PopupResult {
+        if input_map.cancel.contains(input) || input_map.jobs.contains(input) {
+            return PopupResult::Close;
+        }
+        if input_map.list_down.contains(input) {
+            self.list_state.select_next();
+        } else if input_map.list_up.contains(input) {
+            self.list_state.select_previous();
+        } else if input_map.item_remove.contains(input) {
+            if let Some(job) = self.selected().filter(|job| job.is_running()) {
+                info!("Cancelling the import of {}", job.path.display());
+                job.cancel.store(true, Ordering::Relaxed);
+            }
+        } else if input_map.list_clear.contains(input) {
+            self.jobs.retain(|job| job.is_running() || !job.reported);
+            self.list_state.select((!self.jobs.is_empty()).then_some(0));
+        } else if input_map.list_select.contains(input) {
+            if let Some(job) = self.selected() {
+                return PopupResult::Report(
+                    format!("Errors of {}", job.path.display()),
+                    error_text(&job.progress.errors.lock().unwrap()),
+                );
+            }
+        }
+        PopupResult::Open
+    }
+
+    pub fn render(&mut self, area: Rect, buffer: &mut Buffer, theme: &Theme) {
+        let items: Vec<ListItem> = if self.jobs.is_empty() {
+            vec![ListItem::new(
+                "No imports, add a folder from the file explorer",
+            )]
+        } else {
+            self.jobs
+                .iter()
+                .map(|job| ListItem::new(job.description()))
+                .collect()
+        };
+        let height = items.len() as u16 + 2;
+        let area = popup_area(area, area.width.saturating_sub(4), height);
+        Clear.render(area, buffer);
+        let list = List::new(items)
+            .block(popup_block("Imports", theme))
+            .style(*theme.item_style())
+            .highlight_spacing(theme.highlight_spacing().clone())
+            .highlight_style(*theme.highlight_item_style())
+            .highlight_symbol(theme.highlight_symbol().unwrap_or_default());
+        StatefulWidget::render(list, area, buffer, &mut self.list_state);
+    }
+}
+
+fn error_text(errors: &[String]) -> String {
+    if errors.is_empty() {
+        return "No errors".to_string();
+    }
+    let mut text = errors
+        .iter()
+        .take(ERROR_LINES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if errors.len() > ERROR_LINES {
+        text += &format!("\n... {} more in the log", errors.len() - ERROR_LINES);
+    }
+    text
+}
+
+fn import(mut library: Library, path: &Path, progress: &Progress, cancel: &AtomicBool) {
+    let (files, tracks) = match audio_files(path).and_then(|files| {
+        let tracks = library.find_all::<Track>()?;
+        Ok((files, tracks))
+    }) {
+        Ok(found) => found,
+        Err(err) => {
+            progress.set_status(Status::Failed(format!("{err:#}")));
+            return;
+        }
+    };
+    // Files that are already in the library are skipped, adding them again
+    // would duplicate their tracks
+    let known: HashSet<&Path> = tracks.iter().map(|track| Path::new(&track.path)).collect();
+    progress.total.store(files.len(), Ordering::Relaxed);
+    progress.set_status(Status::Importing);
+    let mut skipped = 0;
+    for file in files {
+        if cancel.load(Ordering::Relaxed) {
+            progress.set_status(Status::Cancelled);
+            return;
+        }
+        if known.contains(file.as_path()) {
+            skipped += 1;
+        } else if let Err(err) = library.add_file(&file) {
+            progress
+                .errors
+                .lock()
+                .unwrap()
+                .push(format!("{}: {err}", file.display()));
+        }
+        progress.done.fetch_add(1, Ordering::Relaxed);
+    }
+    if skipped > 0 {
+        info!(
+            "Skipped {skipped} files of {} that are already in the library",
+            path.display()
+        );
+    }
+    progress.set_status(Status::Done);
+}
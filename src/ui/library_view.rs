@@ -1,11 +1,11 @@
-use std::{cmp::min, time::Duration};
+use std::{cmp::min, path::PathBuf, time::Duration};
 
 use anyhow::Result;
 use ratatui::{
     prelude::*,
-    widgets::{Cell, Row, Table, TableState},
+    widgets::{Block, Cell, Row, Table, TableState},
 };
-use ratatui_eventInput::Input;
+use ratatui_eventInput::{Input, Key};
 use rmusic::{
     database::{
         context::TrackResult,
@@ -15,9 +15,15 @@ use rmusic::{
     models::{Artist, Release, Track},
     queue::queue_items::QueueItem,
 };
-use rmusic_tui::settings::input::Navigation;
-
-use super::theme::Theme;
+use rmusic_tui::settings::input::{Dialog, Navigation};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    art::{self, CoverArt},
+    fuzzy::fuzzy_match,
+    mouse::{Mouse, MouseKind},
+    theme::Theme,
+};
 
 pub struct LibraryViewer<A, B, C>
 where
@@ -30,6 +36,14 @@ where
     table_state_l3: TableState,
     active_list: ActiveList,
     library_view: LibraryView<A, B, C>,
+    /// Search in the active list, cleared when another list becomes active
+    search: Option<Search>,
+}
+
+struct Search {
+    query: String,
+    /// The query is being typed, all input goes to the query
+    typing: bool,
 }
 
 #[derive(PartialEq)]
@@ -39,12 +53,34 @@ enum ActiveList {
     Level3,
 }
 
+/// Selected rows of a `LibraryViewer`, kept between runs
+#[derive(Serialize, Deserialize, Default)]
+pub struct LibraryViewerState {
+    /// 0 for the first list
+    active_level: usize,
+    /// Selection of each list, starting at the first
+    selected: Vec<Selection>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Selection {
+    index: usize,
+    name: String,
+}
+
 #[derive(PartialEq)]
 pub enum Action {
     Play(QueueItem),
     // Add to queue,
     Queue(QueueItem, bool),
-    // Add to playlist,
+    // Add to queue after the current track,
+    QueueNext(QueueItem, bool),
+    // Add to playlist, chosen by the user
+    AddToPlaylist(QueueItem),
+    // Replace the queue and play from the first item
+    PlayAll(Vec<QueueItem>),
+    QueueAll(Vec<QueueItem>),
+    QueueAllNext(Vec<QueueItem>),
     None,
 }
 
@@ -56,9 +92,9 @@ impl From<()> for Action {
 
 impl<A, B, C> LibraryViewer<A, B, C>
 where
-    A: L1<B, C> + Clone,
-    B: L2<C> + Clone,
-    C: L3 + Clone,
+    A: L1<B, C> + Viewable + Clone,
+    B: L2<C> + Viewable + Clone,
+    C: L3 + Viewable + Clone,
 {
     pub fn new(library: &mut Library) -> Result<Self> {
         let mut table_state_l1 = TableState::default();
@@ -71,13 +107,20 @@ where
             table_state_l3: TableState::default(),
             library_view,
             active_list: ActiveList::Level1,
+            search: None,
         })
     }
 
+    /// Returns true while a search query is typed, so other bindings should be ignored
+    pub fn is_typing(&self) -> bool {
+        self.search.as_ref().is_some_and(|search| search.typing)
+    }
+
     pub fn handle_input<I>(
         &mut self,
         input: I,
         input_map: &Navigation,
+        dialog: &Dialog,
         library: &mut Library,
     ) -> Result<Action>
     where
@@ -87,25 +130,38 @@ where
 
         let mut action: Action = Action::None;
 
-        if input_map.list_down.contains(&input) {
+        if self.is_typing() {
+            self.handle_search_input(input, input_map, dialog);
+        } else if input_map.search.contains(&input) {
+            self.search = Some(Search {
+                query: String::new(),
+                typing: true,
+            });
+        } else if self.search.is_some() && input_map.search_next.contains(&input) {
+            self.select_match(true);
+        } else if self.search.is_some() && input_map.search_previous.contains(&input) {
+            self.select_match(false);
+        } else if self.search.is_some() && input_map.cancel.contains(&input) {
+            self.search = None;
+        } else if input_map.list_down.contains(&input) {
             self.scroll_down();
         } else if input_map.list_up.contains(&input) {
             self.scroll_up();
         } else if input_map.list_select.contains(&input) {
-            if self.active_list == ActiveList::Level3 {
-                let index = self.index_l3();
-                action = Action::Play(self.library_view.get_context_list_l3(library, index)?)
-            } else {
-                self.active_list = self.next_list_state();
-            }
+            action = self.select(library)?;
         } else if input_map.list_back.contains(&input) {
             self.active_list = self.previous_list_state();
+            self.search = None;
         } else if input_map.item_set.contains(&input) {
             action = Action::Play(self.get_context(library)?);
         } else if input_map.refresh.contains(&input) {
-            self.library_view.sync_with_database_all(library)?;
+            self.refresh(library)?;
         } else if input_map.item_add.contains(&input) {
             action = Action::Queue(self.get_context(library)?, true);
+        } else if input_map.item_add_next.contains(&input) {
+            action = Action::QueueNext(self.get_context(library)?, true);
+        } else if input_map.playlist_add.contains(&input) {
+            action = Action::AddToPlaylist(self.get_context(library)?);
         }
 
         self.sync_with_database(library)?;
@@ -113,6 +169,262 @@ where
         Ok(action)
     }
 
+    /// Go to the next list, or play from the selected track in the last list
+    fn select(&mut self, library: &mut Library) -> Result<Action> {
+        if self.active_list == ActiveList::Level3 {
+            let index = self.index_l3();
+            return Ok(Action::Play(
+                self.library_view.get_context_list_l3(library, index)?,
+            ));
+        }
+        self.active_list = self.next_list_state();
+        self.search = None;
+        Ok(Action::None)
+    }
+
+    /// Clicks select a row and make its list active, double clicks work like `list_select`.
+    /// Scrolling moves the selection of the list under the mouse.
+    pub fn handle_mouse(
+        &mut self,
+        mouse: &Mouse,
+        area: Rect,
+        theme: &Theme,
+        library: &mut Library,
+    ) -> Result<Action> {
+        let (area, _) = art::split_pane(area);
+        let rects = Self::layout().split(area);
+        let lists = [ActiveList::Level1, ActiveList::Level2, ActiveList::Level3];
+        let Some((list, rect)) = lists
+            .into_iter()
+            .zip(rects.iter())
+            .find(|(_, rect)| rect.contains(mouse.position))
+        else {
+            return Ok(Action::None);
+        };
+        let size = self.list_names(&list).len();
+        if size == 0 {
+            return Ok(Action::None);
+        }
+        if self.active_list != list {
+            self.active_list = list;
+            self.search = None;
+        }
+
+        let mut action = Action::None;
+        match mouse.kind {
+            MouseKind::ScrollUp => self.scroll_up(),
+            MouseKind::ScrollDown => self.scroll_down(),
+            MouseKind::Click | MouseKind::DoubleClick => {
+                let inner = self.block(theme).map_or(*rect, |block| block.inner(*rect));
+                let offset = self.active_list_state().offset();
+                if let Some(index) = mouse.row(inner, offset).filter(|index| *index < size) {
+                    self.active_list_state().select(Some(index));
+                    if mouse.kind == MouseKind::DoubleClick {
+                        action = self.select(library)?;
+                    }
+                }
+            }
+        }
+        self.sync_with_database(library)?;
+        Ok(action)
+    }
+
+    fn handle_search_input(&mut self, input: Input, input_map: &Navigation, dialog: &Dialog) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        if input_map.cancel.contains(&input) {
+            self.search = None;
+        } else if dialog.submit.contains(&input) {
+            search.typing = false;
+        } else if input_map.search_next.contains(&input) {
+            self.select_match(true);
+        } else if input_map.search_previous.contains(&input) {
+            self.select_match(false);
+        } else if input.key == Key::Backspace {
+            search.query.pop();
+            self.select_best_match();
+        } else if let Key::Char(char) = input.key {
+            // Ignore characters typed with ctrl or alt
+            if input == Input::new_key(input.key) {
+                search.query.push(char);
+                self.select_best_match();
+            }
+        }
+    }
+
+    /// Names of the items in the active list, in display order
+    fn active_list_names(&self) -> Vec<&str> {
+        self.list_names(&self.active_list)
+    }
+
+    /// Names of the items in a list, in display order
+    fn list_names(&self, list: &ActiveList) -> Vec<&str> {
+        let l1_index = self.table_state_l1.selected().unwrap_or(0);
+        let l2_index = self.table_state_l2.selected().unwrap_or(0);
+        match list {
+            ActiveList::Level1 => self
+                .library_view
+                .get_l1()
+                .iter()
+                .map(Viewable::search_name)
+                .collect(),
+            ActiveList::Level2 => self
+                .library_view
+                .get_l2(l1_index)
+                .iter()
+                .map(Viewable::search_name)
+                .collect(),
+            ActiveList::Level3 => self
+                .library_view
+                .get_l3((l1_index, l2_index))
+                .iter()
+                .map(Viewable::search_name)
+                .collect(),
+        }
+    }
+
+    /// Score of every item in the active list that matches the query, in display order
+    fn matches(&self) -> Vec<(usize, i64)> {
+        let Some(search) = &self.search else {
+            return vec![];
+        };
+        self.active_list_names()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, name)| {
+                fuzzy_match(name, &search.query).map(|found| (index, found.score))
+            })
+            .collect()
+    }
+
+    /// Select the match with the highest score, the first one wins a tie
+    fn select_best_match(&mut self) {
+        let best = self
+            .matches()
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, score)| *score)
+            .map(|(index, _)| index);
+        if best.is_some() {
+            self.active_list_state().select(best);
+        }
+    }
+
+    /// Select the next or previous match after the selected item, wraps around
+    fn select_match(&mut self, forward: bool) {
+        let matches: Vec<usize> = self.matches().into_iter().map(|(index, _)| index).collect();
+        let current = self.active_list_state().selected().unwrap_or(0);
+        let target = if forward {
+            matches
+                .iter()
+                .find(|index| **index > current)
+                .or(matches.first())
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|index| **index < current)
+                .or(matches.last())
+        };
+        if let Some(target) = target.copied() {
+            self.active_list_state().select(Some(target));
+        }
+    }
+
+    /// Title showing the search query and the position of the selection in the matches
+    fn search_title(&self) -> Option<String> {
+        let search = self.search.as_ref()?;
+        if search.typing {
+            return Some(format!("/{}_", search.query));
+        }
+        let matches = self.matches();
+        let selected = match self.active_list {
+            ActiveList::Level1 => self.table_state_l1.selected(),
+            ActiveList::Level2 => self.table_state_l2.selected(),
+            ActiveList::Level3 => self.table_state_l3.selected(),
+        };
+        let position = matches
+            .iter()
+            .position(|(index, _)| Some(*index) == selected)
+            .map_or("-".to_string(), |position| (position + 1).to_string());
+        Some(format!("/{} [{position}/{}]", search.query, matches.len()))
+    }
+
+    /// Read the whole library again, after it has changed
+    pub fn refresh(&mut self, library: &mut Library) -> Result<()> {
+        self.library_view.sync_with_database_all(library)?;
+        self.sync_with_database(library)
+    }
+
+    /// The selected rows, to be restored with `restore_state`
+    pub fn state(&self) -> LibraryViewerState {
+        let lists = [ActiveList::Level1, ActiveList::Level2, ActiveList::Level3];
+        let selected = lists
+            .iter()
+            .map_while(|list| {
+                let index = self.list_state(list).selected()?;
+                let name = self.list_names(list).get(index)?.to_string();
+                Some(Selection { index, name })
+            })
+            .collect();
+        LibraryViewerState {
+            active_level: lists
+                .iter()
+                .position(|list| *list == self.active_list)
+                .unwrap_or(0),
+            selected,
+        }
+    }
+
+    /// Select the rows from an earlier run. Rows are found by name, as the library
+    /// may have changed in the meantime, and by index when the name is gone.
+    pub fn restore_state(
+        &mut self,
+        state: &LibraryViewerState,
+        library: &mut Library,
+    ) -> Result<()> {
+        let lists = [ActiveList::Level1, ActiveList::Level2, ActiveList::Level3];
+        for (list, selection) in lists.iter().zip(&state.selected) {
+            let names = self.list_names(list);
+            let index = names
+                .iter()
+                .position(|name| *name == selection.name)
+                .or_else(|| {
+                    names
+                        .len()
+                        .checked_sub(1)
+                        .map(|last| selection.index.min(last))
+                });
+            self.list_state_mut(list).select(index);
+            // Loads the next list
+            self.sync_with_database(library)?;
+        }
+        self.active_list = match state.active_level {
+            0 => ActiveList::Level1,
+            1 => ActiveList::Level2,
+            _ => ActiveList::Level3,
+        };
+        // Goes back to a list that is not empty
+        self.sync_with_database(library)
+    }
+
+    fn list_state(&self, list: &ActiveList) -> &TableState {
+        match list {
+            ActiveList::Level1 => &self.table_state_l1,
+            ActiveList::Level2 => &self.table_state_l2,
+            ActiveList::Level3 => &self.table_state_l3,
+        }
+    }
+
+    fn list_state_mut(&mut self, list: &ActiveList) -> &mut TableState {
+        match list {
+            ActiveList::Level1 => &mut self.table_state_l1,
+            ActiveList::Level2 => &mut self.table_state_l2,
+            ActiveList::Level3 => &mut self.table_state_l3,
+        }
+    }
+
     fn scroll_up(&mut self) {
         let current_index = self.active_list_state().selected().unwrap_or(0);
         let target_index = if current_index == 0 {
@@ -248,14 +560,6 @@ where
         }
     }
 
-    // fn active_list_state(&self) -> &TableState {
-    //     match self.active_list {
-    //         ActiveList::Level1 => &self.table_state_l1,
-    //         ActiveList::Level2 => &self.table_state_l2,
-    //         ActiveList::Level3 => &self.table_state_l3,
-    //     }
-    // }
-
     fn next_list_state(&self) -> ActiveList {
         match self.active_list {
             ActiveList::Level1 => ActiveList::Level2,
@@ -283,8 +587,22 @@ where
         )
     }
 
-    fn style<'a>(table: Table<'a>, theme: &'a Theme) -> Table<'a> {
-        let mut table = table
+    /// The block around the active list, the search is shown in its title
+    fn block<'a>(&self, theme: &'a Theme) -> Option<Block<'a>> {
+        Self::list_block(theme, self.search_title())
+    }
+
+    fn list_block(theme: &Theme, title: Option<String>) -> Option<Block> {
+        match (theme.block(), title) {
+            (Some(block), Some(title)) => Some(block.clone().title(title)),
+            (Some(block), None) => Some(block.clone()),
+            (None, Some(title)) => Some(Block::default().title(title)),
+            (None, None) => None,
+        }
+    }
+
+    fn style<'a>(table: Table<'a>, theme: &'a Theme, title: Option<String>) -> Table<'a> {
+        let table = table
             .style(*theme.style())
             .highlight_spacing(theme.highlight_spacing().clone())
             // .highlight_style(*theme.highlight_item_style())
@@ -294,16 +612,22 @@ where
         // .scroll_padding(3)
         ;
 
-        if let Some(block) = theme.block() {
-            table = table.block(block.clone());
+        match Self::list_block(theme, title) {
+            Some(block) => table.block(block),
+            None => table,
         }
-        table
     }
 }
 
 pub trait Viewable {
     fn to_view(&self) -> Row;
     fn contrains() -> Vec<Constraint>;
+    /// The text that is matched when searching a list
+    fn search_name(&self) -> &str;
+    /// The file of the item, for items that have one
+    fn file_path(&self) -> Option<&str> {
+        None
+    }
 }
 
 fn cell_al(content: &str, alignment: Alignment) -> Cell {
@@ -321,6 +645,9 @@ impl Viewable for Artist {
     fn contrains() -> Vec<Constraint> {
         vec![Constraint::Fill(1)]
     }
+    fn search_name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl Viewable for Release {
@@ -330,6 +657,9 @@ impl Viewable for Release {
     fn contrains() -> Vec<Constraint> {
         vec![Constraint::Fill(1)]
     }
+    fn search_name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl Viewable for Track {
@@ -345,6 +675,12 @@ impl Viewable for Track {
     fn contrains() -> Vec<Constraint> {
         vec![Constraint::Percentage(80), Constraint::Percentage(20)]
     }
+    fn search_name(&self) -> &str {
+        &self.name
+    }
+    fn file_path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
 }
 
 impl<A, B, C> LibraryViewer<A, B, C>
@@ -353,15 +689,40 @@ where
     B: L2<C> + Sync + Viewable + Clone,
     C: L3 + Viewable + Clone,
 {
-    pub fn render(&mut self, area: Rect, buffer: &mut Buffer, theme: &Theme) {
+    /// The track whose cover is shown, the selected track or the first of the selected release
+    fn art_track(&self) -> Option<PathBuf> {
+        let l1 = self.table_state_l1.selected()?;
+        let l2 = self.table_state_l2.selected()?;
+        let index = match self.active_list {
+            ActiveList::Level3 => self.table_state_l3.selected().unwrap_or(0),
+            _ => 0,
+        };
+        let tracks = self.library_view.get_l3((l1, l2));
+        tracks.get(index)?.file_path().map(PathBuf::from)
+    }
+
+    pub fn render(&mut self, area: Rect, buffer: &mut Buffer, theme: &Theme, art: &mut CoverArt) {
+        let (area, art_area) = art::split_pane(area);
+        if let Some(art_area) = art_area {
+            art.render(self.art_track().as_deref(), art_area, buffer, theme);
+        }
         // TODO: scroll or wrap the text
         let rects = Self::layout().split(area);
+        let mut search_title = self.search_title();
+        let mut title = |level: ActiveList| {
+            if self.active_list == level {
+                search_title.take()
+            } else {
+                None
+            }
+        };
         let mut l1 = Self::style(
             Table::new(
                 self.library_view.get_l1().iter().map(|x| x.to_view()),
                 A::contrains(),
             ),
             theme,
+            title(ActiveList::Level1),
         );
         if self.active_list == ActiveList::Level1 {
             l1 = l1.row_highlight_style(*theme.highlight_item_style());
@@ -374,6 +735,7 @@ where
                     B::contrains(),
                 ),
                 theme,
+                title(ActiveList::Level2),
             );
             if self.active_list == ActiveList::Level2 {
                 l2 = l2.row_highlight_style(*theme.highlight_item_style());
@@ -386,6 +748,7 @@ where
                         C::contrains(),
                     ),
                     theme,
+                    title(ActiveList::Level3),
                 );
                 if self.active_list == ActiveList::Level3 {
                     l3 = l3.row_highlight_style(*theme.highlight_item_style());
@@ -0,0 +1,190 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use log::{error, warn};
+use ratatui::{
+    prelude::*,
+    widgets::{List, ListItem, ListState},
+};
+use ratatui_eventInput::Input;
+use rmusic::{playback::playback_context::ArcPlaybackContext, playback_loop::PlaybackAction};
+use rmusic_tui::settings::input::Navigation;
+
+use super::theme::Theme;
+use crate::{
+    lyrics::{self, Lyrics},
+    transport::{played_ms, seek_action},
+};
+
+/// Lyrics of the current track, synchronized lyrics follow the playback.
+/// The lyrics are read on a separate thread, tags of big files take a while.
+pub struct LyricsView {
+    /// The track the lyrics belong to
+    track: Option<PathBuf>,
+    lyrics: Option<Lyrics>,
+    /// The lyrics of the track are being read
+    loading: bool,
+    list_state: ListState,
+    /// Select the active line, turned off while the user moves through the lines
+    follow: bool,
+    requests: Sender<PathBuf>,
+    responses: Receiver<(PathBuf, Option<Lyrics>)>,
+}
+
+impl LyricsView {
+    pub fn new() -> LyricsView {
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_responses, responses) = mpsc::channel();
+        thread::spawn(move || lyrics_worker(worker_requests, worker_responses));
+        LyricsView {
+            track: None,
+            lyrics: None,
+            loading: false,
+            list_state: ListState::default(),
+            follow: true,
+            requests,
+            responses,
+        }
+    }
+
+    /// Ask for the lyrics when the track has changed, and pick them up when they are read
+    fn update(&mut self, playback_context: &ArcPlaybackContext) {
+        for (track, lyrics) in self.responses.try_iter() {
+            // Lyrics of a track that is no longer playing are not needed
+            if self.track.as_ref() == Some(&track) {
+                self.lyrics = lyrics;
+                self.loading = false;
+            }
+        }
+        let current = playback_context.lock_queue().current_track().clone();
+        if current == self.track {
+            return;
+        }
+        self.lyrics = None;
+        self.loading = false;
+        if let Some(track) = &current {
+            if self.requests.send(track.clone()).is_err() {
+                error!("The lyrics thread has stopped");
+            } else {
+                self.loading = true;
+            }
+        }
+        self.track = current;
+        self.list_state = ListState::default();
+        self.follow = true;
+    }
+
+    pub fn handle_input<I>(
+        &mut self,
+        input: I,
+        input_map: &Navigation,
+        playback_context: &ArcPlaybackContext,
+    ) -> Option<PlaybackAction>
+    where
+        I: Into<Input>,
+    {
+        let input: Input = input.into();
+        let lyrics = self.lyrics.as_ref()?;
+
+        if input_map.list_down.contains(&input) {
+            self.follow = false;
+            self.list_state.select_next();
+        } else if input_map.list_up.contains(&input) {
+            self.follow = false;
+            self.list_state.select_previous();
+        } else if input_map.cancel.contains(&input) {
+            self.follow = true;
+        } else if input_map.list_select.contains(&input) || input_map.item_set.contains(&input) {
+            // Jump to the selected line
+            let time = self
+                .list_state
+                .selected()
+                .and_then(|index| lyrics.lines.get(index))
+                .and_then(|line| line.time)?;
+            self.follow = true;
+            return Some(seek_action(playback_context, time.as_millis() as u64));
+        }
+        None
+    }
+
+    pub fn render(
+        &mut self,
+        rect: Rect,
+        buffer: &mut Buffer,
+        theme: &Theme,
+        playback_context: &ArcPlaybackContext,
+    ) {
+        self.update(playback_context);
+        let [title_rect, list_rect] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(rect);
+
+        let Some(lyrics) = &self.lyrics else {
+            let text = match self.track {
+                Some(_) if self.loading => "Reading the lyrics...",
+                Some(_) => "No lyrics, add a .lrc file next to the track",
+                None => "Nothing is playing",
+            };
+            Line::from(text)
+                .style(*theme.style())
+                .render(title_rect, buffer);
+            return;
+        };
+
+        let active = lyrics.active_line(Duration::from_millis(played_ms(playback_context)));
+        if self.follow {
+            if let Some(active) = active {
+                self.list_state.select(Some(active));
+            }
+        }
+        let title = if lyrics.synced {
+            format!("{} (synchronized)", lyrics.source)
+        } else {
+            lyrics.source.clone()
+        };
+        Line::from(title)
+            .style(*theme.style())
+            .dim()
+            .render(title_rect, buffer);
+
+        let items = lyrics.lines.iter().enumerate().map(|(index, line)| {
+            let item = ListItem::new(line.text.as_str());
+            if Some(index) == active {
+                item.style(*theme.playing_style())
+            } else {
+                item
+            }
+        });
+        let mut list = List::new(items)
+            .style(*theme.item_style())
+            .highlight_spacing(theme.highlight_spacing().clone())
+            .highlight_style(*theme.highlight_item_style())
+            .highlight_symbol(theme.highlight_symbol().unwrap_or_default())
+            // Keeps the active line in the middle
+            .scroll_padding(list_rect.height as usize / 2);
+        if let Some(block) = theme.block() {
+            list = list.block(block.clone());
+        }
+        StatefulWidget::render(list, list_rect, buffer, &mut self.list_state);
+    }
+}
+
+fn lyrics_worker(requests: Receiver<PathBuf>, responses: Sender<(PathBuf, Option<Lyrics>)>) {
+    while let Ok(mut track) = requests.recv() {
+        // Only the newest track is still playing
+        if let Some(newer) = requests.try_iter().last() {
+            track = newer;
+        }
+        let lyrics = lyrics::load(&track).unwrap_or_else(|err| {
+            warn!("Could not read the lyrics of {}: {err}", track.display());
+            None
+        });
+        if responses.send((track, lyrics)).is_err() {
+            // The view is gone
+            return;
+        }
+    }
+}
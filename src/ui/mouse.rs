@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    crossterm::event::{MouseButton, MouseEvent, MouseEventKind},
+    prelude::*,
+};
+
+use super::theme::Theme;
+
+/// Two clicks on the same cell within this time are a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+/// A mouse event for the tabs, the UI handles drags itself
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub position: Position,
+}
+
+#[derive(PartialEq)]
+pub enum MouseKind {
+    Click,
+    DoubleClick,
+    ScrollUp,
+    ScrollDown,
+}
+
+impl Mouse {
+    /// Index of the row under the mouse, in a list drawn in `inner` that is scrolled to `offset`
+    pub fn row(&self, inner: Rect, offset: usize) -> Option<usize> {
+        inner
+            .contains(self.position)
+            .then(|| offset + usize::from(self.position.y - inner.y))
+    }
+}
+
+/// Turns mouse events into `Mouse` events, crossterm does not report double clicks
+#[derive(Default)]
+pub struct Clicks {
+    last: Option<(Instant, Position)>,
+}
+
+impl Clicks {
+    pub fn mouse(&mut self, event: &MouseEvent) -> Option<Mouse> {
+        let position = Position::new(event.column, event.row);
+        let kind = match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let double = self.last.is_some_and(|(time, last)| {
+                    last == position && time.elapsed() <= DOUBLE_CLICK_TIME
+                });
+                // A third click starts again
+                self.last = (!double).then(|| (Instant::now(), position));
+                if double {
+                    MouseKind::DoubleClick
+                } else {
+                    MouseKind::Click
+                }
+            }
+            MouseEventKind::ScrollUp => MouseKind::ScrollUp,
+            MouseEventKind::ScrollDown => MouseKind::ScrollDown,
+            _ => return None,
+        };
+        Some(Mouse { kind, position })
+    }
+}
+
+/// The area inside the block of the theme, where lists draw their rows
+pub fn inner_area(area: Rect, theme: &Theme) -> Rect {
+    theme.block().map_or(area, |block| block.inner(area))
+}
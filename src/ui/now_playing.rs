@@ -0,0 +1,293 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use anyhow::Result;
+use lofty::{
+    file::{AudioFile, TaggedFileExt},
+    tag::Accessor,
+};
+use log::{error, warn};
+use ratatui::{
+    prelude::*,
+    widgets::{Gauge, List, ListItem, Paragraph},
+};
+use rmusic::playback::playback_context::ArcPlaybackContext;
+
+use super::{
+    art::{self, CoverArt},
+    queue_view::upcoming_tracks,
+    theme::Theme,
+};
+
+/// Height of the progress bar
+const PROGRESS_HEIGHT: u16 = 3;
+
+/// The current track with all its details, the progress and what plays next.
+/// The details are read on a separate thread, like the cover.
+pub struct NowPlaying {
+    /// The track that is shown
+    track: Option<PathBuf>,
+    /// Details of the track, `None` while they are read
+    info: Option<TrackInfo>,
+    requests: Sender<PathBuf>,
+    responses: Receiver<TrackInfo>,
+}
+
+/// What is read from the file of the current track
+struct TrackInfo {
+    path: PathBuf,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    year: Option<u32>,
+    genre: Option<String>,
+    track: Option<u32>,
+    track_total: Option<u32>,
+    disc: Option<u32>,
+    disc_total: Option<u32>,
+    codec: String,
+    /// In kbps
+    bitrate: Option<u32>,
+    channels: Option<u8>,
+    /// Sample rate of the file, the output may differ
+    sample_rate: Option<u32>,
+}
+
+impl TrackInfo {
+    fn read(path: &Path) -> Result<TrackInfo> {
+        let tagged_file = lofty::read_from_path(path)?;
+        let tag = tagged_file
+            .primary_tag()
+            .or_else(|| tagged_file.first_tag());
+        let properties = tagged_file.properties();
+        Ok(TrackInfo {
+            path: path.to_path_buf(),
+            title: tag.and_then(|tag| tag.title().map(Cow::into_owned)),
+            artist: tag.and_then(|tag| tag.artist().map(Cow::into_owned)),
+            album: tag.and_then(|tag| tag.album().map(Cow::into_owned)),
+            year: tag.and_then(|tag| tag.year()),
+            genre: tag.and_then(|tag| tag.genre().map(Cow::into_owned)),
+            track: tag.and_then(|tag| tag.track()),
+            track_total: tag.and_then(|tag| tag.track_total()),
+            disc: tag.and_then(|tag| tag.disk()),
+            disc_total: tag.and_then(|tag| tag.disk_total()),
+            codec: format!("{:?}", tagged_file.file_type()),
+            bitrate: properties.audio_bitrate(),
+            channels: properties.channels(),
+            sample_rate: properties.sample_rate(),
+        })
+    }
+
+    /// Only the path, for files whose tags can not be read
+    fn empty(path: &Path) -> TrackInfo {
+        TrackInfo {
+            path: path.to_path_buf(),
+            title: None,
+            artist: None,
+            album: None,
+            year: None,
+            genre: None,
+            track: None,
+            track_total: None,
+            disc: None,
+            disc_total: None,
+            codec: String::new(),
+            bitrate: None,
+            channels: None,
+            sample_rate: None,
+        }
+    }
+
+    fn lines(&self, theme: &Theme, output_sample_rate: u32) -> Vec<Line> {
+        let title = self.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+        });
+        let mut lines = vec![Line::from(title).bold()];
+        lines.push(Line::from(self.artist.clone().unwrap_or_default()));
+        let album = match (&self.album, self.year) {
+            (Some(album), Some(year)) => format!("{album} ({year})"),
+            (Some(album), None) => album.clone(),
+            (None, Some(year)) => year.to_string(),
+            (None, None) => String::new(),
+        };
+        lines.push(Line::from(album));
+
+        let mut position = vec![];
+        if let Some(track) = self.track {
+            position.push(numbered("Track", track, self.track_total));
+        }
+        if let Some(disc) = self.disc {
+            position.push(numbered("Disc", disc, self.disc_total));
+        }
+        if let Some(genre) = &self.genre {
+            position.push(genre.clone());
+        }
+        lines.push(Line::from(position.join(" · ")));
+        lines.push(Line::default());
+
+        let mut format = vec![];
+        if !self.codec.is_empty() {
+            format.push(self.codec.clone());
+        }
+        if let Some(bitrate) = self.bitrate {
+            format.push(format!("{bitrate} kbps"));
+        }
+        if let Some(channels) = self.channels {
+            format.push(format!("{channels} channels"));
+        }
+        let sample_rate = match self.sample_rate {
+            Some(sample_rate) if sample_rate != output_sample_rate => {
+                format!("{sample_rate} Hz, output {output_sample_rate} Hz")
+            }
+            _ => format!("{output_sample_rate} Hz"),
+        };
+        format.push(sample_rate);
+        lines.push(Line::from(format.join(" · ")));
+        lines.push(Line::from(self.path.display().to_string()).style(*theme.dir_style()));
+        lines
+    }
+}
+
+fn numbered(name: &str, number: u32, total: Option<u32>) -> String {
+    match total {
+        Some(total) => format!("{name} {number}/{total}"),
+        None => format!("{name} {number}"),
+    }
+}
+
+fn format_time(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+impl NowPlaying {
+    pub fn new() -> NowPlaying {
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_responses, responses) = mpsc::channel();
+        thread::spawn(move || info_worker(worker_requests, worker_responses));
+        NowPlaying {
+            track: None,
+            info: None,
+            requests,
+            responses,
+        }
+    }
+
+    /// Ask for the details when the track has changed, and pick them up when they are read
+    fn update(&mut self, current: Option<&PathBuf>) {
+        for info in self.responses.try_iter() {
+            if self.track.as_ref() == Some(&info.path) {
+                self.info = Some(info);
+            }
+        }
+        if current == self.track.as_ref() {
+            return;
+        }
+        self.info = None;
+        self.track = current.cloned();
+        if let Some(track) = &self.track {
+            if self.requests.send(track.clone()).is_err() {
+                error!("The track details thread has stopped");
+            }
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        rect: Rect,
+        buffer: &mut Buffer,
+        theme: &Theme,
+        playback_context: &ArcPlaybackContext,
+        art: &mut CoverArt,
+    ) {
+        // The queue is not kept locked, the other values of the context are read below
+        let (current, upcoming) = {
+            let queue = playback_context.lock_queue();
+            let upcoming: Vec<String> =
+                upcoming_tracks(queue.queue_items(), queue.current_position())
+                    .iter()
+                    .map(|track| track.name.clone())
+                    .collect();
+            (queue.current_track().clone(), upcoming)
+        };
+        self.update(current.as_ref());
+
+        let [top_rect, progress_rect, next_rect] = Layout::vertical([
+            Constraint::Percentage(50),
+            Constraint::Length(PROGRESS_HEIGHT),
+            Constraint::Fill(1),
+        ])
+        .areas(rect);
+
+        let (info_rect, art_rect) = art::split_pane(top_rect);
+        if let Some(art_rect) = art_rect {
+            art.render(current.as_deref(), art_rect, buffer, theme);
+        }
+        let lines = match (&self.info, &self.track) {
+            (Some(info), _) => info.lines(theme, playback_context.sample_rate()),
+            (None, Some(_)) => vec![Line::from("Reading the tags...")],
+            (None, None) => vec![Line::from("Nothing is playing")],
+        };
+        let mut paragraph = Paragraph::new(lines).style(*theme.style());
+        if let Some(block) = theme.block() {
+            paragraph = paragraph.block(block.clone());
+        }
+        paragraph.render(info_rect, buffer);
+
+        let played = playback_context.played_sec();
+        let length = playback_context.length_sec();
+        let ratio = if length == 0 {
+            0.0
+        } else {
+            (played as f64 / length as f64).min(1.0)
+        };
+        Gauge::default()
+            .ratio(ratio)
+            .label(format!(
+                "{} / {} (-{})",
+                format_time(played),
+                format_time(length),
+                format_time(length.saturating_sub(played))
+            ))
+            .gauge_style(*theme.gauge_filled_style())
+            .style(*theme.gauge_unfilled_style())
+            .use_unicode(true)
+            .render(progress_rect, buffer);
+
+        let mut items = vec![ListItem::new("Up next").style(*theme.dir_style())];
+        items.extend(
+            upcoming
+                .iter()
+                .take(next_rect.height as usize)
+                .enumerate()
+                .map(|(index, name)| ListItem::new(format!("{}. {name}", index + 1))),
+        );
+        let mut list = List::new(items).style(*theme.item_style());
+        if let Some(block) = theme.block() {
+            list = list.block(block.clone());
+        }
+        Widget::render(list, next_rect, buffer);
+    }
+}
+
+fn info_worker(requests: Receiver<PathBuf>, responses: Sender<TrackInfo>) {
+    while let Ok(mut track) = requests.recv() {
+        // Only the newest track is still playing
+        if let Some(newer) = requests.try_iter().last() {
+            track = newer;
+        }
+        let info = TrackInfo::read(&track).unwrap_or_else(|err| {
+            warn!("Could not read the tags of {}: {err}", track.display());
+            TrackInfo::empty(&track)
+        });
+        if responses.send(info).is_err() {
+            // The view is gone
+            return;
+        }
+    }
+}
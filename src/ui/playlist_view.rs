@@ -0,0 +1,537 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use ratatui::{
+    prelude::*,
+    widgets::{Clear, List, ListState},
+};
+use ratatui_eventInput::Input;
+use rmusic::{database::Library, models::Track, queue::queue_items::QueueItem};
+use rmusic_tui::settings::input::{Dialog, Navigation};
+
+use crate::{
+    playlist_file,
+    playlists::{self, Playlist},
+};
+
+use super::{
+    library_view::Action,
+    popup::{popup_area, popup_block, render_message, PopupResult, Prompt, PromptResult},
+    queue_view::item_tracks,
+    theme::Theme,
+};
+
+/// The playlists in the library, and the tracks of the selected playlist
+pub struct PlaylistView {
+    playlists: Vec<Playlist>,
+    tracks: Vec<Track>,
+    playlist_state: ListState,
+    track_state: ListState,
+    focus: Focus,
+    mode: Mode,
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Playlists,
+    Tracks,
+}
+
+enum Mode {
+    Normal,
+    Create(Prompt),
+    Rename(Prompt),
+    /// Asking before deleting the selected playlist, with the answers to show
+    ConfirmDelete(String),
+}
+
+impl PlaylistView {
+    pub fn new() -> PlaylistView {
+        PlaylistView {
+            playlists: vec![],
+            tracks: vec![],
+            playlist_state: ListState::default(),
+            track_state: ListState::default(),
+            focus: Focus::Playlists,
+            mode: Mode::Normal,
+        }
+    }
+
+    /// Returns true while a name is typed or a question is asked
+    pub fn is_typing(&self) -> bool {
+        !matches!(self.mode, Mode::Normal)
+    }
+
+    pub fn sync_with_database(&mut self, library: &mut Library) -> Result<()> {
+        self.playlists = playlists::all(library)?;
+        if self.playlists.is_empty() {
+            self.playlist_state.select(None);
+        } else {
+            let index = self.playlist_state.selected().unwrap_or(0);
+            self.playlist_state
+                .select(Some(index.min(self.playlists.len() - 1)));
+        }
+        self.sync_tracks(library)
+    }
+
+    fn sync_tracks(&mut self, library: &mut Library) -> Result<()> {
+        self.tracks = match self.selected_playlist() {
+            Some(playlist) => playlists::tracks(library, playlist)?,
+            None => vec![],
+        };
+        if self.tracks.is_empty() {
+            self.track_state.select(None);
+            self.focus = Focus::Playlists;
+        } else if let Some(index) = self.track_state.selected() {
+            self.track_state
+                .select(Some(index.min(self.tracks.len() - 1)));
+        }
+        Ok(())
+    }
+
+    /// Name and tracks of the selected playlist
+    pub fn export_tracks(&self) -> Option<(String, Vec<Track>)> {
+        let playlist = self.selected_playlist()?;
+        Some((playlist.name.clone(), self.tracks.clone()))
+    }
+
+    fn select_playlist(&mut self, id: i32, library: &mut Library) -> Result<()> {
+        if let Some(index) = self.playlists.iter().position(|playlist| playlist.id == id) {
+            self.playlist_state.select(Some(index));
+        }
+        self.sync_tracks(library)
+    }
+
+    fn selected_playlist(&self) -> Option<&Playlist> {
+        self.playlist_state
+            .selected()
+            .and_then(|index| self.playlists.get(index))
+    }
+
+    pub fn handle_input<I>(
+        &mut self,
+        input: I,
+        input_map: &Navigation,
+        dialog: &Dialog,
+        library: &mut Library,
+    ) -> Result<Action>
+    where
+        I: Into<Input>,
+    {
+        let input: Input = input.into();
+
+        match &mut self.mode {
+            Mode::Create(prompt) => match prompt.handle_input(&input, input_map, dialog) {
+                PromptResult::Editing => (),
+                PromptResult::Cancelled => self.mode = Mode::Normal,
+                PromptResult::Done(name) => {
+                    self.mode = Mode::Normal;
+                    if !name.is_empty() {
+                        let name = playlists::unique_name(library, &name)?;
+                        let playlist = playlists::create(library, &name)?;
+                        self.sync_with_database(library)?;
+                        self.select_playlist(playlist.id, library)?;
+                    }
+                }
+            },
+            Mode::Rename(prompt) => match prompt.handle_input(&input, input_map, dialog) {
+                PromptResult::Editing => (),
+                PromptResult::Cancelled => self.mode = Mode::Normal,
+                PromptResult::Done(name) => {
+                    self.mode = Mode::Normal;
+                    if let Some(playlist) = self
+                        .selected_playlist()
+                        .filter(|playlist| !name.is_empty() && name != playlist.name)
+                    {
+                        let id = playlist.id;
+                        let name = playlists::unique_name(library, &name)?;
+                        playlists::rename(library, playlist, &name)?;
+                        self.sync_with_database(library)?;
+                        // The list is by name, so the playlist may have moved
+                        self.select_playlist(id, library)?;
+                    }
+                }
+            },
+            Mode::ConfirmDelete(_) => {
+                self.mode = Mode::Normal;
+                if dialog.confirm.contains(&input) {
+                    if let Some(playlist) = self.selected_playlist() {
+                        playlists::delete(library, playlist)?;
+                        self.sync_with_database(library)?;
+                    }
+                }
+            }
+            Mode::Normal => return self.handle_normal_input(input, input_map, dialog, library),
+        }
+        Ok(Action::None)
+    }
+
+    fn handle_normal_input(
+        &mut self,
+        input: Input,
+        input_map: &Navigation,
+        dialog: &Dialog,
+        library: &mut Library,
+    ) -> Result<Action> {
+        if input_map.list_down.contains(&input) {
+            self.scroll(library, true)?;
+        } else if input_map.list_up.contains(&input) {
+            self.scroll(library, false)?;
+        } else if input_map.list_select.contains(&input) {
+            if !self.tracks.is_empty() {
+                self.focus = Focus::Tracks;
+                if self.track_state.selected().is_none() {
+                    self.track_state.select(Some(0));
+                }
+            }
+        } else if input_map.list_back.contains(&input) {
+            self.focus = Focus::Playlists;
+        } else if input_map.item_new.contains(&input) {
+            self.mode = Mode::Create(Prompt::new("New playlist", ""));
+        } else if input_map.item_rename.contains(&input) {
+            if let Some(playlist) = self.selected_playlist() {
+                self.mode = Mode::Rename(Prompt::new("Rename playlist", playlist.name.clone()));
+            }
+        } else if input_map.refresh.contains(&input) {
+            self.sync_with_database(library)?;
+        } else if self.focus == Focus::Playlists && input_map.item_remove.contains(&input) {
+            if self.selected_playlist().is_some() {
+                self.mode = Mode::ConfirmDelete(dialog.answers());
+            }
+        } else if self.focus == Focus::Tracks && input_map.item_remove.contains(&input) {
+            if let Some(index) = self.track_state.selected() {
+                self.tracks.remove(index);
+                self.save_tracks(library)?;
+            }
+        } else if self.focus == Focus::Tracks && input_map.item_move_up.contains(&input) {
+            if let Some(index) = self.track_state.selected().filter(|index| *index > 0) {
+                self.tracks.swap(index, index - 1);
+                self.track_state.select(Some(index - 1));
+                self.save_tracks(library)?;
+            }
+        } else if self.focus == Focus::Tracks && input_map.item_move_down.contains(&input) {
+            if let Some(index) = self
+                .track_state
+                .selected()
+                .filter(|index| index + 1 < self.tracks.len())
+            {
+                self.tracks.swap(index, index + 1);
+                self.track_state.select(Some(index + 1));
+                self.save_tracks(library)?;
+            }
+        } else if let Some(playlist) = self.selected_playlist() {
+            // Playlists are played and queued as a whole
+            if input_map.item_set.contains(&input) {
+                return Ok(Action::PlayAll(playlists::queue_items(library, playlist)?));
+            } else if input_map.item_add.contains(&input) {
+                return Ok(Action::QueueAll(playlists::queue_items(library, playlist)?));
+            } else if input_map.item_add_next.contains(&input) {
+                return Ok(Action::QueueAllNext(playlists::queue_items(
+                    library, playlist,
+                )?));
+            }
+        }
+        Ok(Action::None)
+    }
+
+    fn scroll(&mut self, library: &mut Library, down: bool) -> Result<()> {
+        let (state, len) = match self.focus {
+            Focus::Playlists => (&mut self.playlist_state, self.playlists.len()),
+            Focus::Tracks => (&mut self.track_state, self.tracks.len()),
+        };
+        if len == 0 {
+            return Ok(());
+        }
+        let index = state.selected().unwrap_or(0);
+        let index = if down {
+            (index + 1) % len
+        } else {
+            index.checked_sub(1).unwrap_or(len - 1)
+        };
+        state.select(Some(index));
+        if self.focus == Focus::Playlists {
+            self.track_state.select(None);
+            self.sync_tracks(library)?;
+        }
+        Ok(())
+    }
+
+    fn save_tracks(&mut self, library: &mut Library) -> Result<()> {
+        if let Some(playlist) = self.selected_playlist() {
+            playlists::set_tracks(library, playlist, &self.tracks)?;
+        }
+        self.sync_tracks(library)
+    }
+
+    fn layout() -> Layout {
+        Layout::new(
+            ratatui::layout::Direction::Horizontal,
+            vec![Constraint::Fill(1), Constraint::Fill(2)],
+        )
+    }
+
+    pub fn render(&mut self, rect: Rect, buffer: &mut Buffer, theme: &Theme) {
+        let rects = Self::layout().split(rect);
+
+        let playlists = Self::style(
+            List::new(self.playlists.iter().map(|playlist| playlist.name.as_str())),
+            theme,
+            self.focus == Focus::Playlists,
+        );
+        StatefulWidget::render(playlists, rects[0], buffer, &mut self.playlist_state);
+
+        let tracks = Self::style(
+            List::new(self.tracks.iter().map(|track| track.name.as_str())),
+            theme,
+            self.focus == Focus::Tracks,
+        );
+        StatefulWidget::render(tracks, rects[1], buffer, &mut self.track_state);
+
+        match &self.mode {
+            Mode::Normal => (),
+            Mode::Create(prompt) | Mode::Rename(prompt) => prompt.render(rect, buffer, theme),
+            Mode::ConfirmDelete(answers) => {
+                let name = self.selected_playlist().map_or("", |p| p.name.as_str());
+                render_message(
+                    rect,
+                    buffer,
+                    theme,
+                    "Delete playlist",
+                    &format!("Delete `{name}`? {answers}"),
+                );
+            }
+        }
+    }
+
+    fn style<'a>(list: List<'a>, theme: &Theme, focused: bool) -> List<'a> {
+        let mut list = list
+            .style(*theme.item_style())
+            .highlight_spacing(theme.highlight_spacing().clone())
+            .highlight_symbol(theme.highlight_symbol().unwrap_or_default())
+            // TODO: make option of padding
+            .scroll_padding(3);
+        if focused {
+            list = list.highlight_style(*theme.highlight_item_style());
+        }
+        if let Some(block) = theme.block() {
+            list = list.block(block.clone());
+        }
+        list
+    }
+}
+
+/// Popup to choose the playlist that an item is added to
+pub struct PlaylistPicker {
+    queue_item: QueueItem,
+    playlists: Vec<Playlist>,
+    /// The last row is for creating a new playlist
+    list_state: ListState,
+    prompt: Option<Prompt>,
+}
+
+impl PlaylistPicker {
+    pub fn new(queue_item: QueueItem, library: &mut Library) -> Result<PlaylistPicker> {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        Ok(PlaylistPicker {
+            queue_item,
+            playlists: playlists::all(library)?,
+            list_state,
+            prompt: None,
+        })
+    }
+
+    /// Returns true when the picker is done and can be closed
+    pub fn handle_input(
+        &mut self,
+        input: &Input,
+        input_map: &Navigation,
+        dialog: &Dialog,
+        library: &mut Library,
+    ) -> Result<bool> {
+        if let Some(prompt) = &mut self.prompt {
+            match prompt.handle_input(input, input_map, dialog) {
+                PromptResult::Editing => (),
+                PromptResult::Cancelled => self.prompt = None,
+                PromptResult::Done(name) if name.is_empty() => self.prompt = None,
+                PromptResult::Done(name) => {
+                    let name = playlists::unique_name(library, &name)?;
+                    let playlist = playlists::create(library, &name)?;
+                    self.add_to(&playlist, library)?;
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+
+        let rows = self.playlists.len() + 1;
+        let index = self.list_state.selected().unwrap_or(0);
+        if input_map.cancel.contains(input) {
+            return Ok(true);
+        } else if input_map.list_down.contains(input) {
+            self.list_state.select(Some((index + 1) % rows));
+        } else if input_map.list_up.contains(input) {
+            self.list_state
+                .select(Some(index.checked_sub(1).unwrap_or(rows - 1)));
+        } else if input_map.list_select.contains(input) || input_map.item_set.contains(input) {
+            match self.playlists.get(index) {
+                Some(playlist) => {
+                    self.add_to(playlist, library)?;
+                    return Ok(true);
+                }
+                None => self.prompt = Some(Prompt::new("New playlist", "")),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Append the tracks of the item to the end of the playlist
+    fn add_to(&self, playlist: &Playlist, library: &mut Library) -> Result<()> {
+        let mut tracks = playlists::tracks(library, playlist)?;
+        tracks.extend(item_tracks(&self.queue_item).into_iter().cloned());
+        playlists::set_tracks(library, playlist, &tracks)
+    }
+
+    pub fn render(&mut self, rect: Rect, buffer: &mut Buffer, theme: &Theme) {
+        if let Some(prompt) = &self.prompt {
+            prompt.render(rect, buffer, theme);
+            return;
+        }
+        let height = (self.playlists.len() as u16 + 3).min(rect.height);
+        let area = popup_area(rect, rect.width.min(40), height);
+        Clear.render(area, buffer);
+        let rows = self
+            .playlists
+            .iter()
+            .map(|playlist| Line::raw(playlist.name.as_str()))
+            .chain([Line::raw("+ New playlist").italic()]);
+        let list = List::new(rows)
+            .block(popup_block("Add to playlist", theme))
+            .style(*theme.item_style())
+            .highlight_spacing(theme.highlight_spacing().clone())
+            .highlight_style(*theme.highlight_item_style())
+            .highlight_symbol(theme.highlight_symbol().unwrap_or_default());
+        StatefulWidget::render(list, area, buffer, &mut self.list_state);
+    }
+}
+
+/// Popup to write tracks to a playlist file
+pub struct ExportDialog {
+    tracks: Vec<Track>,
+    prompt: Prompt,
+    step: ExportStep,
+    /// The answers to show after a question
+    answers: String,
+}
+
+enum ExportStep {
+    Path,
+    /// The file exists, asking to replace it
+    Overwrite(PathBuf),
+    /// Asking for relative paths, after the file is chosen
+    Relative {
+        path: PathBuf,
+        overwrite: bool,
+    },
+}
+
+impl ExportDialog {
+    pub fn new(name: &str, tracks: Vec<Track>, dialog: &Dialog) -> ExportDialog {
+        // Show where the file ends up, a relative path would depend on where rmusic was started
+        let path = playlist_file::export_path(Path::new(&format!("{name}.m3u8")))
+            .map_or(format!("{name}.m3u8"), |path| path.display().to_string());
+        ExportDialog {
+            tracks,
+            prompt: Prompt::new("Export to (.m3u8, .m3u or .pls)", path),
+            step: ExportStep::Path,
+            answers: dialog.answers(),
+        }
+    }
+
+    pub fn handle_input(
+        &mut self,
+        input: &Input,
+        input_map: &Navigation,
+        dialog: &Dialog,
+    ) -> Result<PopupResult> {
+        match &self.step {
+            ExportStep::Path => {
+                match self.prompt.handle_input(input, input_map, dialog) {
+                    PromptResult::Editing => (),
+                    PromptResult::Cancelled => return Ok(PopupResult::Close),
+                    PromptResult::Done(path) if path.is_empty() => return Ok(PopupResult::Close),
+                    PromptResult::Done(path) => {
+                        let path = playlist_file::export_path(Path::new(&path))?;
+                        if playlist_file::Format::from_path(&path).is_none() {
+                            return Ok(PopupResult::Report(
+                                "Export".to_string(),
+                                format!("{} does not end in .m3u, .m3u8 or .pls", path.display()),
+                            ));
+                        }
+                        self.step = if path.exists() {
+                            ExportStep::Overwrite(path)
+                        } else {
+                            ExportStep::Relative {
+                                path,
+                                overwrite: false,
+                            }
+                        };
+                    }
+                }
+                Ok(PopupResult::Open)
+            }
+            ExportStep::Overwrite(path) => {
+                if dialog.confirm.contains(input) {
+                    self.step = ExportStep::Relative {
+                        path: path.clone(),
+                        overwrite: true,
+                    }
+                } else if dialog.deny.contains(input) {
+                    self.step = ExportStep::Path;
+                } else if input_map.cancel.contains(input) {
+                    return Ok(PopupResult::Close);
+                }
+                Ok(PopupResult::Open)
+            }
+            ExportStep::Relative { path, overwrite } => {
+                let relative = if dialog.confirm.contains(input) {
+                    true
+                } else if dialog.deny.contains(input) {
+                    false
+                } else if input_map.cancel.contains(input) {
+                    return Ok(PopupResult::Close);
+                } else {
+                    return Ok(PopupResult::Open);
+                };
+                playlist_file::export(path, &self.tracks, relative, *overwrite)?;
+                Ok(PopupResult::Report(
+                    "Export".to_string(),
+                    format!("Wrote {} tracks to {}", self.tracks.len(), path.display()),
+                ))
+            }
+        }
+    }
+
+    pub fn render(&self, rect: Rect, buffer: &mut Buffer, theme: &Theme) {
+        match &self.step {
+            ExportStep::Path => self.prompt.render(rect, buffer, theme),
+            ExportStep::Overwrite(path) => render_message(
+                rect,
+                buffer,
+                theme,
+                "Export",
+                &format!("{} exists, replace it? {}", path.display(), self.answers),
+            ),
+            ExportStep::Relative { .. } => render_message(
+                rect,
+                buffer,
+                theme,
+                "Export",
+                &format!(
+                    "Write paths relative to the playlist file? {}",
+                    self.answers
+                ),
+            ),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use ratatui::{
+    layout::Flex,
+    prelude::*,
+    widgets::{Block, Borders, Clear, Gauge, Paragraph},
+};
+use ratatui_eventInput::{Input, Key};
+use rmusic_tui::settings::input::{Dialog, Navigation};
+
+use super::theme::Theme;
+
+/// A rectangle of at most `width` by `height` in the center of `area`
+pub fn popup_area(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// The block around a popup, popups always have borders so they stand out
+pub fn popup_block<'a>(title: &'a str, theme: &Theme) -> Block<'a> {
+    theme
+        .block()
+        .cloned()
+        .unwrap_or_else(|| Block::default().borders(Borders::ALL))
+        .title(title)
+        .style(*theme.style())
+}
+
+/// Show text in a popup
+pub fn render_message(area: Rect, buffer: &mut Buffer, theme: &Theme, title: &str, text: &str) {
+    let width = text
+        .lines()
+        .chain([title])
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 4;
+    let height = text.lines().count().max(1) + 2;
+    let area = popup_area(area, width as u16, height as u16);
+    Clear.render(area, buffer);
+    Paragraph::new(text)
+        .block(popup_block(title, theme))
+        .render(area, buffer);
+}
+
+/// Show a gauge filled to `ratio` in a popup
+pub fn render_gauge(
+    area: Rect,
+    buffer: &mut Buffer,
+    theme: &Theme,
+    title: &str,
+    ratio: f64,
+    label: String,
+) {
+    let area = popup_area(area, area.width.min(40), 3);
+    Clear.render(area, buffer);
+    Gauge::default()
+        .block(popup_block(title, theme))
+        .ratio(ratio.clamp(0.0, 1.0))
+        .label(label)
+        .gauge_style(*theme.gauge_filled_style())
+        .style(*theme.gauge_unfilled_style())
+        .use_unicode(true)
+        .render(area, buffer);
+}
+
+pub enum PopupResult {
+    Open,
+    Close,
+    /// Close and show a message
+    Report(String, String),
+}
+
+pub enum PromptResult {
+    Editing,
+    Done(String),
+    Cancelled,
+}
+
+/// A popup to type a single line of text
+pub struct Prompt {
+    title: String,
+    text: String,
+}
+
+impl Prompt {
+    pub fn new(title: impl Into<String>, text: impl Into<String>) -> Prompt {
+        Prompt {
+            title: title.into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn handle_input(
+        &mut self,
+        input: &Input,
+        input_map: &Navigation,
+        dialog: &Dialog,
+    ) -> PromptResult {
+        if input_map.cancel.contains(input) {
+            return PromptResult::Cancelled;
+        }
+        if dialog.submit.contains(input) {
+            return PromptResult::Done(self.text.trim().to_string());
+        }
+        match input.key {
+            Key::Backspace => {
+                self.text.pop();
+            }
+            // Ignore characters typed with ctrl or alt
+            Key::Char(char) if *input == Input::new_key(input.key) => self.text.push(char),
+            _ => (),
+        }
+        PromptResult::Editing
+    }
+
+    pub fn render(&self, area: Rect, buffer: &mut Buffer, theme: &Theme) {
+        let width = area
+            .width
+            .min(60)
+            .max(self.title.chars().count() as u16 + 4);
+        let area = popup_area(area, width, 3);
+        Clear.render(area, buffer);
+        Paragraph::new(format!("{}_", self.text))
+            .block(popup_block(&self.title, theme))
+            .render(area, buffer);
+    }
+}
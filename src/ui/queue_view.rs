@@ -0,0 +1,436 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use log::warn;
+use ratatui::{
+    prelude::*,
+    widgets::{List, ListItem, ListState},
+};
+use ratatui_eventInput::Input;
+use rmusic::{
+    database::Library,
+    models::Track,
+    playback::playback_context::ArcPlaybackContext,
+    playback_loop::PlaybackAction,
+    queue::{queue_items::QueueItem, Queue},
+};
+use rmusic_tui::settings::input::Navigation;
+
+use super::{
+    art::{self, CoverArt},
+    mouse::{inner_area, Mouse, MouseKind},
+    theme::Theme,
+};
+
+/// Maximum number of queue edits that can be undone
+const UNDO_LIMIT: usize = 50;
+
+pub struct QueueView {
+    list_state: ListState,
+    /// The changes of every edit, most recent last. Undo does the opposite of
+    /// the changes, so what was queued in the meantime stays.
+    undo_stack: Vec<Vec<Change>>,
+}
+
+/// A change to the queue items
+enum Change {
+    /// The item was removed from the index
+    Removed(usize, QueueItem),
+    /// The item was moved from the first index to the second
+    Moved(usize, usize),
+    /// The item at the index was split into an item per track, to edit a single track
+    Split(usize, QueueItem),
+    /// The queue was cleared
+    Cleared(Vec<QueueItem>),
+}
+
+/// A track in the queue, albums and playlists are expanded into their tracks
+struct QueueRow<'a> {
+    /// Index of the queue item, and of the track inside that item
+    position: (usize, usize),
+    track: &'a Track,
+}
+
+impl QueueView {
+    pub fn new() -> QueueView {
+        QueueView {
+            list_state: ListState::default(),
+            undo_stack: vec![],
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        rect: Rect,
+        buffer: &mut Buffer,
+        theme: &Theme,
+        playback_context: &ArcPlaybackContext,
+        art: &mut CoverArt,
+    ) {
+        let queue = playback_context.lock_queue();
+        // The cover of the current track
+        let (rect, art_rect) = art::split_pane(rect);
+        if let Some(art_rect) = art_rect {
+            art.render(queue.current_track().as_deref(), art_rect, buffer, theme);
+        }
+        let current = queue.current_position();
+        let rows = queue_rows(queue.queue_items());
+
+        let mut list = List::new(rows.iter().map(|row| {
+            let item = ListItem::new(row.track.name.as_str());
+            if Some(row.position) == current {
+                item.style(*theme.playing_style())
+            } else {
+                item
+            }
+        }))
+        .style(*theme.item_style())
+        .highlight_spacing(theme.highlight_spacing().clone())
+        .highlight_style(*theme.highlight_item_style())
+        .highlight_symbol(theme.highlight_symbol().unwrap_or_default())
+        // TODO: make option of padding
+        .scroll_padding(3);
+
+        if let Some(block) = theme.block() {
+            list = list.block(block.clone());
+        }
+        StatefulWidget::render(list, rect, buffer, &mut self.list_state);
+    }
+
+    pub fn handle_input<I>(
+        &mut self,
+        input: I,
+        input_map: &Navigation,
+        playback_context: &ArcPlaybackContext,
+        library: &mut Library,
+    ) -> Result<Option<PlaybackAction>>
+    where
+        I: Into<Input>,
+    {
+        let input: Input = input.into();
+        let (rows, selected) = {
+            let queue = playback_context.lock_queue();
+            (
+                queue_rows(queue.queue_items()).len(),
+                self.selected(queue.queue_items()),
+            )
+        };
+
+        if input_map.list_down.contains(&input) {
+            self.list_state.scroll_down_by(1);
+        } else if input_map.list_up.contains(&input) {
+            self.list_state.scroll_up_by(1);
+        } else if input_map.list_select.contains(&input) || input_map.item_set.contains(&input) {
+            if let Some((item, track)) = selected {
+                return Ok(Some(PlaybackAction::PlayFromQueue(item, track)));
+            }
+        } else if input_map.item_remove.contains(&input) {
+            if let Some(position) = selected {
+                self.remove_track(playback_context, library, position)?;
+            }
+        } else if input_map.item_move_up.contains(&input) {
+            if let Some(row) = self.list_state.selected().filter(|row| *row > 0) {
+                self.move_track(playback_context, library, row, row - 1)?;
+            }
+        } else if input_map.item_move_down.contains(&input) {
+            if let Some(row) = self.list_state.selected().filter(|row| row + 1 < rows) {
+                self.move_track(playback_context, library, row, row + 1)?;
+            }
+        } else if input_map.list_clear.contains(&input) {
+            let mut queue = playback_context.lock_queue();
+            if !queue.queue_items().is_empty() {
+                self.push_undo(vec![Change::Cleared(queue.queue_items().to_vec())]);
+                queue.clear_queue();
+                self.list_state.select(None);
+            }
+        } else if input_map.undo.contains(&input) {
+            if let Some(changes) = self.undo_stack.pop() {
+                let mut queue = playback_context.lock_queue();
+                for change in changes.into_iter().rev() {
+                    undo(&mut queue, change);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Index of the queue item and of the track in it of the selected row
+    fn selected(&self, queue_items: &[QueueItem]) -> Option<(usize, usize)> {
+        let index = self.list_state.selected()?;
+        queue_rows(queue_items).get(index).map(|row| row.position)
+    }
+
+    /// Remove a track, splitting it from its album or playlist
+    fn remove_track(
+        &mut self,
+        playback_context: &ArcPlaybackContext,
+        library: &mut Library,
+        position: (usize, usize),
+    ) -> Result<()> {
+        let splits = prepare_splits(playback_context, library, &[position.0])?;
+        let mut queue = playback_context.lock_queue();
+        let mut changes = vec![];
+        let counts = apply_splits(&mut queue, splits, &mut changes)?;
+        let index = split_index(position, &counts);
+        let removed = queue.queue_items()[index].clone();
+        queue.remove_queue_item(index);
+        changes.push(Change::Removed(index, removed));
+        self.push_undo(changes);
+        Ok(())
+    }
+
+    /// Move the track of a row to the place of the track of the row next to it,
+    /// splitting both from their albums or playlists
+    fn move_track(
+        &mut self,
+        playback_context: &ArcPlaybackContext,
+        library: &mut Library,
+        row: usize,
+        other_row: usize,
+    ) -> Result<()> {
+        let (position, other_position) = {
+            let queue = playback_context.lock_queue();
+            let rows = queue_rows(queue.queue_items());
+            match (rows.get(row), rows.get(other_row)) {
+                (Some(row), Some(other_row)) => (row.position, other_row.position),
+                _ => return Ok(()),
+            }
+        };
+        let splits = prepare_splits(playback_context, library, &[position.0, other_position.0])?;
+        let mut queue = playback_context.lock_queue();
+        let mut changes = vec![];
+        let counts = apply_splits(&mut queue, splits, &mut changes)?;
+        let (from, to) = (
+            split_index(position, &counts),
+            split_index(other_position, &counts),
+        );
+        queue.move_queue_item(from, to);
+        changes.push(Change::Moved(from, to));
+        self.push_undo(changes);
+        self.select_item(queue.queue_items(), to);
+        Ok(())
+    }
+
+    /// Clicks select a track, double clicks play it
+    pub fn handle_mouse(
+        &mut self,
+        mouse: &Mouse,
+        area: Rect,
+        theme: &Theme,
+        playback_context: &ArcPlaybackContext,
+    ) -> Option<PlaybackAction> {
+        match mouse.kind {
+            MouseKind::ScrollUp => self.list_state.scroll_up_by(1),
+            MouseKind::ScrollDown => self.list_state.scroll_down_by(1),
+            MouseKind::Click | MouseKind::DoubleClick => {
+                let (area, _) = art::split_pane(area);
+                let index = mouse.row(inner_area(area, theme), self.list_state.offset())?;
+                let queue = playback_context.lock_queue();
+                let row = queue_rows(queue.queue_items()).get(index)?.position;
+                self.list_state.select(Some(index));
+                if mouse.kind == MouseKind::DoubleClick {
+                    return Some(PlaybackAction::PlayFromQueue(row.0, row.1));
+                }
+            }
+        }
+        None
+    }
+
+    fn push_undo(&mut self, changes: Vec<Change>) {
+        if self.undo_stack.len() == UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(changes);
+    }
+
+    /// Select the first track of a queue item
+    fn select_item(&mut self, queue_items: &[QueueItem], item: usize) {
+        let index = queue_rows(queue_items)
+            .iter()
+            .position(|row| row.position.0 == item);
+        self.list_state.select(index);
+    }
+}
+
+/// An album or playlist of the queue, with the items per track that replace it
+struct Split {
+    index: usize,
+    item: QueueItem,
+    track_items: Vec<QueueItem>,
+}
+
+/// The splits of the items at `indexes` that have more than one track. The
+/// library is read without the queue locked, so playback does not wait for it.
+fn prepare_splits(
+    playback_context: &ArcPlaybackContext,
+    library: &mut Library,
+    indexes: &[usize],
+) -> Result<Vec<Split>> {
+    let items: Vec<(usize, QueueItem)> = {
+        let queue = playback_context.lock_queue();
+        let mut indexes = indexes.to_vec();
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+            .into_iter()
+            .filter_map(|index| {
+                let item = queue.queue_items().get(index)?;
+                (item_tracks(item).len() > 1).then(|| (index, item.clone()))
+            })
+            .collect()
+    };
+    let mut splits = vec![];
+    for (index, item) in items {
+        let mut track_items = vec![];
+        for track in item_tracks(&item) {
+            track_items.push(library.queue_item_from_path(Path::new(&track.path))?);
+        }
+        splits.push(Split {
+            index,
+            item,
+            track_items,
+        });
+    }
+    Ok(splits)
+}
+
+/// Replace the items of the splits by their tracks, returns the index and the
+/// number of tracks of every split. Nothing changes if the queue changed since
+/// the splits were prepared, or if one of the items is playing: the current
+/// track is kept by its position, which the split would move.
+fn apply_splits(
+    queue: &mut Queue,
+    splits: Vec<Split>,
+    changes: &mut Vec<Change>,
+) -> Result<Vec<(usize, usize)>> {
+    let current = queue.current_position().map(|(item, _)| item);
+    for split in &splits {
+        match queue.queue_items().get(split.index) {
+            Some(item) if same_tracks(item, &split.item) => (),
+            _ => bail!("The queue changed while editing it"),
+        }
+        if current == Some(split.index) {
+            bail!("Can not take a track out of the album or playlist that is playing");
+        }
+    }
+    let mut counts = vec![];
+    // From the back, so the indexes of the splits before stay the same
+    for split in splits.into_iter().rev() {
+        counts.push((split.index, split.track_items.len()));
+        queue.remove_queue_item(split.index);
+        for (offset, track_item) in split.track_items.into_iter().enumerate() {
+            insert_at(queue, split.index + offset, track_item);
+        }
+        changes.push(Change::Split(split.index, split.item));
+    }
+    Ok(counts)
+}
+
+/// The index a track has after the items in `counts` are split into their tracks
+fn split_index((item, track): (usize, usize), counts: &[(usize, usize)]) -> usize {
+    let before: usize = counts
+        .iter()
+        .filter(|(index, _)| *index < item)
+        .map(|(_, tracks)| tracks - 1)
+        .sum();
+    let is_split = counts.iter().any(|(index, _)| *index == item);
+    item + before + if is_split { track } else { 0 }
+}
+
+fn same_tracks(item: &QueueItem, other: &QueueItem) -> bool {
+    item_tracks(item)
+        .iter()
+        .map(|track| &track.path)
+        .eq(item_tracks(other).iter().map(|track| &track.path))
+}
+
+fn insert_at(queue: &mut Queue, index: usize, queue_item: QueueItem) {
+    queue.append_queue_item(queue_item, false);
+    let last = queue.queue_items().len() - 1;
+    if index < last {
+        queue.move_queue_item(last, index);
+    }
+}
+
+/// Do the opposite of a change, if the queue still looks like it did after the change
+fn undo(queue: &mut Queue, change: Change) {
+    let len = queue.queue_items().len();
+    match change {
+        Change::Removed(index, queue_item) => insert_at(queue, index.min(len), queue_item),
+        Change::Moved(from, to) if from < len && to < len => queue.move_queue_item(to, from),
+        Change::Split(index, queue_item) => {
+            let tracks = item_tracks(&queue_item).len();
+            if index + tracks > len {
+                warn!("Not undoing a split, the queue has changed");
+                return;
+            }
+            for _ in 0..tracks {
+                queue.remove_queue_item(index);
+            }
+            insert_at(queue, index, queue_item);
+        }
+        // Put back in front of what was queued since
+        Change::Cleared(queue_items) => {
+            for (index, queue_item) in queue_items.into_iter().enumerate() {
+                insert_at(queue, index, queue_item);
+            }
+        }
+        Change::Moved(..) => warn!("Not undoing a move, the queue has changed"),
+    }
+}
+
+fn queue_rows(queue_items: &[QueueItem]) -> Vec<QueueRow> {
+    queue_items
+        .iter()
+        .enumerate()
+        .flat_map(|(item_index, item)| {
+            item_tracks(item)
+                .into_iter()
+                .enumerate()
+                .map(move |(track_index, track)| QueueRow {
+                    position: (item_index, track_index),
+                    track,
+                })
+        })
+        .collect()
+}
+
+/// The tracks after the current one in queue order, every track when nothing is playing
+pub fn upcoming_tracks(queue_items: &[QueueItem], current: Option<(usize, usize)>) -> Vec<&Track> {
+    let rows = queue_rows(queue_items);
+    let start = current
+        .and_then(|current| rows.iter().position(|row| row.position == current))
+        .map_or(0, |index| index + 1);
+    rows[start..].iter().map(|row| row.track).collect()
+}
+
+/// The tracks in a queue item, in play order
+pub fn item_tracks(item: &QueueItem) -> Vec<&Track> {
+    match item {
+        QueueItem::Track(track) => vec![track.track()],
+        QueueItem::Playlist(playlist) => playlist
+            .tracks()
+            .iter()
+            .map(|track| track.track())
+            .collect(),
+        QueueItem::Album(album) => album.tracks().iter().map(|track| track.track()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_indexes() {
+        // Items 1 and 3 were split into 3 and 2 tracks
+        let counts = [(3, 2), (1, 3)];
+        assert_eq!(split_index((0, 0), &counts), 0);
+        assert_eq!(split_index((1, 0), &counts), 1);
+        assert_eq!(split_index((1, 2), &counts), 3);
+        assert_eq!(split_index((2, 0), &counts), 4);
+        assert_eq!(split_index((3, 1), &counts), 6);
+        assert_eq!(split_index((4, 0), &counts), 7);
+        assert_eq!(split_index((2, 0), &[]), 2);
+    }
+}
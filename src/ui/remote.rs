@@ -0,0 +1,189 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use ratatui::{
+    prelude::*,
+    widgets::{LineGauge, Paragraph},
+};
+use ratatui_eventInput::Input;
+use rmusic_tui::settings::{input::InputMap, playback::PlaybackSettings, Settings};
+
+use super::theme::Theme;
+use crate::control::{self, Command, SeekPosition, Status, VolumeChange};
+
+/// How often the status of the player is asked for
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Controls a player that is already running, through its control socket.
+/// Only the media bindings work, the library is browsed in the player itself.
+pub struct RemoteUI {
+    socket: PathBuf,
+    input_map: InputMap,
+    playback: PlaybackSettings,
+    theme: Theme,
+    status: Option<Status>,
+    /// The last error of the control socket, shown until a request works again
+    error: Option<String>,
+    last_status: Option<Instant>,
+    /// The volume in percent from before muting, while muted
+    muted: Option<u8>,
+}
+
+impl RemoteUI {
+    pub fn new(socket: PathBuf, settings: Settings) -> Result<RemoteUI> {
+        let theme = match &settings.theme {
+            Some(theme) => Theme::load(theme)?,
+            None => Theme::default(),
+        };
+        Ok(RemoteUI {
+            socket,
+            input_map: settings.keys,
+            playback: settings.playback,
+            theme,
+            status: None,
+            error: None,
+            last_status: None,
+            muted: None,
+        })
+    }
+
+    /// Ask the player for its status when it was not asked for a while
+    pub fn update(&mut self) {
+        if self
+            .last_status
+            .is_some_and(|last| last.elapsed() < STATUS_INTERVAL)
+        {
+            return;
+        }
+        self.last_status = Some(Instant::now());
+        let status = control::request(&self.socket, &Command::Status)
+            .and_then(|data| Ok(serde_json::from_str::<Status>(&data.unwrap_or_default())?));
+        match status {
+            Ok(status) => {
+                self.status = Some(status);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(format!("{err:#}")),
+        }
+    }
+
+    pub fn handle_input<I>(&mut self, input: I)
+    where
+        I: Into<Input>,
+    {
+        let input: Input = input.into();
+        let media = &self.input_map.media;
+        let step = i16::from(self.playback.volume_step);
+        let command = if media.playpause.contains(&input) {
+            Command::Toggle
+        } else if media.volume_up.contains(&input) {
+            self.muted = None;
+            Command::Volume(VolumeChange::Relative(step))
+        } else if media.volume_down.contains(&input) {
+            self.muted = None;
+            Command::Volume(VolumeChange::Relative(-step))
+        } else if media.mute.contains(&input) {
+            match self.muted.take() {
+                Some(percent) => Command::Volume(VolumeChange::Set(percent)),
+                None => {
+                    self.muted = self.status.as_ref().map(|status| status.volume);
+                    Command::Volume(VolumeChange::Set(0))
+                }
+            }
+        } else if media.fast_forward.contains(&input) {
+            Command::Forward(self.playback.seek_step)
+        } else if media.rewind.contains(&input) {
+            Command::Rewind(self.playback.seek_step)
+        } else if media.fast_forward_large.contains(&input) {
+            Command::Forward(self.playback.seek_step_large)
+        } else if media.rewind_large.contains(&input) {
+            Command::Rewind(self.playback.seek_step_large)
+        } else if media.shuffle.contains(&input) {
+            Command::Shuffle
+        } else if media.repeat.contains(&input) {
+            Command::Repeat
+        } else if media.next.contains(&input) {
+            Command::Next
+        } else if media.previous.contains(&input) {
+            Command::Previous
+        } else if let Some(tenths) = media.seek_tenths(&input) {
+            let length = self.status.as_ref().map_or(0, |status| status.length);
+            Command::Seek(SeekPosition::Absolute(length * tenths / 10))
+        } else {
+            return;
+        };
+        match control::request(&self.socket, &command) {
+            Ok(_) => self.last_status = None,
+            Err(err) => self.error = Some(format!("{err:#}")),
+        }
+    }
+}
+
+impl Widget for &RemoteUI {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [title_rect, main_rect, track_rect, status_rect] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+
+        Line::from(format!(
+            "Attached to the player on {}, q detaches",
+            self.socket.display()
+        ))
+        .style(*self.theme.status_style())
+        .render(title_rect, buf);
+
+        if let Some(error) = &self.error {
+            Paragraph::new(error.as_str())
+                .style(*self.theme.style())
+                .render(main_rect, buf);
+        }
+        let Some(status) = &self.status else {
+            return;
+        };
+        let track = if status.track.is_empty() {
+            "Nothing playing".to_string()
+        } else {
+            format!(
+                "{} {}",
+                if status.playing { "Playing" } else { "Paused" },
+                status.track
+            )
+        };
+        Line::from(track)
+            .style(*self.theme.status_style())
+            .render(track_rect, buf);
+
+        let [gauge_rect, modes_rect] =
+            Layout::horizontal([Constraint::Fill(1), Constraint::Length(18)]).areas(status_rect);
+        LineGauge::default()
+            .ratio(if status.length == 0 {
+                0.0
+            } else {
+                (status.played as f64 / status.length as f64).min(1.0)
+            })
+            .label(format!(
+                "{}:{:02}/{}:{:02} ",
+                status.played / 60,
+                status.played % 60,
+                status.length / 60,
+                status.length % 60,
+            ))
+            .filled_style(*self.theme.gauge_filled_style())
+            .unfilled_style(*self.theme.gauge_unfilled_style())
+            .line_set(symbols::line::THICK)
+            .render(gauge_rect, buf);
+        Line::from(format!(
+            " {}% {} {}",
+            status.volume, status.shuffle, status.repeat
+        ))
+        .style(*self.theme.status_style())
+        .render(modes_rect, buf);
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use futures::executor::block_on;
+use log::error;
+use ratatui::{
+    prelude::*,
+    widgets::{List, ListItem, ListState},
+};
+use ratatui_eventInput::{Input, Key};
+use rmusic::{
+    database::Library,
+    models::{artist, release, track, Artist, Release, Track},
+    queue::queue_items::QueueItem,
+};
+use rmusic_tui::settings::input::{Dialog, Navigation};
+use sea_orm::{
+    sea_query::{Expr, LikeExpr},
+    EntityTrait, QueryFilter, QuerySelect,
+};
+
+use super::{fuzzy::fuzzy_match, library_view::Action, theme::Theme};
+use crate::database;
+
+/// Maximum number of results shown for artists, releases and tracks each
+const RESULT_LIMIT: usize = 25;
+/// Maximum number of rows of every kind the database gives to rank
+const CANDIDATE_LIMIT: u64 = 500;
+
+/// Search through the whole library, the database is queried on a separate thread
+pub struct SearchView {
+    query: String,
+    typing: bool,
+    /// Results grouped by kind, in the order they are shown
+    results: Vec<Hit>,
+    /// Index of the selected result
+    selected: Option<usize>,
+    list_state: ListState,
+    /// A query was sent and its results have not come back yet
+    searching: bool,
+    requests: Sender<String>,
+    responses: Receiver<Vec<Hit>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Artist,
+    Release,
+    Track,
+}
+
+impl Kind {
+    fn title(self) -> &'static str {
+        match self {
+            Kind::Artist => "Artists",
+            Kind::Release => "Releases",
+            Kind::Track => "Tracks",
+        }
+    }
+}
+
+/// What a result is, to get its queue item once it is picked
+enum Target {
+    Artist(i32),
+    Release(i32),
+    Track(String),
+}
+
+struct Hit {
+    kind: Kind,
+    name: String,
+    detail: String,
+    /// Indexes of the characters in `name` that matched the query
+    positions: Vec<usize>,
+    target: Target,
+}
+
+impl SearchView {
+    pub fn new(library: &mut Library) -> Result<SearchView> {
+        let library = library.try_clone()?;
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_responses, responses) = mpsc::channel();
+        thread::spawn(move || search_worker(library, worker_requests, worker_responses));
+        Ok(SearchView {
+            query: String::new(),
+            typing: false,
+            results: vec![],
+            selected: None,
+            list_state: ListState::default(),
+            searching: false,
+            requests,
+            responses,
+        })
+    }
+
+    /// Returns true while the query is typed, so other bindings should be ignored
+    pub fn is_typing(&self) -> bool {
+        self.typing
+    }
+
+    pub fn handle_input<I>(
+        &mut self,
+        input: I,
+        input_map: &Navigation,
+        dialog: &Dialog,
+        library: &mut Library,
+    ) -> Result<Action>
+    where
+        I: Into<Input>,
+    {
+        let input: Input = input.into();
+
+        if self.typing {
+            if input_map.cancel.contains(&input) || dialog.submit.contains(&input) {
+                self.typing = false;
+            } else if input.key == Key::Backspace {
+                self.query.pop();
+                self.search();
+            } else if let Key::Char(char) = input.key {
+                // Ignore characters typed with ctrl or alt
+                if input == Input::new_key(input.key) {
+                    self.query.push(char);
+                    self.search();
+                }
+            }
+            return Ok(Action::None);
+        }
+
+        if input_map.search.contains(&input) {
+            self.typing = true;
+        } else if input_map.list_down.contains(&input) {
+            self.selected = match self.selected {
+                Some(index) if index + 1 < self.results.len() => Some(index + 1),
+                _ if self.results.is_empty() => None,
+                _ => Some(0),
+            };
+        } else if input_map.list_up.contains(&input) {
+            self.selected = match self.selected {
+                Some(index) if index > 0 => Some(index - 1),
+                _ => self.results.len().checked_sub(1),
+            };
+        } else if input_map.refresh.contains(&input) {
+            self.refresh();
+        } else if let Some(hit) = self.selected.and_then(|index| self.results.get(index)) {
+            let play =
+                input_map.list_select.contains(&input) || input_map.item_set.contains(&input);
+            let add = input_map.item_add.contains(&input);
+            let add_next = input_map.item_add_next.contains(&input);
+            if !(play || add || add_next || input_map.playlist_add.contains(&input)) {
+                return Ok(Action::None);
+            }
+            // Only the picked result gets a queue item
+            let queue_item = queue_item(library, &hit.target)?;
+            return Ok(if play {
+                Action::Play(queue_item)
+            } else if add {
+                Action::Queue(queue_item, true)
+            } else if add_next {
+                Action::QueueNext(queue_item, true)
+            } else {
+                Action::AddToPlaylist(queue_item)
+            });
+        }
+        Ok(Action::None)
+    }
+
+    /// Search again with the current query, after the library has changed
+    pub fn refresh(&mut self) {
+        self.search();
+    }
+
+    fn search(&mut self) {
+        if self.requests.send(self.query.clone()).is_err() {
+            error!("The search thread has stopped");
+            return;
+        }
+        self.searching = true;
+    }
+
+    pub fn render(&mut self, rect: Rect, buffer: &mut Buffer, theme: &Theme) {
+        // Only the newest results are interesting
+        if let Some(results) = self.responses.try_iter().last() {
+            self.searching = false;
+            self.selected = (!results.is_empty()).then_some(0);
+            self.results = results;
+        }
+
+        let [query_rect, list_rect] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(rect);
+
+        let query = if self.typing {
+            format!("/{}_", self.query)
+        } else if self.query.is_empty() {
+            "Press / to search the library".to_string()
+        } else {
+            format!("/{}", self.query)
+        };
+        let status = if self.searching {
+            " searching...".to_string()
+        } else {
+            format!(" {} results", self.results.len())
+        };
+        Line::from(vec![Span::raw(query), Span::raw(status).dim()])
+            .style(*theme.style())
+            .render(query_rect, buffer);
+
+        // Group the results under a header for every kind
+        let mut items = vec![];
+        let mut selected_row = None;
+        let mut last_kind = None;
+        for (index, hit) in self.results.iter().enumerate() {
+            if last_kind != Some(hit.kind) {
+                items.push(ListItem::new(hit.kind.title()).style(*theme.dir_style()));
+                last_kind = Some(hit.kind);
+            }
+            if self.selected == Some(index) {
+                selected_row = Some(items.len());
+            }
+            let mut spans = highlight(&hit.name, &hit.positions, *theme.search_match_style());
+            if !hit.detail.is_empty() {
+                spans.push(Span::raw(format!("  {}", hit.detail)).dim());
+            }
+            items.push(ListItem::new(Line::from(spans)));
+        }
+        self.list_state.select(selected_row);
+
+        let mut list = List::new(items)
+            .style(*theme.item_style())
+            .highlight_spacing(theme.highlight_spacing().clone())
+            .highlight_style(*theme.highlight_item_style())
+            .highlight_symbol(theme.highlight_symbol().unwrap_or_default())
+            // TODO: make option of padding
+            .scroll_padding(3);
+
+        if let Some(block) = theme.block() {
+            list = list.block(block.clone());
+        }
+        StatefulWidget::render(list, list_rect, buffer, &mut self.list_state);
+    }
+}
+
+/// Split `text` in spans, where the characters at `positions` use `style`
+fn highlight<'a>(text: &'a str, positions: &[usize], style: Style) -> Vec<Span<'a>> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut start_matched = false;
+    for (index, (byte, _)) in text.char_indices().enumerate() {
+        let matched = positions.contains(&index);
+        if matched != start_matched && byte > start {
+            spans.push(styled(&text[start..byte], start_matched, style));
+            start = byte;
+        }
+        start_matched = matched;
+    }
+    spans.push(styled(&text[start..], start_matched, style));
+    spans
+}
+
+fn styled(text: &str, matched: bool, style: Style) -> Span {
+    if matched {
+        Span::styled(text, style)
+    } else {
+        Span::raw(text)
+    }
+}
+
+fn search_worker(library: Library, requests: Receiver<String>, responses: Sender<Vec<Hit>>) {
+    while let Ok(mut query) = requests.recv() {
+        // Skip the queries that were typed in the meantime
+        if let Some(newer) = requests.try_iter().last() {
+            query = newer;
+        }
+        let hits = match search(&library, &query) {
+            Ok(hits) => hits,
+            Err(err) => {
+                error!("Error while searching the library: {err}");
+                vec![]
+            }
+        };
+        if responses.send(hits).is_err() {
+            // The view is gone
+            return;
+        }
+    }
+}
+
+/// `LIKE` pattern that has the characters of the query in order, the same rows
+/// fuzzy matching can find, with `\` escaping the wildcards
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for char in query.chars() {
+        if matches!(char, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(char);
+        pattern.push('%');
+    }
+    pattern
+}
+
+/// The best `RESULT_LIMIT` of `rows` by how well their name matches the query, best first
+fn rank<T>(rows: Vec<T>, name: impl Fn(&T) -> &str, query: &str) -> Vec<(T, Vec<usize>)> {
+    let mut found: Vec<_> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let found = fuzzy_match(name(&row), query)?;
+            Some((row, found))
+        })
+        .collect();
+    // Stable, so equal scores keep the database order
+    found.sort_by_key(|(_, found)| Reverse(found.score));
+    found
+        .into_iter()
+        .take(RESULT_LIMIT)
+        .map(|(row, found)| (row, found.positions))
+        .collect()
+}
+
+/// Names of artists and releases, looked up once per search
+#[derive(Default)]
+struct Names {
+    artists: HashMap<i32, String>,
+    releases: HashMap<i32, (String, i32)>,
+}
+
+impl Names {
+    fn artist(&mut self, library: &Library, id: i32) -> Result<String> {
+        if let Some(name) = self.artists.get(&id) {
+            return Ok(name.clone());
+        }
+        let name = block_on(artist::Entity::find_by_id(id).one(library.connection()))?
+            .map(|artist| artist.name)
+            .unwrap_or_default();
+        self.artists.insert(id, name.clone());
+        Ok(name)
+    }
+
+    /// The name and artist id of a release
+    fn release(&mut self, library: &Library, id: i32) -> Result<(String, i32)> {
+        if let Some(release) = self.releases.get(&id) {
+            return Ok(release.clone());
+        }
+        let release = block_on(release::Entity::find_by_id(id).one(library.connection()))?
+            .map_or((String::new(), 0), |release| {
+                (release.name, release.artist_id)
+            });
+        self.releases.insert(id, release.clone());
+        Ok(release)
+    }
+}
+
+/// The best matches for every kind, best first. The database narrows the rows down
+/// with `LIKE`, only those are ranked
+fn search(library: &Library, query: &str) -> Result<Vec<Hit>> {
+    let mut hits = vec![];
+    if query.is_empty() {
+        return Ok(hits);
+    }
+    let like = || LikeExpr::new(like_pattern(query)).escape('\\');
+    let connection = library.connection();
+    let mut names = Names::default();
+
+    let artists: Vec<Artist> = block_on(
+        artist::Entity::find()
+            .filter(Expr::col(artist::Column::Name).like(like()))
+            .limit(CANDIDATE_LIMIT)
+            .all(connection),
+    )?;
+    for (artist, positions) in rank(artists, |artist| &artist.name, query) {
+        hits.push(Hit {
+            kind: Kind::Artist,
+            name: artist.name,
+            detail: String::new(),
+            positions,
+            target: Target::Artist(artist.id),
+        });
+    }
+
+    let releases: Vec<Release> = block_on(
+        release::Entity::find()
+            .filter(Expr::col(release::Column::Name).like(like()))
+            .limit(CANDIDATE_LIMIT)
+            .all(connection),
+    )?;
+    for (release, positions) in rank(releases, |release| &release.name, query) {
+        hits.push(Hit {
+            kind: Kind::Release,
+            detail: names.artist(library, release.artist_id)?,
+            name: release.name,
+            positions,
+            target: Target::Release(release.id),
+        });
+    }
+
+    let tracks: Vec<Track> = block_on(
+        track::Entity::find()
+            .filter(Expr::col(track::Column::Name).like(like()))
+            .limit(CANDIDATE_LIMIT)
+            .all(connection),
+    )?;
+    for (track, positions) in rank(tracks, |track| &track.name, query) {
+        let (release, artist_id) = names.release(library, track.release_id)?;
+        hits.push(Hit {
+            kind: Kind::Track,
+            detail: format!("{} - {release}", names.artist(library, artist_id)?),
+            name: track.name,
+            positions,
+            target: Target::Track(track.path),
+        });
+    }
+    Ok(hits)
+}
+
+/// The queue item of a picked result, artists and releases come from a library view
+fn queue_item(library: &mut Library, target: &Target) -> Result<QueueItem> {
+    let queue_item = match *target {
+        Target::Track(ref path) => return Ok(library.queue_item_from_path(Path::new(path))?),
+        Target::Artist(id) => database::artist_queue_item(library, id)?,
+        Target::Release(id) => database::release_queue_item(library, id)?,
+    };
+    queue_item.ok_or_else(|| anyhow!("The result is no longer in the library, search again"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn like_patterns() {
+        assert_eq!(like_pattern("abc"), "%a%b%c%");
+        assert_eq!(like_pattern("50%_"), "%5%0%\\%%\\_%");
+        assert_eq!(like_pattern(""), "%");
+    }
+}
@@ -8,13 +8,21 @@ use ratatui_eventInput::{Input, Key};
 use rmusic::database::Library;
 use rmusic::models::{Artist, Release, Track};
 use rmusic::playback::playback_context::ArcPlaybackContext;
-use rmusic::queue::queue_items::QueueItem;
 use rmusic_tui::settings::input::Navigation;
 use tui_logger::*;
 
+use super::art::CoverArt;
+use super::history::HistoryView;
 use super::library_view::LibraryViewer;
+use super::lyrics_view::LyricsView;
+use super::mouse::{inner_area, Mouse, MouseKind};
+use super::now_playing::NowPlaying;
+use super::playlist_view::PlaylistView;
+use super::queue_view::QueueView;
+use super::search_view::SearchView;
 use super::theme::Theme;
 use super::FileExplorer;
+use crate::transport::Transport;
 
 pub struct TabPages {
     tab_pages: Vec<TabPage>,
@@ -48,6 +56,62 @@ impl TabPages {
         &self.tab_pages[self.active_tab_index]
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &TabPage> {
+        self.tab_pages.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut TabPage> {
+        self.tab_pages.iter_mut()
+    }
+
+    /// Refresh every tab that shows the library, after the library has changed
+    pub fn refresh(&mut self, library: &mut Library) -> Result<()> {
+        for tab_page in &mut self.tab_pages {
+            tab_page.refresh(library)?;
+        }
+        Ok(())
+    }
+
+    /// Make the tab at `index` active
+    pub fn select(&mut self, index: usize, library: &mut Library) -> Result<()> {
+        if index < self.tab_pages.len() {
+            self.active_tab_index = index;
+            self.sync_with_database(library)?;
+        }
+        Ok(())
+    }
+
+    /// Index of the tab whose title is at `column`, when the tabs are drawn in `area`.
+    /// Titles are drawn like `Tabs` does, padded by a space and split by a divider.
+    pub fn tab_at(&self, column: u16, area: Rect) -> Option<usize> {
+        let mut x = area.x;
+        for (index, tab_page) in self.tab_pages.iter().enumerate() {
+            let end = x + Line::from(tab_page.tab_name()).width() as u16 + 2;
+            if column < x {
+                // On the divider
+                return None;
+            }
+            if column < end {
+                return Some(index);
+            }
+            x = end + 1;
+        }
+        None
+    }
+
+    /// Make the tab with this name active, if there is one
+    pub fn select_by_name(&mut self, name: &str, library: &mut Library) -> Result<()> {
+        if let Some(index) = self
+            .tab_pages
+            .iter()
+            .position(|tab_page| tab_page.tab_name() == name)
+        {
+            self.active_tab_index = index;
+            self.sync_with_database(library)?;
+        }
+        Ok(())
+    }
+
     pub fn handle_input<I>(
         &mut self,
         input: I,
@@ -72,8 +136,11 @@ impl TabPages {
         }
         Ok(())
     }
-    pub fn widget(&self) -> Tabs {
-        Tabs::new(self.tab_pages.iter().map(TabPage::tab_name)).select(self.active_tab_index)
+    pub fn widget(&self, theme: &Theme) -> Tabs {
+        Tabs::new(self.tab_pages.iter().map(TabPage::tab_name))
+            .style(*theme.tab_style())
+            .highlight_style(*theme.tab_highlight_style())
+            .select(self.active_tab_index)
     }
 }
 
@@ -82,8 +149,13 @@ pub enum TabPage {
     Artists(Artists),
     FileExplorer(FileExplorer),
     LibraryView(LibraryViewer<Artist, Release, Track>),
+    Search(SearchView),
+    Playlists(PlaylistView),
+    NowPlaying(NowPlaying),
+    Lyrics(LyricsView),
     TuiLogger(TuiWidgetState),
     Queue(QueueView),
+    History(HistoryView),
 }
 
 impl TabPage {
@@ -92,33 +164,64 @@ impl TabPage {
             TabPage::Artists(_) => "Artist",
             TabPage::FileExplorer(_) => "Files",
             TabPage::LibraryView(_) => "LibraryView",
+            TabPage::Search(_) => "Search",
+            TabPage::Playlists(_) => "Playlists",
+            TabPage::NowPlaying(_) => "Now Playing",
+            TabPage::Lyrics(_) => "Lyrics",
             TabPage::TuiLogger(_) => "TuiLogger",
             TabPage::Queue(_) => "Queue",
+            TabPage::History(_) => "History",
+        }
+    }
+    /// Returns true if the tab takes text input, so no other bindings should be used
+    pub fn is_typing(&self) -> bool {
+        match self {
+            TabPage::LibraryView(library_viewer) => library_viewer.is_typing(),
+            TabPage::Search(search_view) => search_view.is_typing(),
+            TabPage::Playlists(playlist_view) => playlist_view.is_typing(),
+            _ => false,
         }
     }
     pub fn sync_with_database(&mut self, library: &mut Library) -> Result<()> {
         match self {
             TabPage::Artists(artists) => artists.sync_with_database(library),
+            TabPage::Playlists(playlist_view) => playlist_view.sync_with_database(library),
             _ => Ok(()),
         }
     }
+    /// Read the library again, unlike `sync_with_database` this also picks up new items
+    pub fn refresh(&mut self, library: &mut Library) -> Result<()> {
+        match self {
+            TabPage::LibraryView(library_viewer) => library_viewer.refresh(library),
+            TabPage::Search(search_view) => {
+                search_view.refresh();
+                Ok(())
+            }
+            _ => self.sync_with_database(library),
+        }
+    }
     pub fn render(
         &mut self,
         rect: Rect,
         buffer: &mut Buffer,
         theme: &Theme,
         playback_context: &ArcPlaybackContext,
+        art: &mut CoverArt,
+        transport: &Transport,
     ) {
         match self {
             TabPage::Artists(artists) => artists.render(rect, buffer, theme),
             TabPage::FileExplorer(file_explorer) => file_explorer.widget().render(rect, buffer),
-            TabPage::LibraryView(library_viewer) => library_viewer.render(rect, buffer, theme),
+            TabPage::LibraryView(library_viewer) => library_viewer.render(rect, buffer, theme, art),
+            TabPage::Search(search_view) => search_view.render(rect, buffer, theme),
+            TabPage::Playlists(playlist_view) => playlist_view.render(rect, buffer, theme),
             TabPage::TuiLogger(tui_widget_state) => TuiLoggerSmartWidget::default()
-                .style_error(Style::default().fg(Color::Red))
-                .style_debug(Style::default().fg(Color::Green))
-                .style_warn(Style::default().fg(Color::Yellow))
-                .style_trace(Style::default().fg(Color::Magenta))
-                .style_info(Style::default().fg(Color::Cyan))
+                .style(*theme.style())
+                .style_error(theme.log_error_style)
+                .style_debug(theme.log_debug_style)
+                .style_warn(theme.log_warn_style)
+                .style_trace(theme.log_trace_style)
+                .style_info(theme.log_info_style)
                 .output_separator(':')
                 .output_timestamp(Some("%H:%M:%S%.f6".to_string()))
                 .output_level(Some(TuiLoggerLevelOutput::Abbreviated))
@@ -127,52 +230,16 @@ impl TabPage {
                 .output_line(true)
                 .state(tui_widget_state)
                 .render(rect, buffer),
-            TabPage::Queue(queue) => queue.render(rect, buffer, playback_context),
-        }
-    }
-}
-
-pub struct QueueView {
-    list_state: ListState,
-}
-
-impl QueueView {
-    pub fn new() -> QueueView {
-        QueueView {
-            list_state: ListState::default(),
+            TabPage::NowPlaying(now_playing) => {
+                now_playing.render(rect, buffer, theme, playback_context, art)
+            }
+            TabPage::Lyrics(lyrics_view) => {
+                lyrics_view.render(rect, buffer, theme, playback_context)
+            }
+            TabPage::Queue(queue) => queue.render(rect, buffer, theme, playback_context, art),
+            TabPage::History(history_view) => history_view.render(rect, buffer, theme, transport),
         }
     }
-
-    pub fn render(
-        &mut self,
-        rect: Rect,
-        buffer: &mut Buffer,
-        playback_context: &ArcPlaybackContext,
-    ) {
-        let queue = playback_context.lock_queue();
-        let list = List::new(queue.queue_items().iter().map(show_queue_item));
-        StatefulWidget::render(list, rect, buffer, &mut self.list_state);
-    }
-
-    pub fn handle_input<I>(&mut self, input: I, input_map: &Navigation)
-    where
-        I: Into<Input>,
-    {
-        let input: Input = input.into();
-        if input_map.list_down.contains(&input) {
-            self.list_state.scroll_down_by(1);
-        } else if input_map.list_up.contains(&input) {
-            self.list_state.scroll_up_by(1);
-        }
-    }
-}
-
-fn show_queue_item(item: &QueueItem) -> &str {
-    match item {
-        QueueItem::Track(track) => &track.track().name,
-        QueueItem::Playlist(_) => "Playlist",
-        QueueItem::Album(_) => "Album",
-    }
 }
 
 #[derive(Clone)]
@@ -202,6 +269,21 @@ impl Artists {
         }
     }
 
+    pub fn handle_mouse(&mut self, mouse: &Mouse, area: Rect, theme: &Theme) {
+        match mouse.kind {
+            MouseKind::ScrollUp => self.list_state.scroll_up_by(1),
+            MouseKind::ScrollDown => self.list_state.scroll_down_by(1),
+            MouseKind::Click | MouseKind::DoubleClick => {
+                let index = mouse
+                    .row(inner_area(area, theme), self.list_state.offset())
+                    .filter(|index| *index < self.list.len());
+                if index.is_some() {
+                    self.list_state.select(index);
+                }
+            }
+        }
+    }
+
     pub fn sync_with_database(&mut self, library: &mut Library) -> Result<()> {
         self.list = library
             .find_all::<Artist>()?
@@ -1,7 +1,20 @@
+use std::{fs, path::Path, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
 use ratatui::{
-    style::{Color, Style},
+    style::{Color, Modifier, Style, Stylize},
     widgets::{Block, Borders, HighlightSpacing},
 };
+use rmusic_tui::settings::config_dir;
+use serde::Deserialize;
+
+/// Presets that are bundled with the binary, next to the built-in default
+const PRESETS: [(&str, &str); 3] = [
+    ("light", include_str!("themes/light.toml")),
+    ("gruvbox", include_str!("themes/gruvbox.toml")),
+    ("solarized-dark", include_str!("themes/solarized-dark.toml")),
+];
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Theme {
     pub block: Option<Block<'static>>,
@@ -12,6 +25,18 @@ pub struct Theme {
     pub highlight_item_style: Style,
     pub highlight_dir_style: Style,
     pub highlight_symbol: Option<String>,
+    pub playing_style: Style,
+    pub search_match_style: Style,
+    pub tab_style: Style,
+    pub tab_highlight_style: Style,
+    pub status_style: Style,
+    pub gauge_filled_style: Style,
+    pub gauge_unfilled_style: Style,
+    pub log_error_style: Style,
+    pub log_warn_style: Style,
+    pub log_info_style: Style,
+    pub log_debug_style: Style,
+    pub log_trace_style: Style,
 }
 
 #[allow(dead_code)]
@@ -26,7 +51,75 @@ impl Theme {
             highlight_item_style: Style::new(),
             highlight_dir_style: Style::new(),
             highlight_symbol: None,
+            playing_style: Style::new(),
+            search_match_style: Style::new(),
+            tab_style: Style::new(),
+            tab_highlight_style: Style::new(),
+            status_style: Style::new(),
+            gauge_filled_style: Style::new(),
+            gauge_unfilled_style: Style::new(),
+            log_error_style: Style::new(),
+            log_warn_style: Style::new(),
+            log_info_style: Style::new(),
+            log_debug_style: Style::new(),
+            log_trace_style: Style::new(),
+        }
+    }
+
+    /// Load a theme by name or path.
+    /// Bundled presets are tried first, then a file at `name`,
+    /// and last `themes/<name>.toml` in the config directory.
+    pub fn load(name: &str) -> Result<Self> {
+        if let Some(theme) = Self::preset(name) {
+            return theme;
+        }
+        let path = Path::new(name);
+        if path.is_file() {
+            return Self::from_file(path);
         }
+        if let Some(path) = config_dir()
+            .map(|dir| dir.join("themes").join(format!("{name}.toml")))
+            .filter(|path| path.is_file())
+        {
+            return Self::from_file(&path);
+        }
+        bail!(
+            "Theme `{name}` is not a file or one of the presets: default, {}",
+            PRESETS.map(|(preset, _)| preset).join(", ")
+        )
+    }
+
+    /// Returns the bundled preset with this name, if it exists
+    pub fn preset(name: &str) -> Option<Result<Self>> {
+        if name == "default" {
+            return Some(Ok(Self::default()));
+        }
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(preset, content)| {
+                Self::from_toml(content).with_context(|| format!("Invalid preset `{preset}`"))
+            })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read theme {}", path.display()))?;
+        Self::from_toml(&content).with_context(|| format!("Invalid theme {}", path.display()))
+    }
+
+    /// Parse a theme file, entries that are missing are taken from its preset
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let theme_file: ThemeFile = toml::from_str(content)?;
+        let mut theme = match &theme_file.preset {
+            Some(preset) => match Self::preset(preset) {
+                Some(theme) => theme?,
+                None => bail!("Unknown preset `{preset}`"),
+            },
+            None => Self::default(),
+        };
+        theme_file.apply(&mut theme)?;
+        Ok(theme)
     }
 
     /// Returns the wrapping block (if it exist) of the file explorer of the theme.
@@ -68,6 +161,41 @@ impl Theme {
     pub const fn highlight_spacing(&self) -> &HighlightSpacing {
         &self.highlight_spacing
     }
+
+    /// Returns the style of the currently playing track of the theme.
+    pub const fn playing_style(&self) -> &Style {
+        &self.playing_style
+    }
+
+    /// Returns the style of the characters that match a search of the theme.
+    pub const fn search_match_style(&self) -> &Style {
+        &self.search_match_style
+    }
+
+    /// Returns the style of the tab titles of the theme.
+    pub const fn tab_style(&self) -> &Style {
+        &self.tab_style
+    }
+
+    /// Returns the style of the active tab title of the theme.
+    pub const fn tab_highlight_style(&self) -> &Style {
+        &self.tab_highlight_style
+    }
+
+    /// Returns the style of the status line of the theme.
+    pub const fn status_style(&self) -> &Style {
+        &self.status_style
+    }
+
+    /// Returns the style of the played part of the progress gauge of the theme.
+    pub const fn gauge_filled_style(&self) -> &Style {
+        &self.gauge_filled_style
+    }
+
+    /// Returns the style of the unplayed part of the progress gauge of the theme.
+    pub const fn gauge_unfilled_style(&self) -> &Style {
+        &self.gauge_unfilled_style
+    }
 }
 
 impl Default for Theme {
@@ -81,6 +209,172 @@ impl Default for Theme {
             highlight_item_style: Style::default().fg(Color::White).bg(Color::DarkGray),
             highlight_dir_style: Style::default().fg(Color::LightBlue).bg(Color::DarkGray),
             highlight_symbol: None,
+            playing_style: Style::default().fg(Color::LightGreen).bold(),
+            search_match_style: Style::default().fg(Color::Yellow).bold(),
+            tab_style: Style::default(),
+            tab_highlight_style: Style::default().reversed(),
+            status_style: Style::default(),
+            gauge_filled_style: Style::default().white().bold(),
+            gauge_unfilled_style: Style::default().black(),
+            log_error_style: Style::default().fg(Color::Red),
+            log_warn_style: Style::default().fg(Color::Yellow),
+            log_info_style: Style::default().fg(Color::Cyan),
+            log_debug_style: Style::default().fg(Color::Green),
+            log_trace_style: Style::default().fg(Color::Magenta),
+        }
+    }
+}
+
+/// On disk format of a theme, every entry is optional
+///
+/// ```toml
+/// preset = "light"
+/// highlight_symbol = "> "
+///
+/// [highlight_item]
+/// fg = "black"
+/// bg = "#d0d0d0"
+/// modifiers = ["bold"]
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    /// Preset used for the missing entries
+    preset: Option<String>,
+    /// Draw borders around lists
+    borders: Option<bool>,
+    border: Option<StyleFile>,
+    style: Option<StyleFile>,
+    item: Option<StyleFile>,
+    dir: Option<StyleFile>,
+    highlight_spacing: Option<SpacingFile>,
+    highlight_item: Option<StyleFile>,
+    highlight_dir: Option<StyleFile>,
+    highlight_symbol: Option<String>,
+    playing: Option<StyleFile>,
+    search_match: Option<StyleFile>,
+    tab: Option<StyleFile>,
+    tab_highlight: Option<StyleFile>,
+    status: Option<StyleFile>,
+    gauge_filled: Option<StyleFile>,
+    gauge_unfilled: Option<StyleFile>,
+    log_error: Option<StyleFile>,
+    log_warn: Option<StyleFile>,
+    log_info: Option<StyleFile>,
+    log_debug: Option<StyleFile>,
+    log_trace: Option<StyleFile>,
+}
+
+macro_rules! apply_styles {
+    ($file:expr, $theme:expr, $($entry:ident => $field:ident),* $(,)?) => {
+        $(
+            if let Some(style) = &$file.$entry {
+                $theme.$field = style.to_style().with_context(|| stringify!($entry))?;
+            }
+        )*
+    };
+}
+
+impl ThemeFile {
+    fn apply(&self, theme: &mut Theme) -> Result<()> {
+        apply_styles!(self, theme,
+            style => style,
+            item => item_style,
+            dir => dir_style,
+            highlight_item => highlight_item_style,
+            highlight_dir => highlight_dir_style,
+            playing => playing_style,
+            search_match => search_match_style,
+            tab => tab_style,
+            tab_highlight => tab_highlight_style,
+            status => status_style,
+            gauge_filled => gauge_filled_style,
+            gauge_unfilled => gauge_unfilled_style,
+            log_error => log_error_style,
+            log_warn => log_warn_style,
+            log_info => log_info_style,
+            log_debug => log_debug_style,
+            log_trace => log_trace_style,
+        );
+
+        let borders = self.borders.unwrap_or(theme.block.is_some());
+        theme.block = if borders {
+            let mut block = theme
+                .block
+                .clone()
+                .unwrap_or_else(|| Block::default().borders(Borders::ALL));
+            if let Some(border) = &self.border {
+                block = block.border_style(border.to_style().context("border")?);
+            }
+            Some(block)
+        } else {
+            None
+        };
+        if let Some(spacing) = &self.highlight_spacing {
+            theme.highlight_spacing = match spacing {
+                SpacingFile::Always => HighlightSpacing::Always,
+                SpacingFile::WhenSelected => HighlightSpacing::WhenSelected,
+                SpacingFile::Never => HighlightSpacing::Never,
+            };
+        }
+        if let Some(symbol) = &self.highlight_symbol {
+            theme.highlight_symbol = Some(symbol.clone());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SpacingFile {
+    Always,
+    WhenSelected,
+    Never,
+}
+
+/// A style in a theme file.
+/// Colors are names like `lightblue`, indexes like `42` or hex like `#ff8800`,
+/// modifiers are names like `bold` or `crossed_out`.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct StyleFile {
+    fg: Option<String>,
+    bg: Option<String>,
+    modifiers: Vec<String>,
+}
+
+impl StyleFile {
+    fn to_style(&self) -> Result<Style> {
+        let mut style = Style::new();
+        if let Some(fg) = &self.fg {
+            style = style.fg(parse_color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(parse_color(bg)?);
+        }
+        for modifier in &self.modifiers {
+            let modifier = Modifier::from_name(&modifier.to_uppercase())
+                .ok_or_else(|| anyhow!("Unknown modifier `{modifier}`"))?;
+            style = style.add_modifier(modifier);
+        }
+        Ok(style)
+    }
+}
+
+fn parse_color(color: &str) -> Result<Color> {
+    Color::from_str(color).map_err(|_| anyhow!("Unknown color `{color}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_load() {
+        for (name, _) in PRESETS {
+            Theme::preset(name).unwrap().unwrap();
         }
+        assert!(Theme::preset("default").unwrap().is_ok());
+        assert!(Theme::preset("missing").is_none());
     }
 }
@@ -0,0 +1,53 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use rmusic_tui::settings::data_dir;
+use serde::{Deserialize, Serialize};
+
+use super::library_view::LibraryViewerState;
+
+const UI_STATE_FILE: &str = "ui_state.json";
+
+/// The state of the UI that is kept between runs
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct UiState {
+    /// Name of the active tab, names stay the same when tabs are added
+    pub active_tab: Option<String>,
+    pub library_view: Option<LibraryViewerState>,
+    pub explorer: Option<ExplorerState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExplorerState {
+    pub cwd: PathBuf,
+    pub show_hidden: bool,
+}
+
+impl UiState {
+    /// Read the saved state, the default state if there is none
+    pub fn load() -> Result<UiState> {
+        let Some(path) = ui_state_path().filter(|path| path.exists()) else {
+            return Ok(UiState::default());
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read UI state {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Could not parse UI state {}", path.display()))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = ui_state_path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(self)?)
+            .with_context(|| format!("Could not write UI state {}", path.display()))
+    }
+}
+
+fn ui_state_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(UI_STATE_FILE))
+}
@@ -0,0 +1,113 @@
+//! The volume as the user sees it.
+//!
+//! Playback scales the samples by a gain, the `volume_level` of the playback context.
+//! Everything that is shown or set is a level in percent instead, which the curve
+//! from the settings maps to that gain.
+
+use rmusic::playback_loop::PlaybackAction;
+use rmusic_tui::settings::playback::{PlaybackSettings, VolumeCurve};
+
+use crate::transport::Transport;
+
+/// Decibels from the lowest level above zero to full volume, for the logarithmic curve
+const RANGE_DB: f32 = 50.0;
+
+#[derive(Clone, Copy)]
+pub struct Volume {
+    curve: VolumeCurve,
+    /// Percentage points of a volume key
+    step: i16,
+}
+
+impl Volume {
+    pub fn new(settings: &PlaybackSettings) -> Volume {
+        Volume {
+            curve: settings.volume_curve,
+            step: settings.volume_step.into(),
+        }
+    }
+
+    /// The gain for a level from 0 to 1
+    pub fn gain(&self, level: f32) -> f32 {
+        let level = level.clamp(0.0, 1.0);
+        match self.curve {
+            VolumeCurve::Linear => level,
+            VolumeCurve::Logarithmic if level == 0.0 => 0.0,
+            VolumeCurve::Logarithmic => 10_f32.powf(RANGE_DB * (level - 1.0) / 20.0),
+        }
+    }
+
+    /// The level from 0 to 1 for a gain
+    pub fn level(&self, gain: f32) -> f32 {
+        match self.curve {
+            VolumeCurve::Linear => gain.clamp(0.0, 1.0),
+            VolumeCurve::Logarithmic if gain <= 0.0 => 0.0,
+            VolumeCurve::Logarithmic => (1.0 + 20.0 * gain.log10() / RANGE_DB).clamp(0.0, 1.0),
+        }
+    }
+
+    /// The volume in percent, of the gain the transport goes to
+    pub fn percent(&self, transport: &Transport) -> u8 {
+        self.percent_of(transport.gain())
+    }
+
+    fn percent_of(&self, gain: f32) -> u8 {
+        (self.level(gain) * 100.0).round() as u8
+    }
+
+    /// Percentage points of a volume key
+    pub fn step(&self) -> i16 {
+        self.step
+    }
+
+    /// The action that sets the volume to `percent`
+    pub fn set(&self, percent: u8, transport: &Transport) -> PlaybackAction {
+        transport.gain_action(self.gain_of(percent))
+    }
+
+    fn gain_of(&self, percent: u8) -> f32 {
+        self.gain(f32::from(percent.min(100)) / 100.0)
+    }
+
+    /// The action that changes the volume by percentage points
+    pub fn change(&self, points: i16, transport: &Transport) -> PlaybackAction {
+        let percent = i16::from(self.percent(transport)) + points;
+        self.set(percent.clamp(0, 100) as u8, transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(curve: VolumeCurve) -> Volume {
+        Volume { curve, step: 5 }
+    }
+
+    #[test]
+    fn percent_round_trip() {
+        for curve in [VolumeCurve::Linear, VolumeCurve::Logarithmic] {
+            let volume = volume(curve);
+            for percent in 0..=100 {
+                assert_eq!(volume.percent_of(volume.gain_of(percent)), percent);
+            }
+        }
+    }
+
+    #[test]
+    fn logarithmic_curve() {
+        let volume = volume(VolumeCurve::Logarithmic);
+        assert_eq!(volume.gain_of(0), 0.0);
+        assert_eq!(volume.gain_of(100), 1.0);
+        assert!((volume.gain_of(60) - 10_f32.powf(-1.0)).abs() < 1e-6);
+        // Every step down is the same number of decibels
+        assert!(
+            (volume.gain_of(50) / volume.gain_of(60) - volume.gain_of(80) / volume.gain_of(90))
+                .abs()
+                < 1e-4
+        );
+        assert_eq!(volume.gain_of(150), 1.0);
+        assert_eq!(volume.percent_of(-1.0), 0);
+        assert_eq!(volume.percent_of(2.0), 100);
+    }
+}
@@ -0,0 +1,314 @@
+//! Keeps the library in sync with the watched music folders.
+//!
+//! The folders are compared with the library when the watcher starts, after that
+//! file system events are collected until the folders are quiet for a moment,
+//! then new and changed audio files are added and removed ones are pruned.
+//! Paths that fail are logged and tried again after the next quiet moment.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{error, info, warn};
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use rmusic::{database::Library, models::Track};
+
+use crate::{
+    database,
+    scan::{audio_files, is_audio_file},
+};
+
+/// How long the folders have to be quiet before the changes are applied,
+/// so a copied album is added at once instead of file by file
+const QUIET_TIME: Duration = Duration::from_secs(2);
+/// Times a path is tried before it is given up on
+const ATTEMPTS: u32 = 3;
+
+pub struct LibraryWatcher {
+    /// Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    changed: Arc<AtomicBool>,
+}
+
+impl LibraryWatcher {
+    pub fn new(roots: &[PathBuf]) -> Result<LibraryWatcher> {
+        let library = Library::try_new()?;
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        let mut watched = vec![];
+        for root in roots {
+            match watcher.watch(root, RecursiveMode::Recursive) {
+                Ok(()) => watched.push(root.clone()),
+                Err(err) => warn!("Not watching {}: {err}", root.display()),
+            }
+        }
+        let changed = Arc::new(AtomicBool::new(false));
+        {
+            let changed = changed.clone();
+            thread::spawn(move || watch(library, &watched, events, &changed));
+        }
+        Ok(LibraryWatcher {
+            _watcher: watcher,
+            changed,
+        })
+    }
+
+    /// Returns true if the library has changed since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Start watching `roots`, `None` if there is nothing to watch or watching failed
+pub fn start(roots: &[PathBuf]) -> Option<LibraryWatcher> {
+    if roots.is_empty() {
+        return None;
+    }
+    match LibraryWatcher::new(roots) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            error!("Could not watch the music folders: {err:#}");
+            None
+        }
+    }
+}
+
+/// Paths that changed and were not applied yet
+#[derive(Default)]
+struct Changes {
+    updated: BTreeSet<PathBuf>,
+    removed: BTreeSet<PathBuf>,
+    /// Failed attempts of the paths that are tried again
+    failed: HashMap<PathBuf, u32>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    fn update(&mut self, path: &Path) {
+        self.removed.remove(path);
+        self.failed.remove(path);
+        self.updated.insert(path.to_path_buf());
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.updated.remove(path);
+        self.failed.remove(path);
+        self.removed.insert(path.to_path_buf());
+    }
+
+    /// Keep a path that failed to try it again, until it failed too often
+    fn retry(&mut self, path: PathBuf, removed: bool, err: &anyhow::Error) {
+        let attempts = self.failed.remove(&path).unwrap_or(0) + 1;
+        if attempts >= ATTEMPTS {
+            error!(
+                "Giving up on {} after {attempts} attempts: {err:#}",
+                path.display()
+            );
+            return;
+        }
+        warn!("Could not update {}, trying again: {err:#}", path.display());
+        self.failed.insert(path.clone(), attempts);
+        if removed {
+            self.removed.insert(path);
+        } else {
+            self.updated.insert(path);
+        }
+    }
+
+    fn add(&mut self, event: Event) {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let [from, to] = event.paths.as_slice() {
+                    self.remove(from);
+                    self.update(to);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+                event.paths.iter().for_each(|path| self.remove(path));
+            }
+            // Other renames do not say which side they are
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                for path in &event.paths {
+                    if path.exists() {
+                        self.update(path);
+                    } else {
+                        self.remove(path);
+                    }
+                }
+            }
+            // Metadata changes are things like permissions and times, the tags are in the data
+            EventKind::Modify(ModifyKind::Metadata(_)) => (),
+            EventKind::Create(_)
+            | EventKind::Modify(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                event.paths.iter().for_each(|path| self.update(path));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn watch(
+    mut library: Library,
+    roots: &[PathBuf],
+    events: Receiver<notify::Result<Event>>,
+    changed: &AtomicBool,
+) {
+    for root in roots {
+        match sync_root(&mut library, root) {
+            Ok(true) => changed.store(true, Ordering::Relaxed),
+            Ok(false) => (),
+            Err(err) => error!(
+                "Error while syncing {} with the library: {err:#}",
+                root.display()
+            ),
+        }
+    }
+
+    let mut changes = Changes::default();
+    loop {
+        let event = if changes.is_empty() {
+            events.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            events.recv_timeout(QUIET_TIME)
+        };
+        match event {
+            Ok(Ok(event)) => changes.add(event),
+            Ok(Err(err)) => warn!("Error while watching the music folders: {err}"),
+            Err(RecvTimeoutError::Timeout) => {
+                if apply(&mut library, &mut changes) {
+                    changed.store(true, Ordering::Relaxed);
+                }
+            }
+            // The watcher is dropped
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Add the files in `root` that are not in the library, and prune the tracks
+/// in `root` whose file is gone. Returns true if the library changed.
+fn sync_root(library: &mut Library, root: &Path) -> Result<bool> {
+    let tracks = library.find_all::<Track>()?;
+    let known: HashSet<&Path> = tracks.iter().map(|track| Path::new(&track.path)).collect();
+    let mut added = 0;
+    for file in audio_files(root)? {
+        if known.contains(file.as_path()) {
+            continue;
+        }
+        match library.add_file(&file) {
+            Ok(_) => added += 1,
+            Err(err) => warn!("Could not add {} to the library: {err}", file.display()),
+        }
+    }
+    let mut removed = 0;
+    for track in &tracks {
+        let track_path = Path::new(&track.path);
+        if track_path.starts_with(root) && !track_path.exists() {
+            match database::remove_file(library, track_path) {
+                Ok(_) => removed += 1,
+                Err(err) => warn!("Could not remove {}: {err:#}", track_path.display()),
+            }
+        }
+    }
+    if added > 0 || removed > 0 {
+        info!(
+            "Synced {}: added {added} and removed {removed} tracks",
+            root.display()
+        );
+    }
+    Ok(added > 0 || removed > 0)
+}
+
+/// Apply the changes path by path, the paths that fail stay in `changes` to be
+/// tried again. Returns true if the library changed.
+fn apply(library: &mut Library, changes: &mut Changes) -> bool {
+    let mut removed = 0;
+    let removed_paths = std::mem::take(&mut changes.removed);
+    if !removed_paths.is_empty() {
+        // Read once for the whole batch, a deleted folder has an event per file
+        match library.find_all::<Track>() {
+            Ok(tracks) => {
+                for path in removed_paths {
+                    match remove_path(library, &tracks, &path) {
+                        Ok(count) => {
+                            changes.failed.remove(&path);
+                            removed += count;
+                        }
+                        Err(err) => changes.retry(path, true, &err),
+                    }
+                }
+            }
+            Err(err) => {
+                let err = anyhow::Error::from(err);
+                for path in removed_paths {
+                    changes.retry(path, true, &err);
+                }
+            }
+        }
+    }
+
+    let mut added = 0;
+    for path in std::mem::take(&mut changes.updated) {
+        match update_path(library, &path) {
+            Ok(count) => {
+                changes.failed.remove(&path);
+                added += count;
+            }
+            Err(err) => changes.retry(path, false, &err),
+        }
+    }
+
+    if added > 0 || removed > 0 {
+        info!("Music folders changed: added or updated {added} and removed {removed} tracks");
+    }
+    added > 0 || removed > 0
+}
+
+/// Remove the tracks of a removed file or folder whose file no longer exists,
+/// returns how many were removed. `tracks` may have tracks that an earlier
+/// path of the batch removed already.
+fn remove_path(library: &mut Library, tracks: &[Track], path: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for track in tracks {
+        let track_path = Path::new(&track.path);
+        if track_path.starts_with(path)
+            && !track_path.exists()
+            && database::remove_file(library, track_path)?
+        {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Add or read again the audio files of a path, returns how many there were
+fn update_path(library: &mut Library, path: &Path) -> Result<usize> {
+    let files = if path.is_dir() {
+        // A folder that was moved or copied in, its files may not have events
+        audio_files(path)?
+    } else if path.is_file() && is_audio_file(path) {
+        vec![path.to_path_buf()]
+    } else {
+        return Ok(0);
+    };
+    for file in &files {
+        database::reread_file(library, file)?;
+    }
+    Ok(files.len())
+}